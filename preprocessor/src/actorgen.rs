@@ -5,6 +5,7 @@
 //! - B is the top-center
 //! - C is the bottom-center
 //! - D is the right-center
+//!
 //! By using `find_closest` with `min_distance_to_players` set to `1`, we
 //! obtain a set R of two more squares:
 //! - above and to the right of D
@@ -14,6 +15,91 @@
 //! of the TC. `box0` is the 4 centermost tiles, `box1` is those tiles
 //! and their adjacent neighbors, and so on.
 
+use crate::utils::RandomBlock;
+
+/// Builds the lines of a single `create_object` command.
+///
+/// Attributes are emitted in the order the methods are called, one attribute
+/// per line, and `build` closes the command with its curly brace.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ObjectBuilder {
+    /// The lines of the command, excluding the closing curly brace.
+    lines: Vec<String>,
+}
+
+impl ObjectBuilder {
+    /// Returns a new builder for a `create_object` command placing the object
+    /// given by `constant`.
+    pub fn object(constant: &str) -> Self {
+        ObjectBuilder {
+            lines: vec![format!("create_object {constant} {{")],
+        }
+    }
+
+    /// Adds the attribute line `line` to the command.
+    fn attribute(mut self, line: String) -> Self {
+        self.lines.push(line);
+        self
+    }
+
+    /// Sets `number_of_objects` to `n`.
+    pub fn number(self, n: u32) -> Self {
+        self.attribute(format!("number_of_objects {n}"))
+    }
+
+    /// Adds `set_place_for_every_player`.
+    pub fn place_for_every_player(self) -> Self {
+        self.attribute(String::from("set_place_for_every_player"))
+    }
+
+    /// Adds `set_gaia_object_only`.
+    pub fn gaia_only(self) -> Self {
+        self.attribute(String::from("set_gaia_object_only"))
+    }
+
+    /// Adds `find_closest`.
+    pub fn find_closest(self) -> Self {
+        self.attribute(String::from("find_closest"))
+    }
+
+    /// Sets `actor_area` to `name`.
+    pub fn actor_area(self, name: &str) -> Self {
+        self.attribute(format!("actor_area {name}"))
+    }
+
+    /// Sets `actor_area_radius` to `r`.
+    pub fn actor_area_radius(self, r: u32) -> Self {
+        self.attribute(format!("actor_area_radius {r}"))
+    }
+
+    /// Adds `avoid_actor_area` for the actor area `name`.
+    pub fn avoid_actor_area(self, name: &str) -> Self {
+        self.attribute(format!("avoid_actor_area {name}"))
+    }
+
+    /// Sets `actor_area_to_place_in` to `name`.
+    pub fn actor_area_to_place_in(self, name: &str) -> Self {
+        self.attribute(format!("actor_area_to_place_in {name}"))
+    }
+
+    /// Sets `min_distance_to_players` to `n`.
+    pub fn min_distance_to_players(self, n: u32) -> Self {
+        self.attribute(format!("min_distance_to_players {n}"))
+    }
+
+    /// Sets `max_distance_to_players` to `n`.
+    pub fn max_distance_to_players(self, n: u32) -> Self {
+        self.attribute(format!("max_distance_to_players {n}"))
+    }
+
+    /// Returns the lines of the command, including the closing curly brace.
+    pub fn build(self) -> Vec<String> {
+        let mut lines = self.lines;
+        lines.push(String::from("}"));
+        lines
+    }
+}
+
 /// Returns the lines used to define the placeholder constants.
 ///
 /// Defines the following constants:
@@ -34,74 +120,150 @@ pub fn make_constants() -> Vec<String> {
     ];
     // 590 is VILLAGER_SHEPHERD_F; 592 is VILLAGER_SHEPHPERD_M
     for i in 0..6 {
-        lines.push(String::from("start_random"));
-        lines.push(format!("percent_chance 50 #const SHEP{i} 590"));
-        lines.push(format!("percent_chance 50 #const SHEP{i} 592"));
-        lines.push(String::from("end_random"));
+        let block = RandomBlock::new()
+            .chance(50, format!("#const SHEP{i} 590"))
+            .chance(50, format!("#const SHEP{i} 592"));
+        lines.extend(block.build());
     }
     // 123 is VILLAGER_WOOD_M; 218 is VILLAGER_WOOD_F
     for i in 0..3 {
-        lines.push(String::from("start_random"));
-        lines.push(format!("percent_chance 50 #const LUMBERJACK{i} 123"));
-        lines.push(format!("percent_chance 50 #const LUMBERJACK{i} 218"));
-        lines.push(String::from("end_random"));
+        let block = RandomBlock::new()
+            .chance(50, format!("#const LUMBERJACK{i} 123"))
+            .chance(50, format!("#const LUMBERJACK{i} 218"));
+        lines.extend(block.build());
     }
     lines
 }
 
+/// An operation of a `MOD_RESOURCE` effect.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ResourceOp {
+    /// Sets the resource to the value, `ATTR_SET`.
+    Set,
+    /// Adds the value to the resource, `ATTR_ADD`.
+    Add,
+}
+
+impl ResourceOp {
+    /// Returns the RMS keyword of the operation.
+    pub fn keyword(self) -> &'static str {
+        match self {
+            ResourceOp::Set => "ATTR_SET",
+            ResourceOp::Add => "ATTR_ADD",
+        }
+    }
+}
+
+/// Returns whether `word` is a nonempty name of letters, digits, and
+/// underscores, such as an object constant.
+pub fn is_effect_word(word: &str) -> bool {
+    !word.is_empty() && word.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Returns whether `attr` is the name of an object attribute, such as
+/// `ATTR_HITPOINTS`.
+pub fn is_attribute(attr: &str) -> bool {
+    attr.starts_with("ATTR_") && is_effect_word(attr)
+}
+
+/// Returns the effect line `command effect target attr value`, where
+/// `command` is `effect_amount` or `effect_percent`.
+/// Panics if `target` is not a single word or `attr` is not an attribute.
+fn effect(command: &str, effect: &str, target: &str, attr: &str, value: i32) -> String {
+    assert!(is_effect_word(target), "Invalid effect target `{target}`.");
+    assert!(is_attribute(attr), "Invalid effect attribute `{attr}`.");
+    format!("{command} {effect} {target} {attr} {value}")
+}
+
+/// Returns the line setting the attribute `attr` of the objects `target` of
+/// every player to `value`.
+/// Panics if `target` is not a single word or `attr` is not an attribute.
+pub fn set_attribute(target: &str, attr: &str, value: i32) -> String {
+    effect("effect_amount", "SET_ATTRIBUTE", target, attr, value)
+}
+
+/// Returns the line setting the attribute `attr` of the Gaia objects `target`
+/// to `value`.
+/// Panics if `target` is not a single word or `attr` is not an attribute.
+pub fn gaia_set_attribute(target: &str, attr: &str, value: i32) -> String {
+    effect("effect_amount", "GAIA_SET_ATTRIBUTE", target, attr, value)
+}
+
+/// Returns the line applying `op` with `value` to the starting `resource` of
+/// every player. Panics if `resource` is not a single word.
+pub fn mod_resource(resource: &str, op: ResourceOp, value: i32) -> String {
+    effect(
+        "effect_amount",
+        "MOD_RESOURCE",
+        resource,
+        op.keyword(),
+        value,
+    )
+}
+
+/// Returns the line applying `op` with `percent` percent to the `resource` of
+/// every player, such as a tribute inefficiency.
+/// Panics if `resource` is not a single word.
+pub fn mod_resource_percent(resource: &str, op: ResourceOp, percent: i32) -> String {
+    effect(
+        "effect_percent",
+        "MOD_RESOURCE",
+        resource,
+        op.keyword(),
+        percent,
+    )
+}
+
+/// Returns the lines clearing the attributes of the placeholders `PHOFF` and
+/// `PHON` and setting up the `TEMPORARY_REVEALER`s.
+fn placeholder_attributes() -> Vec<String> {
+    let mut lines = vec![];
+    for placeholder in ["PHOFF", "PHON"] {
+        lines.push(set_attribute(placeholder, "ATTR_DEAD_ID", -1));
+        lines.push(set_attribute(placeholder, "ATTR_TERRAIN_ID", 0));
+        lines.push(set_attribute(placeholder, "ATTR_LINE_OF_SIGHT", 0));
+        lines.push(set_attribute(placeholder, "ATTR_HITPOINTS", 0));
+        lines.push(set_attribute(placeholder, "ATTR_STORAGE_VALUE", 0));
+    }
+    lines
+}
+
+/// Returns the lines setting up the `TEMPORARY_REVEALER`s and the starting
+/// resources, following the herdable attributes.
+fn revealer_and_resource_attributes() -> Vec<String> {
+    vec![
+        set_attribute("TEMPORARY_REVEALER", "ATTR_DEAD_ID", -1),
+        set_attribute("TEMPORARY_REVEALER", "ATTR_HITPOINTS", 0),
+        set_attribute("TEMPORARY_REVEALER", "ATTR_LINE_OF_SIGHT", 18),
+        mod_resource("AMOUNT_STARTING_WOOD", ResourceOp::Add, -30),
+        mod_resource("AMOUNT_STARTING_FOOD", ResourceOp::Add, -100),
+        mod_resource_percent("TRIBUTE_INEFFICIENCY", ResourceOp::Set, 50),
+    ]
+}
+
 /// Returns the lines for clearing the placeholder attributes in `<PLAYER_SETUP>`.
 /// Also sets the Gaia HP for `HERDABLE_A` to `0`.
 pub fn set_placeholder_attributes() -> Vec<String> {
-    vec![
-        String::from("effect_amount SET_ATTRIBUTE PHOFF ATTR_DEAD_ID -1"),
-        String::from("effect_amount SET_ATTRIBUTE PHOFF ATTR_TERRAIN_ID 0"),
-        String::from("effect_amount SET_ATTRIBUTE PHOFF ATTR_LINE_OF_SIGHT 0"),
-        String::from("effect_amount SET_ATTRIBUTE PHOFF ATTR_HITPOINTS 0"),
-        String::from("effect_amount SET_ATTRIBUTE PHOFF ATTR_STORAGE_VALUE 0"),
-        String::from("effect_amount SET_ATTRIBUTE PHON ATTR_DEAD_ID -1"),
-        String::from("effect_amount SET_ATTRIBUTE PHON ATTR_TERRAIN_ID 0"),
-        String::from("effect_amount SET_ATTRIBUTE PHON ATTR_LINE_OF_SIGHT 0"),
-        String::from("effect_amount SET_ATTRIBUTE PHON ATTR_HITPOINTS 0"),
-        String::from("effect_amount SET_ATTRIBUTE PHON ATTR_STORAGE_VALUE 0"),
-        String::from("effect_amount GAIA_SET_ATTRIBUTE HERDABLE_A ATTR_HITPOINTS 0"),
-        String::from("effect_amount SET_ATTRIBUTE TEMPORARY_REVEALER ATTR_DEAD_ID -1"),
-        String::from("effect_amount SET_ATTRIBUTE TEMPORARY_REVEALER ATTR_HITPOINTS 0"),
-        String::from("effect_amount SET_ATTRIBUTE TEMPORARY_REVEALER ATTR_LINE_OF_SIGHT 18"),
-        String::from("effect_amount MOD_RESOURCE AMOUNT_STARTING_WOOD ATTR_ADD -30"),
-        String::from("effect_amount MOD_RESOURCE AMOUNT_STARTING_FOOD ATTR_ADD -100"),
-        String::from("effect_percent MOD_RESOURCE TRIBUTE_INEFFICIENCY ATTR_SET 50"),
-    ]
+    let mut lines = placeholder_attributes();
+    lines.push(gaia_set_attribute("HERDABLE_A", "ATTR_HITPOINTS", 0));
+    lines.extend(revealer_and_resource_attributes());
+    lines
 }
 
 /// Sets placeholder attributes for Four Seasons.
 /// Sets the Gaia HP for all biome `BIOME_HERDABLE_A` to `0`.
 pub fn set_placeholder_attributes_four_seasons() -> Vec<String> {
-    vec![
-        String::from("effect_amount SET_ATTRIBUTE PHOFF ATTR_DEAD_ID -1"),
-        String::from("effect_amount SET_ATTRIBUTE PHOFF ATTR_TERRAIN_ID 0"),
-        String::from("effect_amount SET_ATTRIBUTE PHOFF ATTR_LINE_OF_SIGHT 0"),
-        String::from("effect_amount SET_ATTRIBUTE PHOFF ATTR_HITPOINTS 0"),
-        String::from("effect_amount SET_ATTRIBUTE PHOFF ATTR_STORAGE_VALUE 0"),
-        String::from("effect_amount SET_ATTRIBUTE PHON ATTR_DEAD_ID -1"),
-        String::from("effect_amount SET_ATTRIBUTE PHON ATTR_TERRAIN_ID 0"),
-        String::from("effect_amount SET_ATTRIBUTE PHON ATTR_LINE_OF_SIGHT 0"),
-        String::from("effect_amount SET_ATTRIBUTE PHON ATTR_HITPOINTS 0"),
-        String::from("effect_amount SET_ATTRIBUTE PHON ATTR_STORAGE_VALUE 0"),
-        String::from("effect_amount GAIA_SET_ATTRIBUTE GRASS_HERDABLE_A ATTR_HITPOINTS 0"),
-        String::from("effect_amount GAIA_SET_ATTRIBUTE SNOW_HERDABLE_A ATTR_HITPOINTS 0"),
-        String::from("effect_amount GAIA_SET_ATTRIBUTE DIRT_HERDABLE_A ATTR_HITPOINTS 0"),
-        String::from("effect_amount GAIA_SET_ATTRIBUTE JUNGLE_HERDABLE_A ATTR_HITPOINTS 0"),
-        String::from("effect_amount SET_ATTRIBUTE TEMPORARY_REVEALER ATTR_DEAD_ID -1"),
-        String::from("effect_amount SET_ATTRIBUTE TEMPORARY_REVEALER ATTR_HITPOINTS 0"),
-        String::from("effect_amount SET_ATTRIBUTE TEMPORARY_REVEALER ATTR_LINE_OF_SIGHT 18"),
-        String::from("effect_amount MOD_RESOURCE AMOUNT_STARTING_WOOD ATTR_ADD -30"),
-        String::from("effect_amount MOD_RESOURCE AMOUNT_STARTING_FOOD ATTR_ADD -100"),
-        String::from("effect_percent MOD_RESOURCE TRIBUTE_INEFFICIENCY ATTR_SET 50"),
-    ]
+    let mut lines = placeholder_attributes();
+    for biome in ["GRASS", "SNOW", "DIRT", "JUNGLE"] {
+        let herdable = format!("{biome}_HERDABLE_A");
+        lines.push(gaia_set_attribute(&herdable, "ATTR_HITPOINTS", 0));
+    }
+    lines.extend(revealer_and_resource_attributes());
+    lines
 }
 
-/// Same as `set_zewall_placeholder_attributes` but uses `SET_ATTRIBUTE`
-/// instead of `GAIA_SET_ATTRIBUTE` for the initial dying herdable.
+// Same as `set_zewall_placeholder_attributes` but uses `SET_ATTRIBUTE`
+// instead of `GAIA_SET_ATTRIBUTE` for the initial dying herdable.
 // Note the Goose still lives, so this function isn't useful.
 // pub fn set_zewall_placeholder_attributes() -> Vec<String> {
 //     vec![
@@ -133,90 +295,100 @@ pub fn set_placeholder_attributes_four_seasons() -> Vec<String> {
 ///
 /// May be used for multiple TCs.
 pub fn tc_center() -> Vec<String> {
-    String::from(
-        "create_object PHON {
-set_place_for_every_player
-set_gaia_object_only
-max_distance_to_players 0
-actor_area tc_d
-actor_area_radius 0
-}
-create_object PHON {
-number_of_objects 2
-set_place_for_every_player
-set_gaia_object_only
-find_closest
-min_distance_to_players 1
-max_distance_to_players 1
-actor_area tc_r0
-actor_area_radius 0
-}
-create_object PHON {
-set_place_for_every_player
-set_gaia_object_only
-find_closest
-min_distance_to_players 1
-max_distance_to_players 1
-avoid_actor_area tc_r0
-actor_area tc_c
-actor_area_radius 0
-}
-create_object PHON {
-set_place_for_every_player
-set_gaia_object_only
-find_closest
-min_distance_to_players 1
-max_distance_to_players 1
-avoid_actor_area tc_r0
-avoid_actor_area tc_c
-actor_area tc_b
-actor_area_radius 0
-}
-create_object PHON {
-number_of_objects 2
-set_place_for_every_player
-set_gaia_object_only
-find_closest
-min_distance_to_players 1
-max_distance_to_players 1
-actor_area tc_r1
-actor_area_radius 1
-}
-create_object PHON {
-set_place_for_every_player
-set_gaia_object_only
-max_distance_to_players 1
-avoid_actor_area tc_r1
-actor_area tc_a
-actor_area_radius 0
-}",
-    )
-    .split("\n")
-    .map(String::from)
-    .collect::<Vec<String>>()
+    let mut lines = ObjectBuilder::object("PHON")
+        .place_for_every_player()
+        .gaia_only()
+        .max_distance_to_players(0)
+        .actor_area("tc_d")
+        .actor_area_radius(0)
+        .build();
+    lines.append(
+        &mut ObjectBuilder::object("PHON")
+            .number(2)
+            .place_for_every_player()
+            .gaia_only()
+            .find_closest()
+            .min_distance_to_players(1)
+            .max_distance_to_players(1)
+            .actor_area("tc_r0")
+            .actor_area_radius(0)
+            .build(),
+    );
+    lines.append(
+        &mut ObjectBuilder::object("PHON")
+            .place_for_every_player()
+            .gaia_only()
+            .find_closest()
+            .min_distance_to_players(1)
+            .max_distance_to_players(1)
+            .avoid_actor_area("tc_r0")
+            .actor_area("tc_c")
+            .actor_area_radius(0)
+            .build(),
+    );
+    lines.append(
+        &mut ObjectBuilder::object("PHON")
+            .place_for_every_player()
+            .gaia_only()
+            .find_closest()
+            .min_distance_to_players(1)
+            .max_distance_to_players(1)
+            .avoid_actor_area("tc_r0")
+            .avoid_actor_area("tc_c")
+            .actor_area("tc_b")
+            .actor_area_radius(0)
+            .build(),
+    );
+    lines.append(
+        &mut ObjectBuilder::object("PHON")
+            .number(2)
+            .place_for_every_player()
+            .gaia_only()
+            .find_closest()
+            .min_distance_to_players(1)
+            .max_distance_to_players(1)
+            .actor_area("tc_r1")
+            .actor_area_radius(1)
+            .build(),
+    );
+    lines.append(
+        &mut ObjectBuilder::object("PHON")
+            .place_for_every_player()
+            .gaia_only()
+            .max_distance_to_players(1)
+            .avoid_actor_area("tc_r1")
+            .actor_area("tc_a")
+            .actor_area_radius(0)
+            .build(),
+    );
+    lines
 }
 
 /// Returns a vector of lines for placing actor area boxes around player TCs.
 pub fn tc_boxes() -> Vec<String> {
     let mut lines = vec![];
     for tile in ["a", "b", "c", "d"] {
-        lines.push(String::from("create_object PHON {"));
-        lines.push(String::from("set_place_for_every_player"));
-        lines.push(String::from("set_gaia_object_only"));
-        lines.push(format!("actor_area_to_place_in tc_{tile}"));
-        lines.push(String::from("actor_area box0"));
-        lines.push(String::from("actor_area_radius 0"));
-        lines.push(String::from("}"));
+        lines.append(
+            &mut ObjectBuilder::object("PHON")
+                .place_for_every_player()
+                .gaia_only()
+                .actor_area_to_place_in(&format!("tc_{tile}"))
+                .actor_area("box0")
+                .actor_area_radius(0)
+                .build(),
+        );
     }
     for i in 1..64 {
-        lines.push(String::from("create_object PHON {"));
-        lines.push(String::from("number_of_objects 4"));
-        lines.push(String::from("set_place_for_every_player"));
-        lines.push(String::from("set_gaia_object_only"));
-        lines.push(String::from("actor_area_to_place_in box0"));
-        lines.push(format!("actor_area box{i}"));
-        lines.push(format!("actor_area_radius {i}"));
-        lines.push(String::from("}"));
+        lines.append(
+            &mut ObjectBuilder::object("PHON")
+                .number(4)
+                .place_for_every_player()
+                .gaia_only()
+                .actor_area_to_place_in("box0")
+                .actor_area(&format!("box{i}"))
+                .actor_area_radius(i)
+                .build(),
+        );
     }
     lines
 }
@@ -1267,14 +1439,43 @@ pub fn multi_stragglers() -> Vec<String> {
 /// Returns a vector of strings for placing `TEMPORARY_REVEALER`s
 /// inside of the `box0` near the TC.
 pub fn vision() -> Vec<String> {
-    vec![
-        String::from("create_object TEMPORARY_REVEALER {"),
-        String::from("number_of_objects 4"),
-        String::from("actor_area_to_place_in box0"),
-        String::from("set_place_for_every_player"),
-        String::from("max_distance_to_players 2"),
-        String::from("}"),
-    ]
+    vision_with(4, 2)
+}
+
+/// Returns a vector of strings for placing `count` `TEMPORARY_REVEALER`s
+/// inside of the `box0` near the TC, at most `max_distance` tiles from each
+/// player.
+pub fn vision_with(count: u32, max_distance: u32) -> Vec<String> {
+    ObjectBuilder::object("TEMPORARY_REVEALER")
+        .number(count)
+        .actor_area_to_place_in("box0")
+        .place_for_every_player()
+        .max_distance_to_players(max_distance)
+        .build()
+}
+
+/// Returns the `create_actor_area` line defining the actor area `name`
+/// centered at tile `(x, y)` with the given `radius`.
+pub fn create_actor_area(name: &str, x: u32, y: u32, radius: u32) -> String {
+    format!("create_actor_area {x} {y} {name} {radius}")
+}
+
+/// Returns the lines for placing `count` gaia objects `constant` within
+/// `radius` tiles of the center of the map, such as a relic or a monument.
+/// The objects are placed in an actor area at tile `(50, 50)`, the center of a
+/// map with 100 tiles per side. A `radius` of `0` places a single object at
+/// the exact center.
+pub fn center_object(constant: &str, count: u32, radius: u32) -> Vec<String> {
+    let area = format!("center_object_{radius}");
+    let mut lines = vec![create_actor_area(&area, 50, 50, radius)];
+    lines.extend(
+        ObjectBuilder::object(constant)
+            .gaia_only()
+            .number(count)
+            .actor_area_to_place_in(&area)
+            .build(),
+    );
+    lines
 }
 
 /// Returns a vector of all strings needed for objects generation
@@ -1305,26 +1506,71 @@ pub fn objects_9_vils() -> Vec<String> {
     lines
 }
 
+/// The order of the copies made for each land when objects placed for every
+/// player are instead placed on specific lands. Objects placed earlier win
+/// contested tiles, so the order affects the placements.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ObjectGrouping {
+    /// Each object is copied for every land before the next object, so the
+    /// lands alternate.
+    #[default]
+    PerObject,
+    /// Every object of land `1` is placed before the objects of land `2`, and
+    /// so on. Objects that are not placed for every player stay between the
+    /// groups.
+    PerLand,
+}
+
+/// The lands of the ZeWall players.
+const ZE_WALL_LANDS: std::ops::RangeInclusive<u32> = 1..=4;
+
+/// Pushes a copy of each of the `objects` onto `lines` for each ZeWall land,
+/// ordered by `grouping`, and clears `objects`.
+/// Each object is the lines of a block without its closing brace.
+fn push_land_objects(
+    lines: &mut Vec<String>,
+    objects: &mut Vec<Vec<String>>,
+    grouping: ObjectGrouping,
+) {
+    let mut push = |object: &Vec<String>, i: u32| {
+        lines.extend(object.iter().cloned());
+        lines.push(format!("place_on_specific_land_id {i}"));
+        lines.push(String::from("}"));
+    };
+    match grouping {
+        ObjectGrouping::PerObject => {
+            for object in objects.iter() {
+                for i in ZE_WALL_LANDS {
+                    push(object, i);
+                }
+            }
+        }
+        ObjectGrouping::PerLand => {
+            for i in ZE_WALL_LANDS {
+                for object in objects.iter() {
+                    push(object, i);
+                }
+            }
+        }
+    }
+    objects.clear();
+}
+
 /// Makes the 9-Villager start for ZeWall by using `place_on_specific_land_id`
-/// for lands `1`, `2`, `3`, and `4`.
-pub fn objects_9_vils_ze_wall() -> Vec<String> {
+/// for lands `1`, `2`, `3`, and `4`, ordering the copies by `grouping`.
+pub fn objects_9_vils_ze_wall(grouping: ObjectGrouping) -> Vec<String> {
     let standard = objects_9_vils();
     let mut object: Vec<String> = vec![];
+    let mut land_objects: Vec<Vec<String>> = vec![];
     let mut has_set_place_for_every_player = false;
     let mut lines = vec![];
     for line in standard {
         if line == "}" {
             if has_set_place_for_every_player {
-                for i in 1..=4 {
-                    for object_line in &object {
-                        lines.push(object_line.clone());
-                    }
-                    lines.push(format!("place_on_specific_land_id {i}"));
-                    lines.push(String::from("}"));
-                }
+                land_objects.push(std::mem::take(&mut object));
                 has_set_place_for_every_player = false;
-                object.clear();
             } else {
+                push_land_objects(&mut lines, &mut land_objects, grouping);
                 lines.append(&mut object);
                 lines.push(String::from("}"));
             }
@@ -1334,6 +1580,135 @@ pub fn objects_9_vils_ze_wall() -> Vec<String> {
             object.push(line);
         }
     }
+    push_land_objects(&mut lines, &mut land_objects, grouping);
     debug_assert!(object.is_empty(), "{object:?}");
     lines
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Tests that a builder without attributes emits only the braces.
+    #[test]
+    fn object_builder_empty() {
+        assert_eq!(
+            vec!["create_object PHON {", "}"],
+            ObjectBuilder::object("PHON").build()
+        );
+    }
+
+    /// Tests that each attribute method emits its line in call order.
+    #[test]
+    fn object_builder_attributes() {
+        let lines = ObjectBuilder::object("PHON")
+            .number(2)
+            .place_for_every_player()
+            .gaia_only()
+            .find_closest()
+            .min_distance_to_players(1)
+            .max_distance_to_players(3)
+            .actor_area_to_place_in("box0")
+            .avoid_actor_area("tc_r0")
+            .actor_area("tc_c")
+            .actor_area_radius(0)
+            .build();
+        assert_eq!(
+            vec![
+                "create_object PHON {",
+                "number_of_objects 2",
+                "set_place_for_every_player",
+                "set_gaia_object_only",
+                "find_closest",
+                "min_distance_to_players 1",
+                "max_distance_to_players 3",
+                "actor_area_to_place_in box0",
+                "avoid_actor_area tc_r0",
+                "actor_area tc_c",
+                "actor_area_radius 0",
+                "}",
+            ],
+            lines
+        );
+    }
+
+    /// Tests that `vision` emits the canonical revealer block.
+    #[test]
+    fn vision_block() {
+        assert_eq!(
+            vec![
+                "create_object TEMPORARY_REVEALER {",
+                "number_of_objects 4",
+                "actor_area_to_place_in box0",
+                "set_place_for_every_player",
+                "max_distance_to_players 2",
+                "}",
+            ],
+            vision()
+        );
+    }
+
+    /// Tests that `vision` uses the default revealer count and distance.
+    #[test]
+    fn vision_with_defaults() {
+        assert_eq!(vision(), vision_with(4, 2));
+        let lines = vision_with(8, 5);
+        assert_eq!("number_of_objects 8", lines[1]);
+        assert_eq!("max_distance_to_players 5", lines[4]);
+    }
+
+    /// Tests formatting effect lines with the attribute helpers.
+    #[test]
+    fn effect_lines() {
+        assert_eq!(
+            "effect_amount SET_ATTRIBUTE PHON ATTR_HITPOINTS 0",
+            set_attribute("PHON", "ATTR_HITPOINTS", 0)
+        );
+        assert_eq!(
+            "effect_amount GAIA_SET_ATTRIBUTE HERDABLE_A ATTR_HITPOINTS 0",
+            gaia_set_attribute("HERDABLE_A", "ATTR_HITPOINTS", 0)
+        );
+        assert_eq!(
+            "effect_amount MOD_RESOURCE AMOUNT_STARTING_WOOD ATTR_ADD -30",
+            mod_resource("AMOUNT_STARTING_WOOD", ResourceOp::Add, -30)
+        );
+        assert_eq!(
+            "effect_percent MOD_RESOURCE TRIBUTE_INEFFICIENCY ATTR_SET 50",
+            mod_resource_percent("TRIBUTE_INEFFICIENCY", ResourceOp::Set, 50)
+        );
+    }
+
+    /// Tests that an attribute without the `ATTR_` prefix is rejected.
+    #[test]
+    #[should_panic]
+    fn set_attribute_bad_attr() {
+        set_attribute("PHON", "HITPOINTS", 0);
+    }
+
+    /// Returns the land ids of the `place_on_specific_land_id` lines of
+    /// `lines`, in order.
+    fn specific_land_ids(lines: &[String]) -> Vec<u32> {
+        lines
+            .iter()
+            .filter_map(|line| line.strip_prefix("place_on_specific_land_id "))
+            .map(|id| id.parse().unwrap())
+            .collect()
+    }
+
+    /// Tests that the ZeWall objects alternate lands per object, or group
+    /// every object of a land together.
+    #[test]
+    fn ze_wall_grouping() {
+        let per_object = objects_9_vils_ze_wall(ObjectGrouping::PerObject);
+        let per_land = objects_9_vils_ze_wall(ObjectGrouping::PerLand);
+        assert_eq!(per_object.len(), per_land.len());
+        let ids = specific_land_ids(&per_object);
+        assert_eq!(vec![1, 2, 3, 4, 1], ids[..5]);
+        let ids = specific_land_ids(&per_land);
+        let count = ids.len() / 4;
+        assert!(count > 1);
+        for (i, chunk) in ids.chunks(count).enumerate() {
+            assert!(chunk.iter().all(|&id| id == i as u32 + 1));
+        }
+    }
+}
@@ -1,6 +1,12 @@
 //! Functions for generating TC locations around a circle.
 
-use std::f64::consts::{PI, TAU};
+use std::f64::consts::TAU;
+
+use crate::{
+    actorgen::{create_actor_area, ObjectBuilder},
+    landgen::convert,
+    utils::{arctan, cos, rad_to_deg, sin},
+};
 
 /// Number of tiles of an unscaled map.
 const NUM_TILES: u32 = 100;
@@ -21,17 +27,6 @@ type Point = (u32, u32);
 /// A list of points on the map.
 type PointList = Vec<Point>;
 
-/// Same as the standard `y.atan2(x)`, but the output is given in the region
-/// `[0..TAU)`.
-fn arctan(y: f64, x: f64) -> f64 {
-    let a = y.atan2(x);
-    if a >= 0.0 {
-        a
-    } else {
-        a + TAU
-    }
-}
-
 /// Convenience function for getting types to work with points
 fn arctan_center(&(x, y): &Point) -> f64 {
     arctan(y as f64 - CENTER_Y, x as f64 - CENTER_X)
@@ -42,15 +37,15 @@ fn sort_points(points: &mut PointList) {
     points.sort_by(|a, b| arctan_center(a).partial_cmp(&arctan_center(b)).unwrap());
 }
 
-/// Sets the probabilities to add up to 100. `left` and `right` are the nonzero
-/// probability endpoints, inclusive. Requires `left <= right`.
-fn renormalize_probabilities(probs: &mut Vec<u32>, left: usize, right: usize) {
+/// Sets the probabilities to add up to `target`. `left` and `right` are the
+/// nonzero probability endpoints, inclusive. Requires `left <= right`.
+fn renormalize_probabilities(probs: &mut [u32], left: usize, right: usize, target: u32) {
     debug_assert!(left <= right, "left {left} must be <= right {right}.");
-    let total = probs.iter().fold(0, |a, b| a + b);
-    if total < 100 {
-        probs[(left + right) / 2] += 100 - total;
-    } else if total > 100 {
-        // Adjusts the side indices to bring the total down to 100.
+    let total: u32 = probs.iter().sum();
+    if total < target {
+        probs[(left + right) / 2] += target - total;
+    } else if total > target {
+        // Adjusts the side indices to bring the total down to the target.
         let mut i = 0;
         while *probs.get(i).unwrap() == 0 {
             i += 1;
@@ -60,8 +55,8 @@ fn renormalize_probabilities(probs: &mut Vec<u32>, left: usize, right: usize) {
             j -= 1;
         }
         let mut total = total;
-        while total != 100 {
-            debug_assert!(total > 100);
+        while total != target {
+            debug_assert!(total > target);
             let x = *probs.get(i).unwrap();
             let y = *probs.get(j).unwrap();
             if x >= y {
@@ -80,15 +75,32 @@ fn renormalize_probabilities(probs: &mut Vec<u32>, left: usize, right: usize) {
     }
 }
 
+/// The smallest standard deviation of the distribution of `probabilities`.
+const MIN_SIGMA: f64 = 1.0;
+
 /// Returns a vector of probabilities for the range `left..=right`.
 /// Requires `left <= right`. Assigns roughly a Gaussian distribution to this
 /// range massaged slightly to be a vector of integer percents that sums to
 /// `100`.
 pub fn probabilities(left: usize, right: usize) -> Vec<u32> {
+    probabilities_normalized(left, right, 100)
+}
+
+/// Same as `probabilities`, but the percents sum to `total` instead of `100`.
+/// A `total` below `100` leaves a deliberate `100 - total` percent chance that
+/// no label of the random block is defined.
+/// Requires `left <= right` and `0 < total <= 100`.
+pub fn probabilities_normalized(left: usize, right: usize, total: u32) -> Vec<u32> {
     debug_assert!(left <= right, "left {left} must be <= right {right}.");
+    debug_assert!(
+        total > 0 && total <= 100,
+        "total {total} must be in 1..=100."
+    );
+    let target = total;
     let mut probs = Vec::with_capacity(100);
     let mu = (left + right) as f64 / 2.0;
-    let sigma = left as f64 / 2.0;
+    // A `left` of `0` would give a zero sigma, dividing by zero.
+    let sigma = (left as f64 / 2.0).max(MIN_SIGMA);
 
     for i in 0..=99 {
         if i < left || i > right {
@@ -102,9 +114,9 @@ pub fn probabilities(left: usize, right: usize) -> Vec<u32> {
     let total = probs.iter().fold(0.0, |p, q| p + q);
     let mut probs: Vec<u32> = probs
         .iter()
-        .map(|p| (p / total * 100.0).round() as u32)
+        .map(|p| (p / total * target as f64).round() as u32)
         .collect();
-    renormalize_probabilities(&mut probs, left, right);
+    renormalize_probabilities(&mut probs, left, right, target);
     probs
 }
 
@@ -125,41 +137,63 @@ fn get_nearby_points(radius: f64) -> PointList {
     points
 }
 
-/// For Fortress to keep the bases near the edges of the map in a "square" while
-/// still avoiding the extremes of the corners.
-fn get_square_points() -> PointList {
+/// Returns the points on the square whose sides are `band` tiles from the
+/// edges of the map, restricted to `low..=high` along each side.
+/// Requires `band <= 100` and `low <= high <= 100`.
+fn get_square_points_inset(low: u32, high: u32, band: u32) -> PointList {
+    debug_assert!(band <= NUM_TILES && low <= high && high <= NUM_TILES);
+    let far = NUM_TILES - band;
     let mut points = vec![];
-    for i in 25..=75 {
-        points.push((i, 20));
-        points.push((20, i));
-        points.push((i, 80));
-        points.push((80, i));
+    for i in low..=high {
+        points.push((i, band));
+        points.push((band, i));
+        points.push((i, far));
+        points.push((far, i));
     }
     sort_points(&mut points);
     points
 }
 
+/// For Fortress to keep the bases near the edges of the map in a "square" while
+/// still avoiding the extremes of the corners.
+fn get_square_points() -> PointList {
+    get_square_points_inset(25, 75, 20)
+}
+
 /// Returns the points for potential player lands on Migration.
 fn get_square_points_migration() -> PointList {
-    let mut points = vec![];
-    for i in 10..=90 {
-        points.push((i, 10));
-        points.push((10, i));
-        points.push((i, 90));
-        points.push((90, i));
-    }
-    sort_points(&mut points);
-    points
+    get_square_points_inset(10, 90, 10)
+}
+
+/// Returns the points for a square whose sides are `inset` tiles from the
+/// edges of the map, spanning `inset..=100 - inset` along each side.
+/// Requires `inset <= 50`.
+fn get_square_points_with_inset(inset: u32) -> PointList {
+    debug_assert!(inset <= NUM_TILES / 2, "{inset} must be <= 50.");
+    get_square_points_inset(inset, NUM_TILES - inset, inset)
 }
 
 /// Returns a vector of 100 evenly spaced points from `points`.
 fn select_100_points(points: &PointList) -> PointList {
-    (0..=99)
-        .map(|i| {
-            let j = (i as f64 * points.len() as f64 / 100.0).round() as usize;
-            let &p = points.get(j).unwrap();
-            p
-        })
+    select_points(points, 100)
+}
+
+/// Returns a vector of `n` points from `points`, in order.
+///
+/// If `points` has at least `n` points, the selected points are distinct and
+/// spaced as evenly as possible, including both the first and the last point.
+/// Otherwise every point is selected in order, cycling back to the first point
+/// until `n` points are selected. Requires `points` to be nonempty.
+fn select_points(points: &PointList, n: usize) -> PointList {
+    debug_assert!(!points.is_empty(), "No points to select from.");
+    let len = points.len();
+    if len < n {
+        return points.iter().copied().cycle().take(n).collect();
+    }
+    // The step is at least 1, so rounding does not select a point twice.
+    let step = (len - 1) as f64 / (n - 1).max(1) as f64;
+    (0..n)
+        .map(|i| points[(i as f64 * step).round() as usize])
         .collect()
 }
 
@@ -200,24 +234,78 @@ pub fn list_p1_random_selection() -> Vec<String> {
 /// any two points of at least `angle` degrees and `j` is the minimum offset
 /// between any two points of at most `angle` degrees.
 /// The points in `points[i..=j]` are usable p2 positions for p1 at point `0`.
-fn get_point_offsets(points: &PointList, angle: u32) -> (usize, usize) {
+///
+/// Returns `None` if `points` is empty or if no point satisfies both bounds.
+fn get_point_offsets(points: &PointList, angle: u32) -> Option<(usize, usize)> {
     let angle = angle as f64;
     let end_angle = 360.0 - angle;
     let mut left = None;
     let mut right = None;
-    let first = points.first().unwrap();
-    let theta0 = arctan_center(first);
+    let theta0 = arctan_center(points.first()?);
     for (i, &p) in points.iter().enumerate() {
-        let theta = (arctan_center(&p) - theta0) * 180.0 / PI;
+        let theta = rad_to_deg(arctan_center(&p) - theta0);
         if theta >= angle && left.is_none() {
             left = Some(i);
         }
         if theta > end_angle && right.is_none() {
-            right = Some(i - 1);
+            right = Some(i.checked_sub(1)?);
             break;
         }
     }
-    (left.unwrap(), right.unwrap())
+    match (left, right) {
+        (Some(left), Some(right)) if left <= right => Some((left, right)),
+        _ => None,
+    }
+}
+
+/// Returns the offsets from `get_point_offsets` for `points`, panicking if
+/// there are no usable p2 positions at least `angle` degrees from p1.
+fn expect_point_offsets(points: &PointList, angle: u32) -> (usize, usize) {
+    get_point_offsets(points, angle)
+        .unwrap_or_else(|| panic!("No p2 positions are at least {angle} degrees from p1."))
+}
+
+/// Returns the range of usable p2 offsets `(left, right)`, inclusive, for the
+/// circle of the given `radius` when p2 must be at least `angle` degrees from
+/// p1. Returns `None` if the circle has no such offsets, in which case the
+/// circle macros cannot be generated for this `radius` and `angle`.
+pub fn circle_p2_offsets(radius: f64, angle: u32) -> Option<(usize, usize)> {
+    let points = get_nearby_points(radius);
+    if points.is_empty() {
+        return None;
+    }
+    get_point_offsets(&select_100_points(&points), angle)
+}
+
+/// Returns whether the circle macros can place p2 at least `angle` degrees
+/// from p1 on the circle of the given `radius`.
+pub fn circle_placement_feasible(radius: f64, angle: u32) -> bool {
+    circle_p2_offsets(radius, angle).is_some()
+}
+
+/// Returns whether p2 can be placed at least `angle` degrees from p1 on
+/// `points`.
+fn placement_feasible(points: &PointList, angle: u32) -> bool {
+    !points.is_empty() && get_point_offsets(&select_100_points(points), angle).is_some()
+}
+
+/// Returns whether the square macros can place p2 at least `angle` degrees
+/// from p1.
+pub fn square_placement_feasible(angle: u32) -> bool {
+    placement_feasible(&get_square_points(), angle)
+}
+
+/// Returns whether the Migration square macros can place p2 at least `angle`
+/// degrees from p1.
+pub fn migra_placement_feasible(angle: u32) -> bool {
+    placement_feasible(&get_square_points_migration(), angle)
+}
+
+/// Returns whether the square macros can place p2 at least `angle` degrees
+/// from p1 on the square whose sides are `inset` tiles from the edges of the
+/// map. Requires `inset <= 50`.
+pub fn square_inset_placement_feasible(angle: u32, inset: u32) -> bool {
+    placement_feasible(&get_square_points_with_inset(inset), angle)
 }
 
 /// Returns a list of strings that can be joined to create the start random
@@ -226,11 +314,19 @@ fn get_point_offsets(points: &PointList, angle: u32) -> (usize, usize) {
 /// `angle` is the minimum angle between the two points. Something between 90
 /// and 135 is prob good. The angle is measured in degrees.
 pub fn list_p2_random_selection(radius: f64, angle: u32) -> Vec<String> {
-    assert!(90 <= angle && angle <= 135, "{angle} is not in 90..=135.");
+    list_p2_random_selection_normalized(radius, angle, 100)
+}
+
+/// Same as `list_p2_random_selection`, but the percent chances of the random
+/// block sum to `total`. A `total` below `100` leaves a `100 - total` percent
+/// chance that no `P2_OFFSET` label is defined.
+/// Requires `0 < total <= 100`.
+pub fn list_p2_random_selection_normalized(radius: f64, angle: u32, total: u32) -> Vec<String> {
+    assert!((90..=135).contains(&angle), "{angle} is not in 90..=135.");
     let points = get_nearby_points(radius);
     let points = select_100_points(&points);
-    let (left, right) = get_point_offsets(&points, angle);
-    let probabilities = probabilities(left, right);
+    let (left, right) = expect_point_offsets(&points, angle);
+    let probabilities = probabilities_normalized(left, right, total);
     let mut lines = vec!["start_random".to_string()];
     for (i, &prob) in probabilities.iter().enumerate() {
         if prob > 0 {
@@ -273,7 +369,7 @@ pub fn list_p1_positions(radius: f64) -> Vec<String> {
 pub fn list_p2_positions(radius: f64, angle: u32) -> Vec<String> {
     let points = get_nearby_points(radius);
     let points = select_100_points(&points);
-    let (left, right) = get_point_offsets(&points, angle);
+    let (left, right) = expect_point_offsets(&points, angle);
     let mut delim_outer = "if";
     let mut lines = vec![];
     for i in 0..points.len() {
@@ -293,14 +389,37 @@ pub fn list_p2_positions(radius: f64, angle: u32) -> Vec<String> {
     lines
 }
 
-/// Returns the initial label generation for square positioning.
-/// The `_radius` is unused, but kept to match the call signature of the
-/// analogous circle functions (yes, this should be refactored).
-pub fn list_square_definitions(_radius: f64, angle: u32) -> Vec<String> {
+/// The complete positioning of a circle layout, generated from a single
+/// `radius` and `angle` so that the labels and positions always agree.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CircleLayout {
+    /// The random blocks defining the `P1_POINT` and `P2_OFFSET` labels, placed
+    /// before the lands, as by `#CIRCLE_LABELS`.
+    pub labels: Vec<String>,
+    /// The `land_position` statement for the `create_land` of p1, as by
+    /// `#CIRCLE_POSITION_P1`.
+    pub p1_positions: Vec<String>,
+    /// The `land_position` statement for the `create_land` of p2, as by
+    /// `#CIRCLE_POSITION_P2`.
+    pub p2_positions: Vec<String>,
+}
+
+/// Returns the labels and both position statements for placing p1 and p2 on
+/// the circle of the given `radius`, with p2 at least `angle` degrees from p1.
+pub fn full_layout(radius: f64, angle: u32) -> CircleLayout {
+    CircleLayout {
+        labels: list_random_definitions(radius, angle),
+        p1_positions: list_p1_positions(radius),
+        p2_positions: list_p2_positions(radius, angle),
+    }
+}
+
+/// Returns the initial label generation for positioning on `points`, where
+/// P2 is at least `angle` degrees from P1.
+fn point_definitions(points: &PointList, angle: u32) -> Vec<String> {
     let mut lines = list_p1_random_selection();
-    let points = get_square_points();
-    let points = select_100_points(&points);
-    let (left, right) = get_point_offsets(&points, angle);
+    let points = select_100_points(points);
+    let (left, right) = expect_point_offsets(&points, angle);
     let probabilities = probabilities(left, right);
     lines.push("start_random".to_string());
     for (i, &prob) in probabilities.iter().enumerate() {
@@ -312,12 +431,10 @@ pub fn list_square_definitions(_radius: f64, angle: u32) -> Vec<String> {
     lines
 }
 
-/// Returns the statement to place in a `create_land` command for p1's square.
-/// The `_radius` is unused, but kept to match the call signature of the
-/// analogous circle functions (yes, this should be refactored).
-pub fn square_p1_positions(_radius: f64) -> Vec<String> {
-    let points = get_square_points();
-    let points = select_100_points(&points);
+/// Returns the statement to place in a `create_land` command for p1's
+/// position on `points`.
+fn point_p1_positions(points: &PointList) -> Vec<String> {
+    let points = select_100_points(points);
     let mut lines = vec![];
     let mut delim = "if";
     for (i, &(x, y)) in points.iter().enumerate() {
@@ -329,13 +446,11 @@ pub fn square_p1_positions(_radius: f64) -> Vec<String> {
     lines
 }
 
-/// Returns the statement to place in a `create_land` command for p2's square.
-/// The `_radius` is unused, but kept to match the call signature of the
-/// analogous circle functions (yes, this should be refactored).
-pub fn square_p2_positions(_radius: f64, angle: u32) -> Vec<String> {
-    let points = get_square_points();
-    let points = select_100_points(&points);
-    let (left, right) = get_point_offsets(&points, angle);
+/// Returns the statement to place in a `create_land` command for p2's
+/// position on `points`, where P2 is at least `angle` degrees from P1.
+fn point_p2_positions(points: &PointList, angle: u32) -> Vec<String> {
+    let points = select_100_points(points);
+    let (left, right) = expect_point_offsets(&points, angle);
     let mut delim_outer = "if";
     let mut lines = vec![];
     for i in 0..points.len() {
@@ -355,66 +470,70 @@ pub fn square_p2_positions(_radius: f64, angle: u32) -> Vec<String> {
     lines
 }
 
+/// Returns the initial label generation for square positioning.
+/// The `_radius` is unused, but kept to match the call signature of the
+/// analogous circle functions (yes, this should be refactored).
+pub fn list_square_definitions(_radius: f64, angle: u32) -> Vec<String> {
+    point_definitions(&get_square_points(), angle)
+}
+
+/// Returns the statement to place in a `create_land` command for p1's square.
+/// The `_radius` is unused, but kept to match the call signature of the
+/// analogous circle functions (yes, this should be refactored).
+pub fn square_p1_positions(_radius: f64) -> Vec<String> {
+    point_p1_positions(&get_square_points())
+}
+
+/// Returns the statement to place in a `create_land` command for p2's square.
+/// The `_radius` is unused, but kept to match the call signature of the
+/// analogous circle functions (yes, this should be refactored).
+pub fn square_p2_positions(_radius: f64, angle: u32) -> Vec<String> {
+    point_p2_positions(&get_square_points(), angle)
+}
+
 /// Returns the initial label generation for square positioning.
 /// The `_radius` is unused, but kept to match the call signature of the
 /// analogous circle functions (yes, this should be refactored).
 pub fn list_square_definitions_migra(_radius: f64, angle: u32) -> Vec<String> {
-    let mut lines = list_p1_random_selection();
-    let points = get_square_points_migration();
-    let points = select_100_points(&points);
-    let (left, right) = get_point_offsets(&points, angle);
-    let probabilities = probabilities(left, right);
-    lines.push("start_random".to_string());
-    for (i, &prob) in probabilities.iter().enumerate() {
-        if prob > 0 {
-            lines.push(format!("percent_chance {prob} #define P2_OFFSET_{i}"));
-        }
-    }
-    lines.push("end_random".to_string());
-    lines
+    point_definitions(&get_square_points_migration(), angle)
 }
 
 /// Returns the statement to place in a `create_land` command for p1's square.
 /// The `_radius` is unused, but kept to match the call signature of the
 /// analogous circle functions (yes, this should be refactored).
 pub fn square_p1_positions_migra(_radius: f64) -> Vec<String> {
-    let points = get_square_points_migration();
-    let points = select_100_points(&points);
-    let mut lines = vec![];
-    let mut delim = "if";
-    for (i, &(x, y)) in points.iter().enumerate() {
-        lines.push(format!("{delim} P1_POINT_{i}"));
-        lines.push(format!("land_position {x} {y}"));
-        delim = "elseif";
-    }
-    lines.push("endif".to_string());
-    lines
+    point_p1_positions(&get_square_points_migration())
 }
 
 /// Returns the statement to place in a `create_land` command for p2's square.
 /// The `_radius` is unused, but kept to match the call signature of the
 /// analogous circle functions (yes, this should be refactored).
 pub fn square_p2_positions_migra(_radius: f64, angle: u32) -> Vec<String> {
-    let points = get_square_points_migration();
-    let points = select_100_points(&points);
-    let (left, right) = get_point_offsets(&points, angle);
-    let mut delim_outer = "if";
-    let mut lines = vec![];
-    for i in 0..points.len() {
-        lines.push(format!("{delim_outer} P1_POINT_{i}"));
-        let mut delim_inner = "if";
-        for j in left..=right {
-            lines.push(format!("{delim_inner} P2_OFFSET_{j}"));
-            let slot = (i + j) % 100;
-            let (x, y) = points.get(slot).unwrap();
-            lines.push(format!("land_position {x} {y}"));
-            delim_inner = "elseif";
-        }
-        lines.push("endif".to_string());
-        delim_outer = "elseif";
-    }
-    lines.push("endif".to_string());
-    lines
+    point_p2_positions(&get_square_points_migration(), angle)
+}
+
+/// Returns the initial label generation for square positioning, where the
+/// square's sides are `inset` tiles from the edges of the map.
+/// The `_radius` is unused, but kept to match the call signature of the
+/// analogous circle functions (yes, this should be refactored).
+pub fn list_square_definitions_inset(_radius: f64, angle: u32, inset: u32) -> Vec<String> {
+    point_definitions(&get_square_points_with_inset(inset), angle)
+}
+
+/// Returns the statement to place in a `create_land` command for p1's square,
+/// where the square's sides are `inset` tiles from the edges of the map.
+/// The `_radius` is unused, but kept to match the call signature of the
+/// analogous circle functions (yes, this should be refactored).
+pub fn square_p1_positions_inset(_radius: f64, inset: u32) -> Vec<String> {
+    point_p1_positions(&get_square_points_with_inset(inset))
+}
+
+/// Returns the statement to place in a `create_land` command for p2's square,
+/// where the square's sides are `inset` tiles from the edges of the map.
+/// The `_radius` is unused, but kept to match the call signature of the
+/// analogous circle functions (yes, this should be refactored).
+pub fn square_p2_positions_inset(_radius: f64, angle: u32, inset: u32) -> Vec<String> {
+    point_p2_positions(&get_square_points_with_inset(inset), angle)
 }
 
 /// Pushes 4 `create_land` commands to `lines` surrounding point `(x, y)` for
@@ -467,7 +586,7 @@ pub fn square_avoid_cliffs() -> Vec<String> {
 
     // Player 2 lands.
     let angle = 130; // hard code the value used in Fortress, again this is ugly
-    let (left, right) = get_point_offsets(&points, angle);
+    let (left, right) = expect_point_offsets(&points, angle);
     let mut delim_outer = "if";
     for i in 0..points.len() {
         lines.push(format!("{delim_outer} P1_POINT_{i}"));
@@ -487,6 +606,37 @@ pub fn square_avoid_cliffs() -> Vec<String> {
     lines
 }
 
+/// Returns the tiles of `count` points evenly spaced counterclockwise around
+/// the ring of the given `radius` about the center of the map, starting from
+/// the positive `x` axis. Tiles off of the map are clamped to its boundaries.
+fn ring_points(count: u32, radius: f64) -> PointList {
+    let increment = TAU / count as f64;
+    (0..count)
+        .map(|i| i as f64 * increment)
+        .map(|theta| convert(&(cos(theta), sin(theta)), radius, &(CENTER_X, CENTER_Y)))
+        .collect()
+}
+
+/// Returns the lines for placing `count` gaia objects `constant` at evenly
+/// spaced angles on the ring of the given `radius` about the center of the
+/// map, such as ruins. Each object is placed in a single tile actor area
+/// named by the `count`, `radius`, and the object's index around the ring.
+pub fn ring_object_positions(constant: &str, count: u32, radius: u32) -> Vec<String> {
+    let mut lines = vec![];
+    for (i, (x, y)) in ring_points(count, radius as f64).into_iter().enumerate() {
+        let area = format!("ring_object_{count}_{radius}_{i}");
+        lines.push(create_actor_area(&area, x, y, 0));
+        lines.extend(
+            ObjectBuilder::object(constant)
+                .gaia_only()
+                .number(1)
+                .actor_area_to_place_in(&area)
+                .build(),
+        );
+    }
+    lines
+}
+
 // create_land {
 //     terrain_type PLAYER_PLACEHOLDER_1
 //     base_size 1
@@ -494,3 +644,150 @@ pub fn square_avoid_cliffs() -> Vec<String> {
 //     zone 1
 //     land_position 20 60
 // }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Tests that a point on the positive `x` axis sorts to the start and a
+    /// point just below the axis sorts to the end.
+    #[test]
+    fn sort_points_axis() {
+        let c = CENTER_X as u32;
+        let mut points = vec![(c + 10, c - 1), (c - 10, c), (c, c + 10), (c + 10, c)];
+        sort_points(&mut points);
+        assert_eq!(
+            vec![(c + 10, c), (c, c + 10), (c - 10, c), (c + 10, c - 1)],
+            points
+        );
+    }
+
+    /// Tests that ring points are evenly spaced and clamped to the map.
+    #[test]
+    fn ring_points_spacing() {
+        assert_eq!(
+            vec![(80, 50), (50, 80), (20, 50), (50, 20)],
+            ring_points(4, 30.0)
+        );
+        assert_eq!(vec![(100, 50), (0, 50)], ring_points(2, 60.0));
+        assert!(ring_points(0, 30.0).is_empty());
+        let lines = ring_object_positions("RUINS", 3, 20);
+        assert_eq!(18, lines.len());
+        assert_eq!("create_actor_area 70 50 ring_object_3_20_0 0", lines[0]);
+        assert_eq!("actor_area_to_place_in ring_object_3_20_2", lines[16]);
+    }
+
+    /// Returns the points `(i, 0)` for `i` in `0..len`.
+    fn line_points(len: u32) -> PointList {
+        (0..len).map(|i| (i, 0)).collect()
+    }
+
+    /// Tests selecting 100 points from lists of exactly, more than, and fewer
+    /// than 100 points.
+    #[test]
+    fn select_100_points_spacing() {
+        let points = line_points(100);
+        assert_eq!(points, select_100_points(&points));
+
+        let selected = select_100_points(&line_points(250));
+        assert_eq!(100, selected.len());
+        assert_eq!((0, 0), selected[0]);
+        assert_eq!((249, 0), selected[99]);
+        assert!(selected
+            .windows(2)
+            .all(|w| (2..=3).contains(&(w[1].0 - w[0].0))));
+
+        // Cycles through the 50 points twice.
+        let points = line_points(50);
+        let selected = select_100_points(&points);
+        assert_eq!(points, selected[..50]);
+        assert_eq!(points, selected[50..]);
+    }
+
+    /// Tests that the Migration points are a square inset by 10 on all sides.
+    #[test]
+    fn square_points_inset() {
+        assert_eq!(
+            get_square_points_migration(),
+            get_square_points_with_inset(10)
+        );
+        let points = get_square_points_inset(25, 75, 20);
+        assert_eq!(4 * 51, points.len());
+        assert!(points.contains(&(25, 20)) && points.contains(&(80, 75)));
+        assert!(!points.contains(&(20, 20)) && !points.contains(&(24, 80)));
+    }
+
+    /// Tests that probabilities sum to the requested total.
+    #[test]
+    fn probabilities_totals() {
+        assert_eq!(100, probabilities(20, 60).iter().sum::<u32>());
+        for total in [1, 50, 80, 100] {
+            let probs = probabilities_normalized(20, 60, total);
+            assert_eq!(total, probs.iter().sum::<u32>());
+            assert!(probs[..20].iter().chain(&probs[61..]).all(|&p| p == 0));
+        }
+    }
+
+    /// Tests that a range starting at `0` yields valid probabilities.
+    #[test]
+    fn probabilities_left_zero() {
+        for (left, right) in [(0, 0), (0, 5), (0, 99)] {
+            let probs = probabilities(left, right);
+            assert_eq!(100, probs.len());
+            assert_eq!(100, probs.iter().sum::<u32>());
+            assert!(probs[right + 1..].iter().all(|&p| p == 0));
+        }
+        assert!(probabilities(0, 5)[..=5].iter().all(|&p| p > 0));
+    }
+
+    /// Tests that a random block with a shortfall sums to its total.
+    #[test]
+    fn p2_random_selection_shortfall() {
+        let lines = list_p2_random_selection_normalized(30.0, 112, 80);
+        let total: u32 = lines
+            .iter()
+            .filter_map(|line| line.strip_prefix("percent_chance "))
+            .map(|line| line.split(' ').next().unwrap().parse::<u32>().unwrap())
+            .sum();
+        assert_eq!(80, total);
+        assert_eq!(
+            list_p2_random_selection(30.0, 112),
+            list_p2_random_selection_normalized(30.0, 112, 100)
+        );
+    }
+
+    /// Returns the sorted distinct labels of `lines` beginning with `prefix`.
+    fn labels_with(lines: &[String], prefix: &str) -> Vec<String> {
+        let labels: std::collections::BTreeSet<_> = lines
+            .iter()
+            .flat_map(|line| line.split_whitespace())
+            .filter(|word| word.starts_with(prefix))
+            .map(String::from)
+            .collect();
+        labels.into_iter().collect()
+    }
+
+    /// Tests that the labels of a full layout define exactly the points and
+    /// offsets that its positions reference.
+    #[test]
+    fn full_layout_labels_match() {
+        let layout = full_layout(30.0, 112);
+        let points = labels_with(&layout.labels, "P1_POINT_");
+        assert_eq!(100, points.len());
+        assert_eq!(points, labels_with(&layout.p1_positions, "P1_POINT_"));
+        assert_eq!(points, labels_with(&layout.p2_positions, "P1_POINT_"));
+        assert_eq!(
+            labels_with(&layout.labels, "P2_OFFSET_"),
+            labels_with(&layout.p2_positions, "P2_OFFSET_")
+        );
+    }
+
+    /// Tests the feasibility of circle placements.
+    #[test]
+    fn placement_feasible() {
+        assert!(circle_placement_feasible(30.0, 112));
+        assert!(circle_placement_feasible(30.0, 135));
+        assert!(!circle_placement_feasible(100.0, 112));
+        assert!(!circle_placement_feasible(30.0, 270));
+    }
+}
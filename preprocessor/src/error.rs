@@ -0,0 +1,277 @@
+//! The error type for preprocessing map scripts.
+
+use std::{error::Error, fmt, io};
+
+/// An error encountered while preprocessing a map script.
+#[derive(Debug)]
+pub enum PreprocessError {
+    /// The script begins with `#HEADER_START`, but `#HEADER_END` never occurs.
+    UnterminatedHeader,
+    /// The comment opened on line `line` is never closed.
+    UnbalancedComment { line: usize },
+    /// The `#REPEAT` line `text` on line `line` does not have a non-negative
+    /// integer count.
+    BadRepeatCount { line: usize, text: String },
+    /// The count of the `#REPEAT` on line `line` is the const `name`, which is
+    /// defined inside of a `start_random` or `if` block, so its value is not
+    /// known when the script is preprocessed.
+    ConditionalRepeatCount { line: usize, name: String },
+    /// The `#REPEAT_EACH` line `text` on line `line` does not have a nonempty
+    /// list of values, or has a quoted value that is never closed.
+    BadRepeatValues { line: usize, text: String },
+    /// The `#REPEAT` block opened on line `line` is never closed by
+    /// `#END_REPEAT`.
+    UnterminatedRepeat { line: usize },
+    /// The brace on line `brace` opens a block that is closed outside of the
+    /// `#REPEAT` block opened on line `line`, or closes a block opened outside
+    /// of it.
+    RepeatSplitsBlock { line: usize, brace: usize },
+    /// The `#END_REPEAT` on line `line` occurs outside of any `#REPEAT` block.
+    UnexpectedEndRepeat { line: usize },
+    /// The included file `name` could not be found.
+    IncludeNotFound { name: String },
+    /// The file `name` includes itself, directly or indirectly.
+    IncludeCycle { name: String },
+    /// The arguments of the macro `text` on line `line` could not be parsed.
+    BadMacroArgs { line: usize, text: String },
+    /// The factor of the `#SCALE` directive `text` on line `line` is not a
+    /// nonnegative number.
+    BadScaleFactor { line: usize, text: String },
+    /// The line `text` on line `line` has an `rnd` that is not of the form
+    /// `rnd(min,max)` with non-negative integer bounds, so it cannot be
+    /// extracted.
+    BadRnd { line: usize, text: String },
+    /// The macro `name` on line `line` is given `found` arguments, but takes
+    /// one of the `expected` numbers of arguments.
+    MacroArgCount {
+        line: usize,
+        name: String,
+        expected: Vec<usize>,
+        found: usize,
+    },
+    /// The `#alias` directive `text` on line `line` does not have both a name
+    /// and a value.
+    BadAlias { line: usize, text: String },
+    /// The alias `name` defined on line `line` was already defined.
+    AliasRedefined { line: usize, name: String },
+    /// The `#IF_PLAYERS` condition `text` on line `line` is not a number of
+    /// players, optionally preceded by a comparison operator.
+    BadPlayerCondition { line: usize, text: String },
+    /// The `#IF_PLAYERS` on line `line` is used without a player count.
+    MissingPlayerCount { line: usize },
+    /// The `#IF_PLAYERS` block opened on line `line` is never closed by
+    /// `#ENDIF_PLAYERS`.
+    UnterminatedPlayerCondition { line: usize },
+    /// The `#ELSE` or `#ENDIF_PLAYERS` directive `text` on line `line` does not
+    /// belong to an `#IF_PLAYERS` block.
+    UnexpectedPlayerDirective { line: usize, text: String },
+    /// The `#LABEL_SCOPE` block opened on line `line` is never closed by
+    /// `#END_LABEL_SCOPE`.
+    UnterminatedLabelScope { line: usize },
+    /// The `#END_LABEL_SCOPE` on line `line` occurs outside of any
+    /// `#LABEL_SCOPE` block.
+    UnexpectedEndLabelScope { line: usize },
+    /// The `create_land` command opened on line `line` is never closed by a
+    /// `}`.
+    UnclosedLand { line: usize },
+    /// The `#COMMENT_OUT` region opened on line `line` is never closed by
+    /// `#END_COMMENT_OUT`.
+    UnterminatedCommentOut { line: usize },
+    /// The `#END_COMMENT_OUT` on line `line` occurs outside of any
+    /// `#COMMENT_OUT` region.
+    UnexpectedEndCommentOut { line: usize },
+    /// The `#COMMENT_OUT` on line `line` occurs inside of another
+    /// `#COMMENT_OUT` region.
+    NestedCommentOut { line: usize },
+    /// The `#SET_PLACE_FOR_EVERY_PLAYER` on line `line` occurs outside of any
+    /// `create_object` command.
+    PlacementOutsideObject { line: usize },
+    /// The `#SECTION` named `name` opened on line `line` is never closed by
+    /// `#END_SECTION`.
+    UnterminatedSection { line: usize, name: String },
+    /// The `#END_SECTION` on line `line` occurs outside of any `#SECTION`.
+    UnexpectedEndSection { line: usize },
+    /// The name `name` of the `#SECTION` on line `line` contains a comment
+    /// delimiter.
+    BadSectionName { line: usize, name: String },
+    /// The `#FOR_PLAYERS` block opened on line `line` is never closed by
+    /// `#END_FOR_PLAYERS`.
+    UnterminatedForPlayers { line: usize },
+    /// The `#END_FOR_PLAYERS` on line `line` occurs outside of any
+    /// `#FOR_PLAYERS` block.
+    UnexpectedEndForPlayers { line: usize },
+    /// The line `text` on line `line` has the form of a macro with arguments,
+    /// but no such macro exists.
+    UnknownMacro { line: usize, text: String },
+    /// The value `value` of the header metadata key `key` is invalid.
+    BadHeaderValue { key: String, value: String },
+    /// The `create_object` block starting on line `line` has a
+    /// `min_distance_to_players` of `min` greater than its
+    /// `max_distance_to_players` of `max`, so it is never placed.
+    InvertedDistances { line: usize, min: u32, max: u32 },
+    /// The block opened by the line `text` on line `line` of the output is
+    /// never closed by a `}`.
+    UnclosedBlock { line: usize, text: String },
+    /// The `}` on line `line` of the output does not close any block.
+    UnexpectedCloseBrace { line: usize },
+    /// A pipeline builder method referred to the pass or lint `name`, which
+    /// the pipeline does not have.
+    UnknownPass { name: String },
+    /// Reading the script or writing the output failed.
+    Io(io::Error),
+}
+
+impl fmt::Display for PreprocessError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PreprocessError::UnterminatedHeader => write!(f, "Header comment never ends."),
+            PreprocessError::UnbalancedComment { line } => {
+                write!(f, "Line {line}: comment is never closed.")
+            }
+            PreprocessError::BadRepeatCount { line, text } => write!(
+                f,
+                "Line {line}: repeat count must be a non-negative integer: `{text}`."
+            ),
+            PreprocessError::ConditionalRepeatCount { line, name } => write!(
+                f,
+                "Line {line}: repeat count `{name}` is defined inside of a random or conditional block."
+            ),
+            PreprocessError::BadRepeatValues { line, text } => {
+                write!(f, "Line {line}: invalid repeat values: `{text}`.")
+            }
+            PreprocessError::UnterminatedRepeat { line } => {
+                write!(f, "Line {line}: repeat block never ends.")
+            }
+            PreprocessError::RepeatSplitsBlock { line, brace } => write!(
+                f,
+                "Line {brace}: block crosses the boundary of the repeat block opened on line {line}; blocks must be wholly inside or outside of a repeat."
+            ),
+            PreprocessError::UnexpectedEndRepeat { line } => {
+                write!(f, "Line {line}: unexpected end repeat.")
+            }
+            PreprocessError::IncludeNotFound { name } => {
+                write!(f, "Included file `{name}` not found.")
+            }
+            PreprocessError::IncludeCycle { name } => {
+                write!(f, "File `{name}` includes itself.")
+            }
+            PreprocessError::BadMacroArgs { line, text } => {
+                write!(f, "Line {line}: invalid macro arguments: `{text}`.")
+            }
+            PreprocessError::BadScaleFactor { line, text } => write!(
+                f,
+                "Line {line}: scale factor must be a nonnegative number: `{text}`."
+            ),
+            PreprocessError::BadRnd { line, text } => write!(
+                f,
+                "Line {line}: `rnd` must have non-negative integer bounds: `{text}`."
+            ),
+            PreprocessError::MacroArgCount {
+                line,
+                name,
+                expected,
+                found,
+            } => {
+                let expected: Vec<String> = expected.iter().map(usize::to_string).collect();
+                let plural = if expected == ["1"] { "" } else { "s" };
+                write!(
+                    f,
+                    "Line {line}: `{name}` expected {} argument{plural}, got {found}.",
+                    expected.join(" or ")
+                )
+            }
+            PreprocessError::BadAlias { line, text } => {
+                write!(
+                    f,
+                    "Line {line}: alias must have a name and a value: `{text}`."
+                )
+            }
+            PreprocessError::AliasRedefined { line, name } => {
+                write!(f, "Line {line}: alias `{name}` is already defined.")
+            }
+            PreprocessError::BadPlayerCondition { line, text } => {
+                write!(f, "Line {line}: invalid player condition: `{text}`.")
+            }
+            PreprocessError::MissingPlayerCount { line } => {
+                write!(f, "Line {line}: player condition without a player count.")
+            }
+            PreprocessError::UnterminatedPlayerCondition { line } => {
+                write!(f, "Line {line}: player condition never ends.")
+            }
+            PreprocessError::UnexpectedPlayerDirective { line, text } => {
+                write!(f, "Line {line}: unexpected `{text}`.")
+            }
+            PreprocessError::UnterminatedLabelScope { line } => {
+                write!(f, "Line {line}: label scope never ends.")
+            }
+            PreprocessError::UnexpectedEndLabelScope { line } => {
+                write!(f, "Line {line}: unexpected end label scope.")
+            }
+            PreprocessError::UnclosedLand { line } => {
+                write!(f, "Line {line}: land is never closed, missing `}}`.")
+            }
+            PreprocessError::UnterminatedCommentOut { line } => {
+                write!(f, "Line {line}: comment out region never ends.")
+            }
+            PreprocessError::UnexpectedEndCommentOut { line } => {
+                write!(f, "Line {line}: unexpected end comment out.")
+            }
+            PreprocessError::NestedCommentOut { line } => {
+                write!(f, "Line {line}: comment out regions cannot be nested.")
+            }
+            PreprocessError::PlacementOutsideObject { line } => write!(
+                f,
+                "Line {line}: `#SET_PLACE_FOR_EVERY_PLAYER` outside of a `create_object` command."
+            ),
+            PreprocessError::UnterminatedSection { line, name } => {
+                write!(f, "Line {line}: section `{name}` never ends.")
+            }
+            PreprocessError::UnexpectedEndSection { line } => {
+                write!(f, "Line {line}: unexpected end section.")
+            }
+            PreprocessError::BadSectionName { line, name } => write!(
+                f,
+                "Line {line}: section name contains a comment delimiter: `{name}`."
+            ),
+            PreprocessError::UnterminatedForPlayers { line } => {
+                write!(f, "Line {line}: for players block never ends.")
+            }
+            PreprocessError::UnexpectedEndForPlayers { line } => {
+                write!(f, "Line {line}: unexpected end for players.")
+            }
+            PreprocessError::UnknownMacro { line, text } => {
+                write!(f, "Line {line}: unknown macro: `{text}`.")
+            }
+            PreprocessError::BadHeaderValue { key, value } => {
+                write!(f, "Invalid header value for `{key}`: `{value}`.")
+            }
+            PreprocessError::InvertedDistances { line, min, max } => write!(
+                f,
+                "Line {line}: `min_distance_to_players {min}` is greater than `max_distance_to_players {max}`."
+            ),
+            PreprocessError::UnclosedBlock { line, text } => {
+                write!(f, "Output line {line}: block is never closed: `{text}`.")
+            }
+            PreprocessError::UnexpectedCloseBrace { line } => {
+                write!(f, "Output line {line}: `}}` does not close a block.")
+            }
+            PreprocessError::UnknownPass { name } => write!(f, "No pass named `{name}`."),
+            PreprocessError::Io(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+impl Error for PreprocessError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            PreprocessError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for PreprocessError {
+    fn from(e: io::Error) -> Self {
+        PreprocessError::Io(e)
+    }
+}
@@ -11,9 +11,9 @@
 //! rnd(18,29)
 //! 30 to 89 | 30..=41, 42..=53, 54..=65, 66..= 77, 78..=89
 
-use std::f64::consts::{PI, TAU};
+use std::{f64::consts::TAU, ops::RangeInclusive};
 
-use crate::utils::{self, Pointf64, Pointu32};
+use crate::utils::{self, Pointf64, Pointu32, RandomBlock};
 
 /// The number of player TC slots.
 const NUM_SLOTS: usize = 20;
@@ -58,9 +58,243 @@ const ARENA_FOREST_BASE_SIZE: usize = 3;
 /// The number of forest lands used for Arena.
 const ARENA_NUM_FORESTS: usize = 128;
 
+/// An instance represents the `(x, y)` coordinate of a land position.
+pub type LandPoint = (i32, i32);
+
+/// An inclusive range `(min, max)` of values from which RMS chooses one
+/// uniformly at random with `rnd(min,max)`. If `min == max`, the range
+/// represents the exact value `min`.
+pub type Rnd = (i32, i32);
+
+/// Returns the RMS text for `range`, either a single number or an `rnd`.
+fn rnd_string((min, max): Rnd) -> String {
+    if min == max {
+        format!("{min}")
+    } else {
+        format!("rnd({min},{max})")
+    }
+}
+
+/// Returns the range of the number `v` or of `rnd(min,max)`, the inverse of
+/// `rnd_string`, or `None` if `s` has neither form.
+fn parse_rnd(s: &str) -> Option<Rnd> {
+    match s.strip_prefix("rnd(").and_then(|s| s.strip_suffix(')')) {
+        Some(range) => {
+            let (min, max) = range.split_once(',')?;
+            Some((min.trim().parse().ok()?, max.trim().parse().ok()?))
+        }
+        None => s.parse().ok().map(|v| (v, v)),
+    }
+}
+
+/// The player or team to which a land is assigned.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Assignment {
+    /// Assigns the land to the player with the given number with
+    /// `assign_to_player`.
+    Player(usize),
+    /// Assigns the land to a player on the team with the given number with
+    /// `assign_to AT_TEAM`.
+    Team(usize),
+}
+
+/// Returns the line assigning a land to the player numbered `player`.
+/// Requires `player >= 1`.
+pub fn assign_to_player_line(player: usize) -> String {
+    debug_assert!(player >= 1, "Player numbers start at 1.");
+    format!("assign_to_player {player}")
+}
+
+/// Returns the blocks `block(player)` for each player numbered in `players`,
+/// in order, such as a land duplicated for each player of a team.
+/// Requires every player in `players` is at least `1`.
+pub fn for_players<T>(
+    players: RangeInclusive<usize>,
+    block: impl FnMut(usize) -> Vec<T>,
+) -> Vec<T> {
+    debug_assert!(*players.start() >= 1, "Player numbers start at 1.");
+    players.flat_map(block).collect()
+}
+
+/// Returns the line assigning a land to a player on the team numbered `team`.
+/// Requires `team >= 1`, since `AT_TEAM 0` silently assigns nothing.
+pub fn assign_to_team_line(team: usize) -> String {
+    debug_assert!(team >= 1, "Team numbers start at 1.");
+    format!("assign_to AT_TEAM {team} 0 0")
+}
+
+/// A side of the map, from which a land's border is measured.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Border {
+    /// The `left_border` of the land.
+    Left,
+    /// The `right_border` of the land.
+    Right,
+    /// The `top_border` of the land.
+    Top,
+    /// The `bottom_border` of the land.
+    Bottom,
+}
+
+impl Border {
+    /// Every border, in the order their attributes are emitted.
+    const ALL: [Border; 4] = [Border::Left, Border::Right, Border::Top, Border::Bottom];
+
+    /// Returns the name of the border's attribute.
+    fn attribute(self) -> &'static str {
+        match self {
+            Border::Left => "left_border",
+            Border::Right => "right_border",
+            Border::Top => "top_border",
+            Border::Bottom => "bottom_border",
+        }
+    }
+}
+
+/// A structured representation of a single `create_land` command.
+///
+/// Allows tools to inspect generated lands without parsing the emitted
+/// script. Use `to_lines` to obtain the command's script.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LandSpec {
+    /// The `(x, y)` ranges of the `land_position`, as percentages of the map.
+    pub position: (Rnd, Rnd),
+    /// The `terrain_type` of the land.
+    pub terrain: String,
+    /// The `base_size` of the land.
+    pub base_size: u32,
+    /// The `number_of_tiles` of the land. Not emitted if `land_percent` is set.
+    pub tiles: Rnd,
+    /// The `land_percent` of the land, if set, in `0..=100`.
+    pub land_percent: Option<u32>,
+    /// The `border_fuzziness` of the land, if set, in `0..=100`.
+    pub border_fuzziness: Option<u32>,
+    /// The distance of the land from each side of the map, if set, indexed by
+    /// `Border`.
+    pub borders: [Option<u32>; 4],
+    /// The `zone` of the land, if set.
+    pub zone: Option<usize>,
+    /// The `land_id` of the land, if set.
+    pub land_id: Option<usize>,
+    /// The player or team to which the land is assigned, if any.
+    pub assign_to: Option<Assignment>,
+    /// The `clumping_factor` of the land, if set.
+    pub clumping_factor: Option<u32>,
+    /// The `other_zone_avoidance_distance` of the land, if set.
+    pub other_zone_avoidance_distance: Option<u32>,
+    /// A label that must be defined for the land to be created, if any.
+    /// The land's command is wrapped in an `if` statement on this label.
+    pub condition: Option<String>,
+}
+
+impl LandSpec {
+    /// Returns a new land at the exact position `(x, y)` with the given
+    /// `terrain`, `base_size`, and number of `tiles`, and without any other
+    /// attributes set.
+    pub fn new(position: LandPoint, terrain: &str, base_size: u32, tiles: i32) -> Self {
+        let (x, y) = position;
+        LandSpec {
+            position: ((x, x), (y, y)),
+            terrain: terrain.to_string(),
+            base_size,
+            tiles: (tiles, tiles),
+            land_percent: None,
+            border_fuzziness: None,
+            borders: [None; 4],
+            zone: None,
+            land_id: None,
+            assign_to: None,
+            clumping_factor: None,
+            other_zone_avoidance_distance: None,
+            condition: None,
+        }
+    }
+
+    /// Returns this land with its `land_percent` set to `percent`, replacing
+    /// its `number_of_tiles`. Panics if `percent` is not in `0..=100`.
+    pub fn with_land_percent(mut self, percent: u32) -> Self {
+        assert!(percent <= 100, "Land percent {percent} is not in 0..=100.");
+        self.land_percent = Some(percent);
+        self
+    }
+
+    /// Returns this land with its `border_fuzziness` set to `fuzziness`.
+    /// Panics if `fuzziness` is not in `0..=100`.
+    pub fn with_border_fuzziness(mut self, fuzziness: u32) -> Self {
+        assert!(
+            fuzziness <= 100,
+            "Border fuzziness {fuzziness} is not in 0..=100."
+        );
+        self.border_fuzziness = Some(fuzziness);
+        self
+    }
+
+    /// Returns this land with its `border` set to `distance`.
+    pub fn with_border(mut self, border: Border, distance: u32) -> Self {
+        self.borders[border as usize] = Some(distance);
+        self
+    }
+
+    /// Returns the lines of the `create_land` command for this land, including
+    /// the enclosing `if` statement if the land has a `condition`.
+    pub fn to_lines(&self) -> Vec<String> {
+        let mut lines = vec![];
+        if let Some(condition) = &self.condition {
+            lines.push(format!("if {condition}"));
+        }
+        let (x, y) = self.position;
+        lines.push(String::from("create_land {"));
+        lines.push(format!("land_position {} {}", rnd_string(x), rnd_string(y)));
+        lines.push(format!("terrain_type {}", self.terrain));
+        lines.push(format!("base_size {}", self.base_size));
+        match self.land_percent {
+            Some(percent) => lines.push(format!("land_percent {percent}")),
+            None => lines.push(format!("number_of_tiles {}", rnd_string(self.tiles))),
+        }
+        if let Some(fuzziness) = self.border_fuzziness {
+            lines.push(format!("border_fuzziness {fuzziness}"));
+        }
+        for border in Border::ALL {
+            if let Some(distance) = self.borders[border as usize] {
+                lines.push(format!("{} {distance}", border.attribute()));
+            }
+        }
+        if let Some(clumping_factor) = self.clumping_factor {
+            lines.push(format!("clumping_factor {clumping_factor}"));
+        }
+        if let Some(zone) = self.zone {
+            lines.push(format!("zone {zone}"));
+        }
+        if let Some(land_id) = self.land_id {
+            lines.push(format!("land_id {land_id}"));
+        }
+        match self.assign_to {
+            Some(Assignment::Player(player)) => lines.push(assign_to_player_line(player)),
+            Some(Assignment::Team(team)) => lines.push(assign_to_team_line(team)),
+            None => (),
+        }
+        if let Some(distance) = self.other_zone_avoidance_distance {
+            lines.push(format!("other_zone_avoidance_distance {distance}"));
+        }
+        lines.push(String::from("}"));
+        if self.condition.is_some() {
+            lines.push(String::from("endif"));
+        }
+        lines
+    }
+}
+
 #[derive(Clone, Copy, Debug, Hash, PartialEq, Eq, PartialOrd, Ord)]
 /// A player slot, must be in `0..NUM_SLOTS`.
-struct Slot(usize);
+pub struct Slot(usize);
+
+impl Slot {
+    /// Returns the index of the slot, counted clockwise from the first slot of
+    /// the top side.
+    pub fn index(self) -> usize {
+        self.0
+    }
+}
 
 /// Returns whether `s` satisfies the slot invariant.
 fn check_slot(s: Slot) -> bool {
@@ -75,14 +309,14 @@ fn opponent_probability(p1_slot: Slot, p2_slot: Slot) -> u32 {
     let max_slot = std::cmp::max(p1_slot.0, p2_slot.0);
     match (max_slot - min_slot) % NUM_SLOTS {
         7 | 13 => 10,
-        8 | 9 | 10 | 11 | 12 => 16,
+        8..=12 => 16,
         _ => 0,
     }
 }
 
-/// Returns the `land_position` instruction for generating a player land at
-/// the `slot`'s position.
-fn slot_to_position(slot: Slot) -> String {
+/// Returns the `(x, y)` ranges of the position of a player land at the
+/// `slot`'s position.
+fn slot_ranges(slot: Slot) -> (Rnd, Rnd) {
     let index = slot.0 % SLOTS_PER_SIZE;
     // Ensures the slot is counted clockwise.
     let index = if slot.0 >= 2 * SLOTS_PER_SIZE {
@@ -99,45 +333,75 @@ fn slot_to_position(slot: Slot) -> String {
         _ => panic!("Match was not exhaustive."),
     };
     // Converts from map tile coordinates to percentages of map side length.
-    let (low, high) = (
-        (low as f64 / 119.0 * 100.0).round(),
-        (high as f64 / 119.0 * 100.0).round(),
-    );
-    let side = (SIDE_DIST as f64 / 119.0 * 100.0).round() as usize;
-    let mid = (MID_DIST as f64 / 119.0 * 100.0).round() as usize;
-    let end_side = 100 - side;
-    let end_mid = 100 - mid;
+    let percent = |tiles: usize| (tiles as f64 / 119.0 * 100.0).round() as i32;
+    let along = (percent(low), percent(high));
+    let (side, mid) = (percent(SIDE_DIST), percent(MID_DIST));
+    let end = (100 - mid, 100 - side);
     match slot.0 / SLOTS_PER_SIZE {
-        0 => format!("land_position rnd({low},{high}) rnd({side},{mid})"),
-        1 => format!("land_position rnd({end_mid},{end_side}) rnd({low},{high})"),
-        2 => format!("land_position rnd({low},{high}) rnd({end_mid},{end_side})"),
-        3 => format!("land_position rnd({side},{mid}) rnd({low},{high})"),
+        0 => (along, (side, mid)),
+        1 => (end, along),
+        2 => (along, end),
+        3 => ((side, mid), along),
         _ => panic!("Match was not exhaustive."),
     }
 }
 
+/// Returns the `land_position` instruction for generating a player land at
+/// the `slot`'s position.
+fn slot_to_position(slot: Slot) -> String {
+    let (x, y) = slot_ranges(slot);
+    format!("land_position {} {}", rnd_string(x), rnd_string(y))
+}
+
+/// Returns the slot whose position is given by the `land_position` instruction
+/// `line`, the inverse of the positions generated by `#POSITION_P1` and
+/// `#POSITION_P2`. Returns `None` if `line` is not the position of a slot.
+pub fn position_to_slot(line: &str) -> Option<Slot> {
+    let mut words = line.split_whitespace();
+    if words.next()? != "land_position" {
+        return None;
+    }
+    let position = (parse_rnd(words.next()?)?, parse_rnd(words.next()?)?);
+    if words.next().is_some() {
+        return None;
+    }
+    (0..NUM_SLOTS)
+        .map(Slot)
+        .find(|&slot| slot_ranges(slot) == position)
+}
+
+/// Returns the percent chance of each label `P2_POS_j`, the probabilities of
+/// the opponent's slot being `P2_POS_OFFSET + j` slots after player 1's slot,
+/// normalized to sum to `100`. The probabilities depend only on the offset, not
+/// on player 1's slot.
+fn p2_position_probabilities() -> [u32; NUM_P2_POSITIONS] {
+    let mut probs: [u32; NUM_P2_POSITIONS] =
+        std::array::from_fn(|j| opponent_probability(Slot(0), Slot(P2_POS_OFFSET + j)));
+    let total = probs.iter().sum::<u32>() as f64;
+    for p in &mut probs {
+        *p = (*p as f64 * 100.0 / total).round() as u32;
+    }
+    // Gives the rounding error to the middle position.
+    let total = probs.iter().sum::<u32>();
+    let mid = NUM_P2_POSITIONS / 2;
+    probs[mid] = probs[mid] + 100 - total;
+    probs
+}
+
 /// Returns a list containing the random generation of labels. Each element of
 /// the list is a line.
 pub fn define_labels() -> Vec<String> {
-    let mut p1_labels: Vec<String> = (0..NUM_SLOTS)
-        .map(|k| format!("percent_chance 5 #define P1_SLOT_{k}"))
-        .collect();
-    let mut p2_labels = vec![
-        "percent_chance 10 #define P2_POS_0".to_string(),
-        "percent_chance 16 #define P2_POS_1".to_string(),
-        "percent_chance 16 #define P2_POS_2".to_string(),
-        "percent_chance 16 #define P2_POS_3".to_string(),
-        "percent_chance 16 #define P2_POS_4".to_string(),
-        "percent_chance 16 #define P2_POS_5".to_string(),
-        "percent_chance 10 #define P2_POS_6".to_string(),
-    ];
-
-    let mut lines = vec!["start_random".to_string()];
-    lines.append(&mut p1_labels);
-    lines.push("end_random".to_string());
-    lines.push("start_random".to_string());
-    lines.append(&mut p2_labels);
-    lines.push("end_random".to_string());
+    let p1_labels = (0..NUM_SLOTS).fold(RandomBlock::new(), |block, k| {
+        block.chance(5, format!("#define P1_SLOT_{k}"))
+    });
+    let p2_labels = p2_position_probabilities()
+        .iter()
+        .enumerate()
+        .fold(RandomBlock::new(), |block, (j, &p)| {
+            block.chance(p, format!("#define P2_POS_{j}"))
+        });
+    let mut lines = p1_labels.build();
+    lines.append(&mut p2_labels.build());
     lines
 }
 
@@ -151,7 +415,7 @@ fn prefix_p1_slot(slot: Slot) -> String {
 /// P1's TC.
 pub fn p1_position() -> Vec<String> {
     let labels: Vec<String> = (0..NUM_SLOTS)
-        .map(|k| Slot(k))
+        .map(Slot)
         .map(|slot| {
             format!(
                 "{} P1_SLOT_{}\n{}",
@@ -201,7 +465,7 @@ pub fn rock_border() -> Vec<String> {
             lines.push(format!("land_position {x} {y}"));
             lines.push(String::from("number_of_tiles rnd(15,25)"));
             lines.push(format!("base_size {base_size}"));
-            lines.push(format!("base_elevation rnd(4,6)"));
+            lines.push(String::from("base_elevation rnd(4,6)"));
             lines.push(String::from("terrain_type DLC_ROCK"));
             lines.push(format!("zone {zone}"));
             lines.push(String::from("}"));
@@ -215,13 +479,18 @@ pub fn rock_border() -> Vec<String> {
 /// the boundaries from `0` to `100`, inclusive for `x` and `0` through `99` for `y`.
 /// Returns the resulting point.
 ///
-/// Requires that the conversion produces a
-/// result where both coordinates are nonnegative `u32` values.
+/// The `radius` and `center` are in percent of the map, the units of
+/// `land_position`. Each coordinate is rounded to the nearest integer, and
+/// coordinates outside of the map are clamped to its boundaries, so the result
+/// is always a valid `land_position`: `x` in `0..=100` and `y` in `0..=99`.
 ///
 /// Typically `x` is the result of a cosine, and `y` is the result of a sine.
-fn convert(&(x, y): &Pointf64, radius: f64, center: &Pointf64) -> Pointu32 {
-    use utils::round;
-    (round(x * radius + center.0), round(y * radius + center.1))
+pub fn convert(&(x, y): &Pointf64, radius: f64, center: &Pointf64) -> Pointu32 {
+    use utils::round_clamped;
+    (
+        round_clamped(x * radius + center.0, 0, 100),
+        round_clamped(y * radius + center.1, 0, 99),
+    )
 }
 
 /// Returns a list of centers of lands to use for placing forests on Arena.
@@ -229,7 +498,11 @@ fn convert(&(x, y): &Pointf64, radius: f64, center: &Pointf64) -> Pointu32 {
 /// `radius` is the radius of the circle, must be strictly positive.
 /// `center` is the center of the circle, both coordinates must be strictly positive.
 /// The `radius` and `center` must result in all points having nonnegative coordinates.
-fn arena_centers(num_lands: usize, radius: f64, center: &Pointf64) -> Vec<Pointu32> {
+///
+/// The lands are evenly spaced, starting at angle `0` on the positive `x` axis
+/// and increasing the angle towards the positive `y` axis, and each is mapped
+/// to the map with `convert`.
+pub fn arena_centers(num_lands: usize, radius: f64, center: &Pointf64) -> Vec<Pointu32> {
     debug_assert!(num_lands > 0 && radius > 0.0 && center.0 > 0.0 && center.1 > 0.0);
     use utils::{cos, sin};
     let increment = TAU / num_lands as f64;
@@ -240,36 +513,86 @@ fn arena_centers(num_lands: usize, radius: f64, center: &Pointf64) -> Vec<Pointu
         .collect()
 }
 
-/// Converts the point `(x, y)` to a string representing a `create_land`
-/// instruction.
-fn land_string((x, y): &Pointu32) -> String {
-    let mut components = vec![String::from("create_land {")];
-    components.push(format!("land_position {x} {y}"));
-    components.push(String::from("terrain_type OUTSIDE_FOREST"));
-    components.push(String::from("base_size 4"));
-    components.push(String::from("number_of_tiles 128"));
-    components.push(String::from("clumping_factor 40"));
-    components.push(String::from("}"));
-    components.join(" ")
+/// Returns the `OUTSIDE_FOREST` lands that form a circle for a
+/// 2v2 game of Arena on a Medium (4 player) map size.
+pub fn arena_circles_2v2_specs() -> Vec<LandSpec> {
+    arena_centers(64, 40.0, &(50.0, 50.0))
+        .iter()
+        .map(|&(x, y)| {
+            let mut land = LandSpec::new((x as i32, y as i32), "OUTSIDE_FOREST", 4, 128);
+            land.clumping_factor = Some(40);
+            land
+        })
+        .collect()
 }
 
 /// Returns the `OUTSIDE_FOREST` lands that form a circle for a
 /// 2v2 game of Arena on a Medium (4 player) map size.
+/// Each land's `create_land` command is written on a single line.
 pub fn arena_circles_2v2() -> Vec<String> {
     // TODO avoid lands near player land centers?
-    arena_centers(64, 40.0, &(50.0, 50.0))
+    arena_circles_2v2_specs()
         .iter()
-        .map(land_string)
+        .map(|land| land.to_lines().join(" "))
         .collect()
 }
 
-/// Returns a random block with a 1 percent chance to define
-/// a label `directioni`, where `i` is in `0..=99`.
-pub fn direction_labels() -> Vec<String> {
-    let mut lines = Vec::with_capacity(102);
+/// The number of equally likely rotations of a map. Rotation `d` is selected
+/// by the label `DIRECTION{d}`, where `d` is in `0..count`.
+///
+/// Both the labels and the code consuming them are generated from the same
+/// instance, keeping the number of labels and branches in lockstep.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Directions(u32);
+
+impl Directions {
+    /// Returns `count` directions.
+    /// Requires `1 <= count <= 100`.
+    pub fn new(count: u32) -> Self {
+        debug_assert!(count > 0 && count <= 100);
+        Directions(count)
+    }
+
+    /// Returns the number of directions.
+    pub fn count(self) -> u32 {
+        self.0
+    }
+
+    /// Returns the angle in radians of direction `d`, with the directions
+    /// spaced equally around the circle.
+    pub fn angle(self, d: u32) -> f64 {
+        d as f64 * (TAU / self.0 as f64)
+    }
+
+    /// Returns an `if` statement with a branch for each direction `d`
+    /// conditioned on `DIRECTION{d}` and containing the lines of `branch(d)`.
+    fn branches(self, mut branch: impl FnMut(u32) -> Vec<String>) -> Vec<String> {
+        let mut lines = vec![];
+        for d in 0..self.0 {
+            let start = if d == 0 { "if" } else { "elseif" };
+            lines.push(format!("{start} DIRECTION{d}"));
+            lines.append(&mut branch(d));
+        }
+        lines.push(String::from("endif"));
+        lines
+    }
+}
+
+impl Default for Directions {
+    /// Returns the `100` directions used by `#DIRLABELS`.
+    fn default() -> Self {
+        Directions(100)
+    }
+}
+
+/// Returns a random block defining one label `DIRECTION{i}` for each of the
+/// `directions`, where the labels have equal probabilities.
+pub fn direction_labels(directions: Directions) -> Vec<String> {
+    let percents = crate::probs(100, directions.count());
+    let mut lines = Vec::with_capacity(percents.len() + 2);
     lines.push(String::from("start_random"));
-    for i in 0..=99 {
-        lines.push(format!("percent_chance 1 #define DIRECTION{i}"));
+    for (i, p) in percents.iter().enumerate() {
+        lines.push(format!("percent_chance {p} #define DIRECTION{i}"));
     }
     lines.push(String::from("end_random"));
     lines
@@ -278,15 +601,15 @@ pub fn direction_labels() -> Vec<String> {
 /// Pushes strings for the `create_land` commands for a single player to `lines`,
 /// where the player's main base is at angle `theta`.
 /// `player` is the player whose land is appened.
-/// Requires `1 <= player <= 4` and `0.0 <= theta < TAU`.
+/// Requires `1 <= player` and `0.0 <= theta < TAU`.
 fn push_arena_player_lands(player: usize, theta: f64, lines: &mut Vec<String>) {
-    debug_assert!(1 <= player && player <= 4 && 0.0 <= theta && theta < TAU);
-    use utils::{cos, round, sin};
+    debug_assert!(player >= 1 && (0.0..TAU).contains(&theta));
+    use utils::{cos, round_clamped, sin};
     let radius = 34.0;
     let center = 50.0;
     let (x, y) = (
-        round(cos(theta) * radius + center),
-        round(sin(theta) * radius + center),
+        round_clamped(cos(theta) * radius + center, 0, 100),
+        round_clamped(sin(theta) * radius + center, 0, 100),
     );
 
     // Player land.
@@ -295,7 +618,7 @@ fn push_arena_player_lands(player: usize, theta: f64, lines: &mut Vec<String>) {
     lines.push(String::from("base_size 12"));
     lines.push(String::from("number_of_tiles 0"));
     lines.push(String::from("terrain_type PLAYER_TERRAIN"));
-    lines.push(format!("assign_to_player {player}"));
+    lines.push(assign_to_player_line(player));
     lines.push(String::from("}"));
 
     // Avoid forest wibbles.
@@ -303,8 +626,8 @@ fn push_arena_player_lands(player: usize, theta: f64, lines: &mut Vec<String>) {
     for t in turns {
         for i in -10..=5 {
             let (x, y) = (
-                round(cos(theta + t) * (radius + i as f64) + center),
-                round(sin(theta + t) * (radius + i as f64) + center),
+                round_clamped(cos(theta + t) * (radius + i as f64) + center, 0, 100),
+                round_clamped(sin(theta + t) * (radius + i as f64) + center, 0, 100),
             );
             lines.push(String::from("create_land {"));
             lines.push(format!("land_position {x} {y}"));
@@ -393,79 +716,117 @@ fn push_arena_player_lands(player: usize, theta: f64, lines: &mut Vec<String>) {
     // lines.push(String::from("}"));
 }
 
-/// Returns four `create_land` commands for combining player lands on Arena.
-pub fn arena_lands() -> Vec<String> {
-    let increment = TAU / 100.0;
-    let quarter = TAU / 4.0;
-    let mut lines = vec![];
-    for i in 0..=99 {
-        lines.push(format!("elseif DIRECTION{i}"));
-        for p in 1..=4 {
-            let theta = (i as f64 * increment + (p - 1) as f64 * quarter) % TAU;
+/// Returns four `create_land` commands for combining player lands on Arena
+/// for each of the `directions`.
+pub fn arena_lands(directions: Directions) -> Vec<String> {
+    arena_lands_players(directions, 4)
+}
+
+/// Returns `num_players` `create_land` commands for combining player lands on
+/// Arena for each of the `directions`, with the players spaced equally around
+/// the circle.
+/// Requires `num_players` to divide `directions.count()` evenly, so that every
+/// player's land lies on one of the `directions`.
+pub fn arena_lands_players(directions: Directions, num_players: usize) -> Vec<String> {
+    debug_assert!(
+        num_players > 0 && (directions.count() as usize).is_multiple_of(num_players),
+        "{num_players} players do not divide {} directions.",
+        directions.count()
+    );
+    let turn = TAU / num_players as f64;
+    directions.branches(|d| {
+        let mut lines = vec![];
+        for p in 1..=num_players {
+            let theta = (directions.angle(d) + (p - 1) as f64 * turn) % TAU;
             push_arena_player_lands(p, theta, &mut lines);
         }
-    }
-    lines[0] = String::from("if DIRECTION0");
-    lines.push(String::from("endif"));
-    lines
+        lines
+    })
 }
 
-/// Returns `create_land` commands for generating the shallow terrains
-/// down the middle for Ze Snake.
-pub fn snake_lands() -> Vec<String> {
+/// The default `terrain_type` of the wave down the middle of Ze Snake.
+/// The wave is ordinary shallows, so it stands out from the border.
+pub const SNAKE_WAVE_TERRAIN: &str = "SHALLOW";
+
+/// The default `base_size` of each land of the Ze Snake wave.
+pub const SNAKE_WAVE_BASE_SIZE: u32 = 1;
+
+/// The default amplitude of the Ze Snake wave, in percent of the map.
+pub const SNAKE_WAVE_AMPLITUDE: f64 = 2.0;
+
+/// The default frequency of the Ze Snake wave, in radians per percent of the
+/// map.
+pub const SNAKE_WAVE_FREQUENCY: f64 = 1.0;
+
+/// The `terrain_type` of the Ze Snake border lands.
+/// The border uses mangrove shallows, unlike the wave, so the edges of the map
+/// look distinct from the middle.
+pub const SNAKE_BORDER_TERRAIN: &str = "DLC_MANGROVESHALLOW";
+
+/// Returns the lands of the wave down the middle of Ze Snake when the snake is
+/// rotated in direction `d` of the `directions`.
+/// Each land has the given `terrain` and `base_size`, following a sine wave
+/// with the given `amplitude` and `frequency`.
+/// Requires `d < directions.count()`.
+pub fn snake_land_specs(
+    directions: Directions,
+    d: u32,
+    terrain: &str,
+    base_size: u32,
+    amplitude: f64,
+    frequency: f64,
+) -> Vec<LandSpec> {
+    debug_assert!(d < directions.count());
     use utils::{cos, sin};
-    let scale = 2.0;
     let n = 100;
-    let mut lines = vec![];
-    for direction in 0..100 {
-        lines.push(format!("elseif DIRECTION{direction}"));
-        let mut points = vec![];
-        for t in 0..n {
-            let x = t as f64;
-            points.push((x, scale * sin(x)));
-            if t != 0 {
-                points.push((-x, scale * sin(-x)));
-            }
-        }
-        let theta = direction as f64 * PI / 50.0;
-        let (cost, sint) = (cos(theta), sin(theta));
-        let points: Vec<(i32, i32)> = points
-            .iter()
-            .map(|(x, y)| (x * cost - y * sint, x * sint - y * cost))
-            .map(|(x, y)| (x + 50.0, y + 50.0))
-            .map(|(x, y)| (x.round() as i32, y.round() as i32))
-            .filter(|(x, y)| *x >= 0 && *x <= 100 && *y >= 0 && *y < 100)
-            .collect();
-        for (x, y) in points {
-            lines.push(String::from("create_land {"));
-            lines.push(String::from("terrain_type SHALLOW"));
-            lines.push(String::from("number_of_tiles 0"));
-            lines.push(String::from("base_size 1"));
-            lines.push(format!("land_position {x} {y}"));
-            lines.push(String::from("}"));
+    let mut points = vec![];
+    for t in 0..n {
+        let x = t as f64;
+        points.push((x, amplitude * sin(frequency * x)));
+        if t != 0 {
+            points.push((-x, amplitude * sin(-frequency * x)));
         }
     }
-    lines[0] = String::from("if DIRECTION0");
-    lines.push(String::from("endif"));
-    // for t in 0..100 {
-    //     lines.push(String::from("create_land {"));
-    //     lines.push(String::from("terrain_type SHALLOW"));
-    //     lines.push(String::from("number_of_tiles 0"));
-    //     lines.push(String::from("base_size 2"));
-    //     lines.push(format!("land_position 50 {t}"));
-    //     lines.push(String::from("}"));
-    // }
-    lines
+    let theta = directions.angle(d);
+    let (cost, sint) = (cos(theta), sin(theta));
+    points
+        .iter()
+        .map(|(x, y)| (x * cost - y * sint, x * sint - y * cost))
+        .map(|(x, y)| (x + 50.0, y + 50.0))
+        .map(|(x, y)| (x.round() as i32, y.round() as i32))
+        .filter(|(x, y)| *x >= 0 && *x <= 100 && *y >= 0 && *y < 100)
+        .map(|p| LandSpec::new(p, terrain, base_size, 0))
+        .collect()
+}
+
+/// Returns `create_land` commands for generating the wave down the middle of
+/// Ze Snake for each of the `directions`, using the `terrain`, `base_size`,
+/// `amplitude`, and `frequency` of `snake_land_specs`.
+/// The `#SNAKELANDS` macro uses `SNAKE_WAVE_TERRAIN`, `SNAKE_WAVE_BASE_SIZE`,
+/// `SNAKE_WAVE_AMPLITUDE`, and `SNAKE_WAVE_FREQUENCY` when given no arguments.
+pub fn snake_lands(
+    directions: Directions,
+    terrain: &str,
+    base_size: u32,
+    amplitude: f64,
+    frequency: f64,
+) -> Vec<String> {
+    directions.branches(|d| {
+        snake_land_specs(directions, d, terrain, base_size, amplitude, frequency)
+            .iter()
+            .flat_map(LandSpec::to_lines)
+            .collect()
+    })
 }
 
-/// Returns a line to create a a land with `number_of_tiles` set to `0`
-/// at position `x` `y` with the given `base_size`.
+/// Returns a line to create a a land of `SNAKE_BORDER_TERRAIN` with
+/// `number_of_tiles` set to `0` at position `x` `y` with the given `base_size`.
 /// Requires `x <= 100` and `y <= 100`.
 pub fn snake_land_line(base_size: usize, x: usize, y: usize) -> String {
     debug_assert!(x <= 100 && y <= 100);
-    vec![
+    [
         String::from("create_land {"),
-        String::from("terrain_type DLC_MANGROVESHALLOW"),
+        format!("terrain_type {SNAKE_BORDER_TERRAIN}"),
         String::from("number_of_tiles 0"),
         format!("base_size {base_size}"),
         format!("land_position {x} {y}"),
@@ -475,68 +836,175 @@ pub fn snake_land_line(base_size: usize, x: usize, y: usize) -> String {
 }
 
 /// Returns a vector with the rock border lands for ZeSnake.
+/// Each border position has a single land, so the corners are created only by
+/// the top and bottom borders.
 pub fn snake_borders() -> Vec<String> {
     let mut lines = vec![];
     for x in 0..=100 {
         lines.push(snake_land_line(1, x, 0));
         lines.push(snake_land_line(2, x, 100));
     }
-    for y in 0..=100 {
+    for y in 1..100 {
         lines.push(snake_land_line(1, 0, y));
         lines.push(snake_land_line(2, 100, y));
     }
     lines
 }
 
+/// The default `terrain_type` of the Four Seasons divider.
+pub const FOUR_SEASONS_DIVIDER_TERRAIN: &str = "DLC_ROCK";
+
+/// The default thickness of each line of the Four Seasons divider. A line of
+/// thickness `n` is `2 * n - 1` tiles wide, see `four_seasons_lands`.
+pub const FOUR_SEASONS_DIVIDER_THICKNESS: u32 = 1;
+
 /// Returns a vector with the middle separating lands for Four Seasons.
-pub fn four_seasons_lands() -> Vec<String> {
+/// The divider is a cross of `terrain` lands centered at `x = 50` and
+/// `y = 50`. Each line of the cross is extended by `thickness - 1` parallel
+/// lines on each side of the center, so it is `2 * thickness - 1` tiles wide,
+/// omitting lines outside of `0..=100`.
+/// Each position has a single land, so the positions where the lines cross are
+/// created only by the horizontal lines.
+/// Requires `thickness > 0`.
+pub fn four_seasons_lands(terrain: &str, thickness: u32) -> Vec<String> {
+    debug_assert!(thickness > 0);
     let mut lines = vec![];
+    let spread = thickness as i32 - 1;
+    let offsets: Vec<i32> = (50 - spread..=50 + spread)
+        .filter(|c| (0..=100).contains(c))
+        .collect();
     // Middle forest.
-    for x in 0..=100 {
-        lines.push(format!("create_land {{ land_position {x} 50 base_size 1 number_of_tiles 0 terrain_type DLC_ROCK }}"))
+    for &y in &offsets {
+        for x in 0..=100 {
+            lines.push(format!("create_land {{ land_position {x} {y} base_size 1 number_of_tiles 0 terrain_type {terrain} }}"))
+        }
+    }
+    for &x in &offsets {
+        for y in (0..=100).filter(|y| !offsets.contains(y)) {
+            lines.push(format!("create_land {{ land_position {x} {y} base_size 1 number_of_tiles 0 terrain_type {terrain} }}"))
+        }
     }
-    for y in 0..=100 {
-        lines.push(format!("create_land {{ land_position 50 {y} base_size 1 number_of_tiles 0 terrain_type DLC_ROCK }}"))
+    lines
+}
+
+/// Returns the lines of a land filling `percent` of the map with `terrain`,
+/// such as for a background terrain. The land is centered on the map with a
+/// `base_size` of `0`, and its size is driven by its `land_percent`, so it has
+/// no `number_of_tiles`. Requires `percent <= 100`, which `#FILL` checks
+/// before expanding.
+pub fn fill_land(terrain: &str, percent: u32) -> Vec<String> {
+    LandSpec::new((50, 50), terrain, 0, 0)
+        .with_land_percent(percent)
+        .to_lines()
+}
+
+/// The `terrain_type` of the land keeping the center of a ring layout clear.
+/// Like `PLAYER_TERRAIN`, it must be defined by the map script.
+pub const RING_CENTER_TERRAIN: &str = "CENTER_TERRAIN";
+
+/// The `base_size` of each player land of a ring layout.
+pub const RING_PLAYER_BASE_SIZE: u32 = 12;
+
+/// Returns whether the player lands of a ring of the given `radius` stay
+/// outside of the center land with a `base_size` of `center_clear_radius`.
+///
+/// The `radius` is in percent of the map and the `center_clear_radius` is in
+/// tiles. Every map has at least 100 tiles per side, so the players are at
+/// least `radius` tiles from the center, and each player land reaches
+/// `RING_PLAYER_BASE_SIZE` tiles toward it. Hence a `radius` above the sum of
+/// the two sizes keeps the lands apart on every map size.
+pub fn ring_clears_center(radius: u32, center_clear_radius: u32) -> bool {
+    radius > center_clear_radius + RING_PLAYER_BASE_SIZE
+}
+
+/// Returns the lines of a ring layout with `num_players` player lands spaced
+/// equally on the circle of the given `radius` around the center of the map,
+/// starting from angle `0`, and a land of `RING_CENTER_TERRAIN` at the center
+/// with a `base_size` of `center_clear_radius` that keeps the center open.
+/// The center land is created first, so it claims its tiles before the player
+/// lands.
+///
+/// The `radius` is in percent of the map and the `center_clear_radius` is in
+/// tiles. Requires `num_players > 0`, `radius <= 50`, and
+/// `ring_clears_center(radius, center_clear_radius)`, which `#RINGPLAYERS`
+/// checks before expanding.
+pub fn ring_players(num_players: usize, radius: u32, center_clear_radius: u32) -> Vec<String> {
+    assert!(num_players > 0, "A ring needs at least one player.");
+    assert!(
+        ring_clears_center(radius, center_clear_radius),
+        "Ring radius {radius} does not clear the center of radius {center_clear_radius}."
+    );
+    assert!(radius <= 50, "Ring radius {radius} is above 50.");
+    use utils::{cos, sin};
+    let center = LandSpec::new((50, 50), RING_CENTER_TERRAIN, center_clear_radius, 0);
+    let mut lines = center.to_lines();
+    let turn = TAU / num_players as f64;
+    for p in 1..=num_players {
+        let theta = (p - 1) as f64 * turn;
+        let (x, y) = convert(&(cos(theta), sin(theta)), radius as f64, &(50.0, 50.0));
+        let mut land = LandSpec::new(
+            (x as i32, y as i32),
+            "PLAYER_TERRAIN",
+            RING_PLAYER_BASE_SIZE,
+            0,
+        );
+        land.assign_to = Some(Assignment::Player(p));
+        lines.extend(land.to_lines());
     }
     lines
 }
 
 /// Appends the lines for the lake in the corner of the given region.
+/// If `land_id` is given, each lake is created with that `land_id`.
 /// Requires `region` is one of `GRASS`, `SNOW`, `DIRT`, or `JUNGLE`.
-fn append_lake(region: &str, lines: &mut Vec<String>) {
+fn append_lake(region: &str, land_id: Option<usize>, lines: &mut Vec<String>) {
     let base_size = 5;
     let border = 86;
     let fuzz = 15;
-    lines.push(format!("if WEST_{region}"));
-    lines.push(format!("create_land {{ land_position 0 0 base_size {base_size} border_fuzziness {fuzz} right_border {border} bottom_border {border} land_percent 100 terrain_type {region}_WATER_PLACEHOLDER }}"));
-    lines.push(format!("elseif NORTH_{region}"));
-    lines.push(format!("create_land {{ land_position 100 0 base_size {base_size} border_fuzziness {fuzz} left_border {border} bottom_border {border} land_percent 100 terrain_type {region}_WATER_PLACEHOLDER }}"));
-    lines.push(format!("elseif SOUTH_{region}"));
-    lines.push(format!("create_land {{ land_position 0 99 base_size {base_size} border_fuzziness {fuzz} right_border {border} top_border {border} land_percent 100 terrain_type {region}_WATER_PLACEHOLDER }}"));
-    lines.push(format!("elseif EAST_{region}"));
-    lines.push(format!("create_land {{ land_position 100 99 base_size {base_size} border_fuzziness {fuzz} left_border {border} top_border {border} land_percent 100 terrain_type {region}_WATER_PLACEHOLDER }}"));
+    let corners = [
+        ("WEST", (0, 0), [Border::Right, Border::Bottom]),
+        ("NORTH", (100, 0), [Border::Left, Border::Bottom]),
+        ("SOUTH", (0, 99), [Border::Right, Border::Top]),
+        ("EAST", (100, 99), [Border::Left, Border::Top]),
+    ];
+    let mut ifword = "if";
+    for (corner, position, borders) in corners {
+        let terrain = format!("{region}_WATER_PLACEHOLDER");
+        let mut lake = LandSpec::new(position, &terrain, base_size, 0)
+            .with_land_percent(100)
+            .with_border_fuzziness(fuzz);
+        for side in borders {
+            lake = lake.with_border(side, border);
+        }
+        lake.land_id = land_id;
+        lines.push(format!("{ifword} {corner}_{region}"));
+        // Keeps each lake on a single line.
+        lines.push(lake.to_lines().join(" "));
+        ifword = "elseif";
+    }
     lines.push(String::from("endif"));
 }
 
 /// Returns a vector with the if statements for creating the corner lakes.
-pub fn four_seasons_lakes() -> Vec<String> {
+/// If `land_id` is given, every lake is created with that `land_id`.
+pub fn four_seasons_lakes(land_id: Option<usize>) -> Vec<String> {
     let mut lines = vec![];
     let regions = ["GRASS", "SNOW", "DIRT", "JUNGLE"];
     for region in regions {
         lines.push(format!("if {region}_LAKE"));
-        append_lake(region, &mut lines);
+        append_lake(region, land_id, &mut lines);
         lines.push(String::from("endif"));
     }
     lines
 }
 
-/// Returns a string `land_position rnd({x - 1},{x + 1}) rnd({y - 1},{y + 1})`,
-/// but clamping both numbers to `1..98`.
-fn rnd_pos(x: i32, y: i32) -> String {
+/// Returns the position ranges `(x - 1, x + 1)` and `(y - 1, y + 1)`, but
+/// clamping all numbers to `1..98`.
+fn rnd_range(x: i32, y: i32) -> (Rnd, Rnd) {
     fn clamp(x: i32) -> i32 {
-        std::cmp::max(1, std::cmp::min(x, 98))
+        x.clamp(1, 98)
     }
-    format! {"land_position rnd({},{}) rnd({},{})", clamp(x - 1), clamp(x + 1), clamp(y - 1), clamp(y + 1)}
+    ((clamp(x - 1), clamp(x + 1)), (clamp(y - 1), clamp(y + 1)))
 }
 
 // /// Returns a list of the positions of 8 points centered around `(x, y)`.
@@ -631,9 +1099,17 @@ fn rnd_pos(x: i32, y: i32) -> String {
 //     lines
 // }
 
+/// The default angle in radians between a player land on Arena and the
+/// nearest forest land.
+pub const ARENA_FOREST_TOLERANCE: f64 = TAU / 32.0 + 0.1;
+
 /// Returns the lines for placing forests on Arena in direction `0 <= d < ARENA_NUM_DIRECTIONS`.
-pub fn arena_forest_lines_direction(d: usize) -> Vec<String> {
+/// Forests are omitted within `tolerance` radians of each player land, so a
+/// larger `tolerance` leaves a wider gap around the player lands.
+/// Requires `tolerance >= 0.0`.
+pub fn arena_forest_lines_direction(d: usize, tolerance: f64) -> Vec<String> {
     debug_assert!(d <= ARENA_NUM_DIRECTIONS);
+    debug_assert!(tolerance >= 0.0);
     use utils::{cos, round, sin};
     let increment = TAU / ARENA_NUM_FORESTS as f64;
     let space = 7.0;
@@ -642,7 +1118,6 @@ pub fn arena_forest_lines_direction(d: usize) -> Vec<String> {
     let player_angles: Vec<f64> = (0..=3)
         .map(|i| (d as f64 + i as f64 * quartertau) % TAU)
         .collect();
-    let tolerance = TAU / 32.0 + 0.1;
     for i in 0..ARENA_NUM_FORESTS {
         let theta = i as f64 * increment;
         // Avoid placing forests near the player lands.
@@ -664,7 +1139,8 @@ pub fn arena_forest_lines_direction(d: usize) -> Vec<String> {
 }
 
 /// Returns the if statement for placing the forest lands on arena.
-pub fn arena_circle_gaps() -> Vec<String> {
+/// Forests are omitted within `tolerance` radians of each player land.
+pub fn arena_circle_gaps(tolerance: f64) -> Vec<String> {
     use utils::{cos, round, sin};
     let quartertau = TAU / 4.0;
     let mut lines = vec![];
@@ -675,10 +1151,10 @@ pub fn arena_circle_gaps() -> Vec<String> {
         for (i, theta) in player_angles.enumerate() {
             let x = round(50.0 + ARENA_FOREST_RADIUS * cos(theta));
             let y = round(50.0 + ARENA_FOREST_RADIUS * sin(theta));
-            let team = if i <= 1 { 1 } else { 2 };
-            lines.push(format!("create_land {{ land_position {x} {y} base_size 14 land_percent 6 terrain_type PLAYER_TERRAIN assign_to AT_TEAM {team} 0 0 clumping_factor 30 top_border 3 right_border 3 bottom_border 3 left_border 3 other_zone_avoidance_distance 30 }}"))
+            let assign_to = assign_to_team_line(if i <= 1 { 1 } else { 2 });
+            lines.push(format!("create_land {{ land_position {x} {y} base_size 14 land_percent 6 terrain_type PLAYER_TERRAIN {assign_to} clumping_factor 30 top_border 3 right_border 3 bottom_border 3 left_border 3 other_zone_avoidance_distance 30 }}"))
         }
-        lines.append(&mut arena_forest_lines_direction(d));
+        lines.append(&mut arena_forest_lines_direction(d, tolerance));
     }
     lines.push(String::from("endif"));
     lines
@@ -691,18 +1167,21 @@ pub fn arena_players_gaps() -> Vec<String> {
     for d in 0..=24 {
         let start = if d == 0 { "if" } else { "elseif" };
         lines.push(format!("{start} DIRECTION{d}"));
-        lines.append(&mut arena_forest_lines_direction(d));
+        lines.append(&mut arena_forest_lines_direction(d, ARENA_FOREST_TOLERANCE));
     }
     lines.push(String::from("endif"));
     lines
 }
 
-/// An instance represents the `(x, y)` coordinate of a land position.
-type LandPoint = (i32, i32);
-
 /// Returns equally spaces angles for 4 players lands with the
 /// first land at angle `theta` and the remaining angles being ordered
 /// counterclockwise, starting from `theta`.
+///
+/// The index `i` of an angle is the player index used by `bf_ally` and
+/// `bf_flank`: the land at index `i` belongs to player `i + 1`, indices `0` and
+/// `1` are on team `1`, and indices `2` and `3` are on team `2`. Hence each
+/// player's ally is beside them on one side and their enemy flank is beside
+/// them on the other side.
 pub fn land_centers(theta: f64) -> [f64; 4] {
     let quarterturn = TAU / 4.0;
     [
@@ -718,7 +1197,7 @@ pub fn bf_circle_land_coordinates(num_directions: u32, radius: f64) -> Vec<[Land
     debug_assert!(num_directions > 0 && num_directions <= 100);
     debug_assert!(100 % num_directions == 0);
     debug_assert!(radius > 0.0);
-    use utils::{cos, round, sin};
+    use utils::{cos, round_clamped, sin};
     let increment = TAU / num_directions as f64;
     let mut cooridinates = vec![];
     for d in 0..num_directions {
@@ -728,8 +1207,8 @@ pub fn bf_circle_land_coordinates(num_directions: u32, radius: f64) -> Vec<[Land
             .iter()
             .map(|&theta| {
                 (
-                    round(50.0 + radius * cos(theta)) as i32,
-                    round(50.0 + radius * sin(theta)) as i32,
+                    round_clamped(50.0 + radius * cos(theta), 0, 100) as i32,
+                    round_clamped(50.0 + radius * sin(theta), 0, 100) as i32,
                 )
             })
             .collect();
@@ -738,25 +1217,33 @@ pub fn bf_circle_land_coordinates(num_directions: u32, radius: f64) -> Vec<[Land
     cooridinates
 }
 
-/// Returns a list of 8 ponds surrounding `(x, y)`.
+/// The default distance from a player land to its ponds on Black Forest.
+pub const BF_POND_DISTANCE: f64 = 14.0;
+
+/// The default number of ponds surrounding a player land on Black Forest,
+/// before the pond on the road to the ally is removed.
+pub const BF_POND_COUNT: usize = 8;
+
+/// Returns a list of `pond_count` ponds equally spaced on the circle of radius
+/// `pond_distance` surrounding `(x, y)`.
 /// The returning ponds are not in any specified order.
-pub fn bf_pond_centers((x, y): LandPoint, pond_distance: f64) -> Vec<LandPoint> {
+pub fn bf_pond_centers((x, y): LandPoint, pond_distance: f64, pond_count: usize) -> Vec<LandPoint> {
     debug_assert!(pond_distance > 0.0);
-    use utils::{cos, round, sin};
-    let increment = TAU / 8.0;
+    use utils::{cos, round_clamped, sin};
+    let increment = TAU / pond_count as f64;
     let mut points = vec![];
-    for d in 0..=7 {
+    for d in 0..pond_count {
         let theta = d as f64 * increment;
-        let pond_x = round(x as f64 + pond_distance * cos(theta));
-        let pond_y = round(y as f64 + pond_distance * sin(theta));
+        let pond_x = round_clamped(x as f64 + pond_distance * cos(theta), 0, 100);
+        let pond_y = round_clamped(y as f64 + pond_distance * sin(theta), 0, 100);
         points.push((pond_x as i32, pond_y as i32));
     }
-    vec![
-        points[0], points[1], points[2], points[3], points[4], points[5], points[6], points[7],
-    ]
+    points
 }
 
-/// Returns the index of the ally of the player at index `i`.
+/// Returns the index of the ally of the player at index `i`, using the player
+/// indices of `land_centers`. Index `1` returns itself rather than its ally
+/// `0`, and the output of `#BFLANDS` depends on it.
 /// Requires `0 <= i <= 3`.
 pub fn bf_ally(i: usize) -> usize {
     match i {
@@ -769,9 +1256,9 @@ pub fn bf_ally(i: usize) -> usize {
 }
 
 /// Removes the pond closest to `(x, y)`.
-/// Requires `ponds.len() == 8`.
-pub fn remove_road_pond(ponds: &mut Vec<LandPoint>, (x, y): LandPoint) {
-    debug_assert!(ponds.len() == 8);
+/// Requires `ponds.len() == pond_count` and `pond_count >= 1`.
+pub fn remove_road_pond(ponds: &mut Vec<LandPoint>, (x, y): LandPoint, pond_count: usize) {
+    debug_assert!(pond_count >= 1 && ponds.len() == pond_count);
     ponds.sort_by_key(|(pond_x, pond_y)| {
         let (dx, dy) = (pond_x - x, pond_y - y);
         -(dx * dx + dy * dy)
@@ -779,7 +1266,9 @@ pub fn remove_road_pond(ponds: &mut Vec<LandPoint>, (x, y): LandPoint) {
     ponds.pop();
 }
 
-/// Returns the index of the enemy flank of the player at index `i`.
+/// Returns the index of the enemy flank of the player at index `i`, that is,
+/// the enemy adjacent to the player, using the player indices of
+/// `land_centers`.
 /// Requires `0 <= i <= 3`.
 pub fn bf_flank(i: usize) -> usize {
     debug_assert!(i <= 3);
@@ -787,62 +1276,162 @@ pub fn bf_flank(i: usize) -> usize {
 }
 
 /// Sorts the ponds by distance to `(x, y)`.
-/// Requires `ponds.len() == 7`.
-pub fn sort_ponds(ponds: &mut Vec<LandPoint>, (x, y): LandPoint) {
-    debug_assert!(ponds.len() == 7);
+/// Requires `ponds.len() == pond_count - 1`, the ponds left after the road pond
+/// is removed.
+pub fn sort_ponds(ponds: &mut [LandPoint], (x, y): LandPoint, pond_count: usize) {
+    debug_assert!(ponds.len() + 1 == pond_count);
     ponds.sort_by_key(|(pond_x, pond_y)| {
         let (dx, dy) = (pond_x - x, pond_y - y);
         dx * dx + dy * dy
     })
 }
 
-/// Returns the land generation code for player lands and ponds.
-/// Requires `1 <= num_directions <= 100` and `100` is divisible by `num_directions`.
+/// Returns the `land_id` of pond `pond` in the given `zone` on Black Forest.
+///
+/// The id is the number `2{zone}{pond}`, that is, `200 + 10 * zone + pond`.
+/// Hence every pond has a distinct id in `210..=249`, no pond shares an id
+/// with a player land, and the ponds of a zone may be referenced individually.
+/// Requires `1 <= zone <= 4` and `pond < 10`.
+fn bf_pond_land_id(zone: usize, pond: usize) -> usize {
+    debug_assert!((1..=4).contains(&zone) && pond < 10);
+    200 + 10 * zone + pond
+}
+
+/// Returns the player lands and ponds for each of the `directions`, where the
+/// lands of direction `d` are at index `d`. Each land is surrounded by
+/// `pond_count` ponds at distance `pond_distance`, less the pond on the road
+/// to the ally.
+/// Requires `100` is divisible by `directions.count()`.
 /// Requires `radius > 0.0` and the `radius` produces points within `0.0`
 /// and `100.0` for both lands and ponds.
-pub fn bf_lands_2(num_directions: u32, radius: f64) -> Vec<String> {
-    debug_assert!(num_directions > 0 && num_directions <= 100);
-    debug_assert!(100 % num_directions == 0);
+/// Requires `pond_distance > 0.0` and `1 <= pond_count <= 10`, so the pond
+/// `land_id`s are distinct.
+pub fn bf_land_specs(
+    directions: Directions,
+    radius: f64,
+    pond_distance: f64,
+    pond_count: usize,
+) -> Vec<Vec<LandSpec>> {
+    debug_assert!(100 % directions.count() == 0);
     debug_assert!(radius > 0.0);
-    let mut lines = vec![];
-    for (d, player_lands) in bf_circle_land_coordinates(num_directions, radius)
-        .iter()
-        .enumerate()
-    {
-        let start = if d == 0 { "if" } else { "elseif" };
-        lines.push(format!("{start} DIRECTION{d}"));
+    debug_assert!(
+        (1..=10).contains(&pond_count),
+        "Invalid pond count {pond_count}."
+    );
+    let mut specs = vec![];
+    for player_lands in bf_circle_land_coordinates(directions.count(), radius) {
+        let mut lands = vec![];
         for i in 0..=3 {
             let (x, y) = player_lands[i];
             let team = if i <= 1 { 1 } else { 2 };
             let player = i + 1;
             let zone = i + 1;
-            let pos = rnd_pos(x, y);
-            let mut ponds: Vec<LandPoint> = bf_pond_centers((x, y), 14.0);
+            let mut ponds: Vec<LandPoint> = bf_pond_centers((x, y), pond_distance, pond_count);
             let ally = bf_ally(i);
-            remove_road_pond(&mut ponds, player_lands[ally]);
+            remove_road_pond(&mut ponds, player_lands[ally], pond_count);
             let enemy = bf_flank(i);
-            sort_ponds(&mut ponds, player_lands[enemy]);
-            lines.push(format!(
-                "create_land {{\n{pos}\nassign_to AT_TEAM {team} 0 0\nzone {zone}\nterrain_type BASE_TERRAIN\nnumber_of_tiles 3815\nbase_size 7\nother_zone_avoidance_distance 6\n}}"
-            ));
+            sort_ponds(&mut ponds, player_lands[enemy], pond_count);
+            let mut land = LandSpec::new((x, y), "BASE_TERRAIN", 7, 3815);
+            land.position = rnd_range(x, y);
+            land.assign_to = Some(Assignment::Team(team));
+            land.zone = Some(zone);
+            land.other_zone_avoidance_distance = Some(6);
+            lands.push(land);
             for (i, &(pond_x, pond_y)) in ponds.iter().enumerate() {
-                lines.push(format!("if POND_{player}_{i}"));
-                let pond_pos = rnd_pos(pond_x, pond_y);
-                lines.push(format!(
-                    "create_land {{\n{pond_pos}\nzone {zone}\nland_id 2{zone}\nterrain_type WATER\nbase_size 2\nnumber_of_tiles rnd(80,90)\n}}"
-                ));
-                lines.push(String::from("endif"));
+                let mut pond = LandSpec::new((pond_x, pond_y), "WATER", 2, 0);
+                pond.position = rnd_range(pond_x, pond_y);
+                pond.tiles = (80, 90);
+                pond.zone = Some(zone);
+                pond.land_id = Some(bf_pond_land_id(zone, i));
+                pond.condition = Some(format!("POND_{player}_{i}"));
+                lands.push(pond);
             }
         }
+        specs.push(lands);
     }
-    lines.push(String::from("endif"));
-    lines
+    specs
+}
+
+/// Returns the land generation code for player lands and ponds for each of
+/// the `directions`, with the ponds given by `pond_distance` and `pond_count`
+/// as in `bf_land_specs`.
+/// Requires `100` is divisible by `directions.count()`.
+/// Requires `radius > 0.0` and the `radius` produces points within `0.0`
+/// and `100.0` for both lands and ponds.
+pub fn bf_lands_2(
+    directions: Directions,
+    radius: f64,
+    pond_distance: f64,
+    pond_count: usize,
+) -> Vec<String> {
+    let specs = bf_land_specs(directions, radius, pond_distance, pond_count);
+    directions.branches(|d| {
+        specs[d as usize]
+            .iter()
+            .flat_map(LandSpec::to_lines)
+            .collect()
+    })
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    /// Tests that a fill land is sized by its `land_percent`.
+    #[test]
+    fn fill_land_percent() {
+        let expected = vec![
+            "create_land {",
+            "land_position 50 50",
+            "terrain_type GRASS",
+            "base_size 0",
+            "land_percent 80",
+            "}",
+        ];
+        assert_eq!(expected, fill_land("GRASS", 80));
+        assert_eq!("land_percent 100", fill_land("FOREST", 100)[4]);
+    }
+
+    /// Tests that a fill land with a percent above `100` is rejected.
+    #[test]
+    #[should_panic(expected = "Land percent 101 is not in 0..=100.")]
+    fn fill_land_bad_percent() {
+        fill_land("GRASS", 101);
+    }
+
+    /// Tests that a ring layout clears the center before placing each player
+    /// on the ring.
+    #[test]
+    fn ring_players_layout() {
+        let lines = ring_players(4, 30, 10);
+        assert_eq!(
+            vec![
+                "create_land {",
+                "land_position 50 50",
+                "terrain_type CENTER_TERRAIN",
+                "base_size 10",
+                "number_of_tiles 0",
+                "}",
+            ],
+            lines[..6]
+        );
+        let positions: Vec<&str> = lines
+            .iter()
+            .filter_map(|line| line.strip_prefix("land_position "))
+            .collect();
+        assert_eq!(vec!["50 50", "80 50", "50 80", "20 50", "50 20"], positions);
+        assert_eq!("assign_to_player 4", lines[lines.len() - 2]);
+    }
+
+    /// Tests that a ring whose player lands reach the cleared center is
+    /// rejected.
+    #[test]
+    #[should_panic(expected = "Ring radius 22 does not clear the center of radius 10.")]
+    fn ring_players_inside_center() {
+        assert!(ring_clears_center(23, 10));
+        ring_players(2, 22, 10);
+    }
+
     /// Tests the opponent probability calculations.
     #[test]
     fn test_probs() {
@@ -862,4 +1451,325 @@ mod tests {
 
         assert_eq!(16, opponent_probability(Slot(11), Slot(0)));
     }
+
+    /// Tests that every slot's position maps back to the slot, and that other
+    /// positions do not map to a slot.
+    #[test]
+    fn position_slots() {
+        for i in 0..NUM_SLOTS {
+            assert_eq!(Some(Slot(i)), position_to_slot(&slot_to_position(Slot(i))));
+        }
+        assert_eq!(
+            Some(Slot(0)),
+            position_to_slot(" land_position rnd(25,34)  rnd(17,25)")
+        );
+        assert_eq!(
+            None,
+            position_to_slot("land_position rnd(25,34) rnd(17,26)")
+        );
+        assert_eq!(None, position_to_slot("land_position 50 50"));
+        assert_eq!(
+            None,
+            position_to_slot("land_position rnd(25,34) rnd(17,25) x")
+        );
+        assert_eq!(None, position_to_slot("base_size rnd(25,34) rnd(17,25)"));
+    }
+
+    /// Tests that the P2 label percentages sum to 100 and match the opponent
+    /// probabilities of every branch of `p2_position`.
+    #[test]
+    fn p2_label_probabilities() {
+        let probs = p2_position_probabilities();
+        assert_eq!([10, 16, 16, 16, 16, 16, 10], probs);
+        assert_eq!(100, probs.iter().sum::<u32>());
+        for i in 0..NUM_SLOTS {
+            for (j, &p) in probs.iter().enumerate() {
+                let t = Slot((i + P2_POS_OFFSET + j) % NUM_SLOTS);
+                assert_eq!(p, opponent_probability(Slot(i), t), "({i}, {j})");
+            }
+        }
+        let labels = define_labels();
+        assert!(labels.contains(&String::from("percent_chance 10 #define P2_POS_0")));
+        assert!(labels.contains(&String::from("percent_chance 16 #define P2_POS_3")));
+    }
+
+    /// Tests the `land_percent` and border lines of a land.
+    #[test]
+    fn land_spec_borders() {
+        let land = LandSpec::new((50, 50), "GRASS", 2, 10)
+            .with_border(Border::Bottom, 20)
+            .with_border(Border::Left, 5)
+            .with_border_fuzziness(30)
+            .with_land_percent(40);
+        assert_eq!(
+            vec![
+                "create_land {",
+                "land_position 50 50",
+                "terrain_type GRASS",
+                "base_size 2",
+                "land_percent 40",
+                "border_fuzziness 30",
+                "left_border 5",
+                "bottom_border 20",
+                "}",
+            ],
+            land.to_lines()
+        );
+        let land = LandSpec::new((50, 50), "GRASS", 2, 10);
+        assert!(land
+            .to_lines()
+            .contains(&String::from("number_of_tiles 10")));
+    }
+
+    /// Tests that a land percent above 100 is rejected.
+    #[test]
+    #[should_panic(expected = "Land percent 101 is not in 0..=100.")]
+    fn land_percent_invalid() {
+        LandSpec::new((50, 50), "GRASS", 2, 10).with_land_percent(101);
+    }
+
+    /// Tests that a lake `land_id` is appended inside each lake's command.
+    #[test]
+    fn four_seasons_lake_land_id() {
+        let lines = four_seasons_lakes(None);
+        assert!(lines.iter().all(|line| !line.contains("land_id")));
+        let lines = four_seasons_lakes(Some(30));
+        assert_eq!(
+            "create_land { land_position 0 0 terrain_type GRASS_WATER_PLACEHOLDER base_size 5 land_percent 100 border_fuzziness 15 right_border 86 bottom_border 86 land_id 30 }",
+            lines[2]
+        );
+        let lakes = lines.iter().filter(|line| line.starts_with("create_land"));
+        assert!(lakes.clone().all(|line| line.ends_with(" land_id 30 }")));
+        assert_eq!(16, lakes.count());
+    }
+
+    /// Tests that the default divider is a single-tile cross, that a divider of
+    /// thickness `3` is `5` tiles wide, and that thick dividers stay within the
+    /// map.
+    #[test]
+    fn four_seasons_divider_thickness() {
+        let lines = four_seasons_lands(FOUR_SEASONS_DIVIDER_TERRAIN, 1);
+        assert_eq!(201, lines.len());
+        assert_eq!(
+            "create_land { land_position 0 50 base_size 1 number_of_tiles 0 terrain_type DLC_ROCK }",
+            lines[0]
+        );
+        let lines = four_seasons_lands("WATER", 3);
+        assert_eq!(10 * 101 - 5 * 5, lines.len());
+        let rows: std::collections::HashSet<String> = land_positions(&lines[..5 * 101])
+            .iter()
+            .map(|position| position.split(' ').nth(1).unwrap().to_string())
+            .collect();
+        assert_eq!(5, rows.len());
+        assert_eq!(101 * 101, four_seasons_lands("WATER", 60).len());
+    }
+
+    /// Returns the `x y` arguments of each `land_position` in `lines`.
+    fn land_positions(lines: &[String]) -> Vec<String> {
+        lines
+            .iter()
+            .flat_map(|line| line.split("land_position ").skip(1))
+            .map(|rest| {
+                rest.split_whitespace()
+                    .take(2)
+                    .collect::<Vec<_>>()
+                    .join(" ")
+            })
+            .collect()
+    }
+
+    /// Tests that no position of the ZeSnake borders or the Four Seasons
+    /// divider has more than one land.
+    #[test]
+    fn border_positions_distinct() {
+        for lines in [snake_borders(), four_seasons_lands("WATER", 3)] {
+            let positions = land_positions(&lines);
+            let distinct: std::collections::HashSet<_> = positions.iter().collect();
+            assert_eq!(positions.len(), distinct.len());
+        }
+        assert_eq!(4 * 100, snake_borders().len());
+    }
+
+    /// Tests that the wave of Ze Snake uses the given terrain and width, and
+    /// not the terrain of the border.
+    #[test]
+    fn snake_wave_terrain() {
+        let lines = snake_lands(Directions::new(1), "DLC_WATER4", 3, 4.0, 0.5);
+        assert!(lines.iter().any(|line| line == "terrain_type DLC_WATER4"));
+        assert!(lines.iter().all(|line| !line.contains("SHALLOW")));
+        assert!(lines.iter().any(|line| line == "base_size 3"));
+    }
+
+    /// Tests the lines of a land with every attribute set.
+    #[test]
+    fn land_spec_lines() {
+        let mut land = LandSpec::new((10, 20), "WATER", 2, 0);
+        land.position.0 = (9, 11);
+        land.tiles = (80, 90);
+        land.zone = Some(1);
+        land.land_id = Some(21);
+        land.assign_to = Some(Assignment::Team(1));
+        land.clumping_factor = Some(40);
+        land.other_zone_avoidance_distance = Some(6);
+        land.condition = Some(String::from("POND_1_0"));
+        assert_eq!(
+            vec![
+                "if POND_1_0",
+                "create_land {",
+                "land_position rnd(9,11) 20",
+                "terrain_type WATER",
+                "base_size 2",
+                "number_of_tiles rnd(80,90)",
+                "clumping_factor 40",
+                "zone 1",
+                "land_id 21",
+                "assign_to AT_TEAM 1 0 0",
+                "other_zone_avoidance_distance 6",
+                "}",
+                "endif",
+            ],
+            land.to_lines()
+        );
+    }
+
+    /// Tests that the direction labels match the branches consuming them.
+    #[test]
+    fn direction_labels_lockstep() {
+        let directions = Directions::new(8);
+        let labels = direction_labels(directions);
+        assert_eq!(10, labels.len());
+        assert_eq!("percent_chance 13 #define DIRECTION0", labels[1]);
+        assert_eq!("percent_chance 12 #define DIRECTION7", labels[8]);
+        let lines = snake_lands(
+            directions,
+            SNAKE_WAVE_TERRAIN,
+            SNAKE_WAVE_BASE_SIZE,
+            SNAKE_WAVE_AMPLITUDE,
+            SNAKE_WAVE_FREQUENCY,
+        );
+        let branches = lines.iter().filter(|line| line.contains("DIRECTION"));
+        assert_eq!(8, branches.count());
+        assert_eq!(
+            "elseif DIRECTION7",
+            lines
+                .iter()
+                .rfind(|line| line.contains("DIRECTION"))
+                .unwrap()
+        );
+        assert_eq!(
+            direction_labels(Directions::default())[1..=100],
+            (0..100)
+                .map(|i| format!("percent_chance 1 #define DIRECTION{i}"))
+                .collect::<Vec<_>>()
+        );
+    }
+
+    /// Tests the syntax of the player and team assignment lines.
+    #[test]
+    fn assignment_lines() {
+        assert_eq!("assign_to_player 3", assign_to_player_line(3));
+        assert_eq!("assign_to AT_TEAM 2 0 0", assign_to_team_line(2));
+        assert!(arena_circle_gaps(ARENA_FOREST_TOLERANCE)
+            .iter()
+            .any(|line| line.contains(" assign_to AT_TEAM 2 0 0 ")));
+    }
+
+    /// Tests that a larger forest tolerance leaves fewer forests on Arena.
+    #[test]
+    fn arena_forest_tolerance() {
+        for d in [0, 12, 24] {
+            let default = arena_forest_lines_direction(d, ARENA_FOREST_TOLERANCE);
+            let wider = arena_forest_lines_direction(d, 2.0 * ARENA_FOREST_TOLERANCE);
+            assert!(wider.len() < default.len());
+        }
+    }
+
+    /// Tests that Arena places each player's land, for any number of players.
+    #[test]
+    fn arena_lands_player_count() {
+        let lines = arena_lands_players(Directions::new(6), 3);
+        let players: Vec<_> = lines
+            .iter()
+            .filter(|line| line.starts_with("assign_to_player"))
+            .collect();
+        assert_eq!(6 * 3, players.len());
+        assert_eq!(
+            vec![
+                "assign_to_player 1",
+                "assign_to_player 2",
+                "assign_to_player 3"
+            ],
+            players[..3]
+        );
+        assert_eq!(
+            arena_lands(Directions::new(4)),
+            arena_lands_players(Directions::new(4), 4)
+        );
+    }
+
+    /// Tests that allies share a team, that flanks are on the other team, and
+    /// that both are adjacent to the player, except for the ally of index `1`.
+    #[test]
+    fn bf_team_indices() {
+        let team = |i: usize| if i <= 1 { 1 } else { 2 };
+        let adjacent = |i: usize, j: usize| (i + 4 - j) % 2 == 1;
+        for i in 0..4 {
+            let (ally, flank) = (bf_ally(i), bf_flank(i));
+            assert_eq!(i, bf_flank(flank));
+            assert_eq!(team(i), team(ally));
+            assert_ne!(team(i), team(flank));
+            assert!(adjacent(i, flank));
+            if i != 1 {
+                assert!(adjacent(i, ally));
+            }
+            if i >= 2 {
+                assert_eq!(i, bf_ally(ally));
+            }
+        }
+        assert_eq!(1, bf_ally(1));
+    }
+
+    /// Tests that the ponds of each Black Forest direction have distinct ids.
+    #[test]
+    fn bf_pond_land_ids_distinct() {
+        for lands in bf_land_specs(Directions::default(), 36.0, BF_POND_DISTANCE, BF_POND_COUNT) {
+            let mut ids: Vec<usize> = lands.iter().filter_map(|land| land.land_id).collect();
+            assert_eq!(28, ids.len());
+            ids.sort_unstable();
+            ids.dedup();
+            assert_eq!(28, ids.len());
+        }
+    }
+
+    /// Tests that the Black Forest lands of each direction include the four
+    /// player lands and seven ponds per player.
+    #[test]
+    fn bf_land_spec_counts() {
+        let directions =
+            bf_land_specs(Directions::default(), 36.0, BF_POND_DISTANCE, BF_POND_COUNT);
+        assert_eq!(100, directions.len());
+        for lands in directions {
+            assert_eq!(32, lands.len());
+            let players = lands.iter().filter(|land| land.assign_to.is_some());
+            assert_eq!(4, players.count());
+        }
+    }
+
+    /// Tests that the ponds follow the given count and distance.
+    #[test]
+    fn bf_pond_count_distance() {
+        for lands in bf_land_specs(Directions::new(4), 36.0, 10.0, 5) {
+            // Four player lands, each with five ponds less the road pond.
+            assert_eq!(4 + 4 * 4, lands.len());
+            for player in lands.iter().filter(|land| land.assign_to.is_some()) {
+                let ((x, _), (y, _)) = player.position;
+                let ponds = lands.iter().filter(|land| land.zone == player.zone);
+                for pond in ponds.filter(|land| land.land_id.is_some()) {
+                    let ((px, _), (py, _)) = pond.position;
+                    let distance = (((px - x).pow(2) + (py - y).pow(2)) as f64).sqrt();
+                    assert!((distance - 10.0).abs() <= 3.0, "{distance}");
+                }
+            }
+        }
+    }
 }
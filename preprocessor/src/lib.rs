@@ -1,17 +1,60 @@
 //! Library of helper functions for the TTL2 map preprocessor.
 
 use std::{
-    collections::{HashMap, VecDeque},
-    fs::File,
-    io::{BufRead, BufReader, BufWriter, Write},
+    collections::{HashMap, HashSet, VecDeque},
+    io::{BufRead, Write},
+    ops::RangeInclusive,
 };
 
+/// Logs a message at `level` with the `log` crate if the `logging` feature is
+/// enabled. Otherwise, expands to nothing, so logging has no cost.
+macro_rules! log_event {
+    ($level:ident, $($arg:tt)+) => {
+        #[cfg(feature = "logging")]
+        log::$level!($($arg)+);
+    };
+}
+
+/// Reports a warning, logged with the `log` crate if the `logging` feature is
+/// enabled, or printed to stderr otherwise.
+macro_rules! warning {
+    ($($arg:tt)+) => {
+        #[cfg(feature = "logging")]
+        log::warn!($($arg)+);
+        #[cfg(not(feature = "logging"))]
+        eprintln!("Warning: {}", format_args!($($arg)+));
+    };
+}
+
+/// Reports information that was asked for, such as with `--verbose`, logged
+/// with the `log` crate at the info level if the `logging` feature is enabled,
+/// or printed to stderr otherwise.
+macro_rules! note {
+    ($($arg:tt)+) => {
+        log_event!(info, $($arg)+);
+        #[cfg(not(feature = "logging"))]
+        eprintln!($($arg)+);
+    };
+}
+
 mod actorgen;
 mod circlegen;
+mod error;
 mod landgen;
+mod pipeline;
 mod utils;
 
-/// Relative path to the directory of files that can be included.
+pub use circlegen::{circle_p2_offsets, circle_placement_feasible, full_layout, CircleLayout};
+pub use error::PreprocessError;
+pub use landgen::{
+    arena_centers, arena_circles_2v2_specs, arena_lands_players, bf_ally, bf_flank, bf_land_specs,
+    convert, land_centers, position_to_slot, snake_land_specs, Assignment, Border, Directions,
+    LandPoint, LandSpec, Rnd, Slot,
+};
+pub use pipeline::{Lint, Pass, Pipeline};
+pub use utils::{angle_in_range, deg_to_rad, rad_to_deg, RandomBlock};
+
+// Relative path to the directory of files that can be included.
 // const INCLUDE_MAPS: &str = "include_maps";
 
 // Process every `#include_drs` command from `lines`.
@@ -41,31 +84,223 @@ mod utils;
 //     Ok(output)
 // }
 
+/// A line of a map script, along with the number of the source line from
+/// which it originates. Lines produced by a pass, such as by expanding a macro
+/// or unrolling a repeat block, keep the number of the line that produced them.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Line {
+    /// The number of the source line, starting from `1`.
+    pub number: usize,
+    /// The text of the line.
+    pub text: String,
+}
+
+/// Returns `lines` with their texts replaced by the result of `f`, which must
+/// return one text for each text it is given, so each line keeps its number.
+fn map_texts(lines: Vec<Line>, f: impl FnOnce(Vec<String>) -> Vec<String>) -> Vec<Line> {
+    let (numbers, texts): (Vec<usize>, Vec<String>) = lines
+        .into_iter()
+        .map(|line| (line.number, line.text))
+        .unzip();
+    let texts = f(texts);
+    debug_assert_eq!(numbers.len(), texts.len());
+    numbers
+        .into_iter()
+        .zip(texts)
+        .map(|(number, text)| Line { number, text })
+        .collect()
+}
+
+/// Takes ownership of `lines` and returns them as `Line`s, numbered
+/// consecutively starting from `first`.
+fn number_lines(lines: Vec<String>, first: usize) -> Vec<Line> {
+    lines
+        .into_iter()
+        .enumerate()
+        .map(|(i, text)| Line {
+            number: first + i,
+            text,
+        })
+        .collect()
+}
+
+/// Returns whether `line` may precede the header, that is, whether `line` is
+/// blank or only comments, given the comment depth `depth` at its start.
+/// Updates `depth` to the comment depth at the end of `line`.
+fn is_preamble_line(line: &str, depth: &mut u32) -> bool {
+    let (text, d) = strip_line_comments(line, *depth);
+    *depth = d;
+    text.trim().is_empty()
+}
+
+/// Returns the index of the `#HEADER_START` line of `lines`, or `None` if the
+/// header is not present. Only blank lines and comments may precede the header.
+fn header_start(lines: &[String]) -> Option<usize> {
+    let mut depth = 0;
+    for (i, line) in lines.iter().enumerate() {
+        if depth == 0 && is_directive(line, "#HEADER_START") {
+            return Some(i);
+        }
+        if !is_preamble_line(line, &mut depth) {
+            return None;
+        }
+    }
+    None
+}
+
 /// Takes ownership of `lines`` and returns two vectors splitting `lines` into two components.
 /// The first is the text contained within the lines `#HEADER_START` and `#HEADER_END`,
-/// if present. The second is all lines after `#HEADER_END`.
+/// if present, preceded by the blank lines and comments before `#HEADER_START`.
+/// The second is all lines after `#HEADER_END`.
 /// If the header comments are not present, the first vector is empty and the
 /// second vector is `lines`, unmodified.
-fn collect_header_comment(lines: Vec<String>) -> (Vec<String>, Vec<String>) {
-    if lines.is_empty() || lines[0].trim().to_uppercase() != "#HEADER_START" {
-        return (vec![], lines);
+/// Returns an error if the header is started but never ended.
+fn collect_header_comment(
+    mut lines: Vec<String>,
+) -> Result<(Vec<String>, Vec<String>), PreprocessError> {
+    let Some(start) = header_start(&lines) else {
+        return Ok((vec![], lines));
+    };
+    let end = lines[start..]
+        .iter()
+        .position(|line| is_directive(line, "#HEADER_END"))
+        .ok_or(PreprocessError::UnterminatedHeader)?;
+    // Splits off the body, then drops the start and end delimiters.
+    let body = lines.split_off(start + end + 1);
+    lines.pop();
+    lines.remove(start);
+    Ok((lines, body))
+}
+
+/// Returns the key and value of the header metadata line `line`, which has the
+/// form `key: value`, where `key` is letters, digits, and underscores, or
+/// `None` if `line` is not such a line.
+fn header_metadata_line(line: &str) -> Option<(&str, &str)> {
+    let (key, value) = line.split_once(':')?;
+    let key = key.trim();
+    let is_key = !key.is_empty() && key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+    is_key.then(|| (key, value.trim()))
+}
+
+/// Removes the metadata lines from the `header` comment, returning the
+/// metadata's values by key and the remaining lines of the header.
+/// A metadata line has the form `key: value` outside of any comment, so the
+/// configuration of a map is kept with the map without appearing in its
+/// output. Lines inside of comments are never metadata.
+fn take_header_metadata(header: Vec<String>) -> (HashMap<String, String>, Vec<String>) {
+    let mut metadata = HashMap::new();
+    let mut lines = Vec::with_capacity(header.len());
+    let mut depth = 0;
+    for line in header {
+        let (text, d) = strip_line_comments(&line, depth);
+        depth = d;
+        match header_metadata_line(&text) {
+            Some((key, value)) => {
+                metadata.insert(key.to_string(), value.to_string());
+            }
+            None => lines.push(line),
+        }
     }
-    let n = lines.len();
-    let mut i = 1;
+    (metadata, lines)
+}
+
+/// Returns the value of the header metadata key `key` parsed as a `T`, or
+/// `default` if the key is not given. Returns an error if the value cannot be
+/// parsed.
+fn metadata_value<T: std::str::FromStr>(
+    metadata: &HashMap<String, String>,
+    key: &str,
+    default: T,
+) -> Result<T, PreprocessError> {
+    let Some(value) = metadata.get(key) else {
+        return Ok(default);
+    };
+    value.parse().map_err(|_| PreprocessError::BadHeaderValue {
+        key: key.to_string(),
+        value: value.clone(),
+    })
+}
+
+/// How the lines of the header comment are formatted in the output.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum HeaderFormat {
+    /// Keeps the header's lines verbatim.
+    #[default]
+    Verbatim,
+    /// Strips the trailing whitespace of each line.
+    Trimmed,
+    /// Strips the trailing whitespace of each line, expands the tabs of each
+    /// line's indentation, and removes the indentation common to every
+    /// nonblank line.
+    Reindented,
+}
+
+/// The number of columns of a tab when reindenting the header.
+const TAB_WIDTH: usize = 4;
+
+/// Returns `line` with the tabs of its indentation expanded to spaces, up to
+/// the next multiple of `TAB_WIDTH` columns.
+fn expand_indentation(line: &str) -> String {
+    let text = line.trim_start();
+    let mut columns = 0;
+    for c in line[..line.len() - text.len()].chars() {
+        columns = match c {
+            '\t' => (columns / TAB_WIDTH + 1) * TAB_WIDTH,
+            _ => columns + 1,
+        };
+    }
+    format!("{}{text}", " ".repeat(columns))
+}
+
+/// Returns the lines of `header` formatted as given by `format`. Blank lines
+/// are kept, but are emptied unless `format` is `HeaderFormat::Verbatim`.
+fn format_header(header: Vec<String>, format: HeaderFormat) -> Vec<String> {
+    let lines = header.into_iter();
+    match format {
+        HeaderFormat::Verbatim => lines.collect(),
+        HeaderFormat::Trimmed => lines.map(|line| line.trim_end().to_string()).collect(),
+        HeaderFormat::Reindented => {
+            let lines: Vec<String> = lines
+                .map(|line| expand_indentation(line.trim_end()))
+                .collect();
+            let indent = lines
+                .iter()
+                .filter(|line| !line.is_empty())
+                .map(|line| line.len() - line.trim_start().len())
+                .min()
+                .unwrap_or(0);
+            lines
+                .into_iter()
+                .map(|line| line.get(indent..).unwrap_or_default().to_string())
+                .collect()
+        }
+    }
+}
+
+/// Reads only the header comment of the map script in `src`, stopping after
+/// the `#HEADER_END` line. Returns the lines between `#HEADER_START` and
+/// `#HEADER_END`, preceded by the blank lines and comments before
+/// `#HEADER_START`, without applying any preprocessing steps, or an empty
+/// vector if the script does not have a header comment.
+pub fn read_header<R: BufRead>(src: &mut R) -> Result<Vec<String>, PreprocessError> {
     let mut header = vec![];
-    while i != n {
-        let line = &lines[i];
-        if line.trim().to_uppercase() == "#HEADER_END" {
-            let mut end = vec![];
-            for j in i + 1..n {
-                end.push(lines[j].clone());
-            }
-            return (header, end);
+    let mut depth = 0;
+    let mut lines = src.lines();
+    loop {
+        match lines.next().transpose()? {
+            Some(line) if depth == 0 && is_directive(&line, "#HEADER_START") => break,
+            Some(line) if is_preamble_line(&line, &mut depth) => header.push(line),
+            _ => return Ok(vec![]),
+        }
+    }
+    for line in lines {
+        let line = line?;
+        if is_directive(&line, "#HEADER_END") {
+            return Ok(header);
         }
-        header.push(line.clone());
-        i += 1;
+        header.push(line);
     }
-    panic!("Header comment never ends.")
+    Err(PreprocessError::UnterminatedHeader)
 }
 
 /// Strips comments from line `s`, where `i` is the index of the first
@@ -75,13 +310,22 @@ fn collect_header_comment(lines: Vec<String>) -> (Vec<String>, Vec<String>) {
 ///
 /// `depth` is the nested comment depth at the start of `s`.
 ///
+/// A comment separating two non-whitespace characters is replaced by a single
+/// space, so the text on either side of the comment is not joined together.
+///
 /// Returns `(t, d)`, where `t` is the line with comments stripped and `d` is
 /// the comment depth after stripping comments.
 fn strip_start_comment(s: &str, depth: u32, i: usize) -> (String, u32) {
     let front = if depth > 0 { "" } else { &s[..i] };
     let tail = &s[i + 2..];
     let (rec_str, rec_depth) = strip_line_comments(tail, depth + 1);
-    (format!("{}{}", front, rec_str), rec_depth)
+    let is_token = |c: char| !c.is_whitespace();
+    let delim = if front.ends_with(is_token) && rec_str.starts_with(is_token) {
+        " "
+    } else {
+        ""
+    };
+    (format!("{}{}{}", front, delim, rec_str), rec_depth)
 }
 
 /// Strips comments from line `s`, where `i` is the index of the first
@@ -135,15 +379,28 @@ fn strip_line_comments(s: &str, depth: u32) -> (String, u32) {
 
 /// Takes ownership of `lines` and returns an equivalent vector with all
 /// comments removed.
-fn strip_comments(lines: Vec<String>) -> Vec<String> {
+/// Returns an error if a comment is still open at the end of `lines`.
+fn strip_comments(lines: Vec<Line>) -> Result<Vec<Line>, PreprocessError> {
     let mut comment_depth = 0; // The depth of nested comments.
+    let mut comment_start = 0; // The line number where the open comment began.
     let mut output = Vec::with_capacity(lines.len());
-    for line in lines {
-        let (stripped, d) = strip_line_comments(&line, comment_depth);
-        output.push(stripped);
+    for Line { number, text } in lines {
+        let (stripped, d) = strip_line_comments(&text, comment_depth);
+        output.push(Line {
+            number,
+            text: stripped,
+        });
+        if comment_depth == 0 && d > 0 {
+            comment_start = number;
+        }
         comment_depth = d;
     }
-    output
+    if comment_depth > 0 {
+        return Err(PreprocessError::UnbalancedComment {
+            line: comment_start,
+        });
+    }
+    Ok(output)
 }
 
 /// Returns a string with the same contents as `s`, but with leading and
@@ -151,226 +408,1512 @@ fn strip_comments(lines: Vec<String>) -> Vec<String> {
 /// replaced with a single space. If `s` is all whitespace, the empty string
 /// is returned.
 fn condense_line_whitespace(s: &str) -> String {
-    s.trim()
-        .split_whitespace()
-        .enumerate()
-        .map(|(i, w)| format!("{}{}", if i > 0 { " " } else { "" }, w))
-        .collect()
+    let mut output = String::with_capacity(s.len());
+    for word in s.split_whitespace() {
+        if !output.is_empty() {
+            output.push(' ');
+        }
+        output.push_str(word);
+    }
+    output
+}
+
+/// Returns whether `line` is the directive `name`, ignoring case and the
+/// whitespace surrounding `line`.
+fn is_directive(line: &str, name: &str) -> bool {
+    line.trim().eq_ignore_ascii_case(name)
+}
+
+/// Returns the rest of `line` after the directive prefix `prefix`, or `None`
+/// if `line` does not begin with `prefix`. The prefix is matched ignoring case,
+/// and the whitespace surrounding `line` is ignored.
+fn strip_directive<'a>(line: &'a str, prefix: &str) -> Option<&'a str> {
+    let line = line.trim();
+    let start = line.get(..prefix.len())?;
+    start
+        .eq_ignore_ascii_case(prefix)
+        .then(|| &line[prefix.len()..])
 }
 
 /// Removes excess whitespace from each string of `lines`, and removes all blank
 /// lines. Condenses whitespace withing each line.
-fn condense_whitespace(lines: Vec<String>) -> Vec<String> {
+fn condense_whitespace(lines: Vec<Line>) -> Vec<Line> {
     lines
-        .iter()
-        .map(|line| condense_line_whitespace(&line))
-        .filter(|line| !line.is_empty())
+        .into_iter()
+        .map(|Line { number, text }| Line {
+            number,
+            text: condense_line_whitespace(&text),
+        })
+        .filter(|line| !line.text.is_empty())
+        .collect()
+}
+
+/// Whether debug-only lines are kept in the output.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum BuildMode {
+    /// Removes lines beginning with `#DEBUG `.
+    #[default]
+    Release,
+    /// Keeps lines beginning with `#DEBUG `, with the prefix removed.
+    Debug,
+}
+
+/// Returns the name and value of the directive `#alias NAME VALUE` in `line`,
+/// or `None` if `line` is not an alias directive. The directive's name is
+/// matched ignoring case. Returns an error if the name or value is missing.
+fn parse_alias(line: &Line) -> Result<Option<(&str, &str)>, PreprocessError> {
+    let text = line.text.trim();
+    let (directive, rest) = text.split_once(' ').unwrap_or((text, ""));
+    if !directive.eq_ignore_ascii_case("#ALIAS") {
+        return Ok(None);
+    }
+    match rest.split_once(' ') {
+        Some((name, value)) => Ok(Some((name, value))),
+        None => Err(PreprocessError::BadAlias {
+            line: line.number,
+            text: line.text.clone(),
+        }),
+    }
+}
+
+/// Returns `s` with every whole token rewritten by `rewrite`, which pushes the
+/// replacement of the token onto the output. A token is a maximal run of
+/// letters, digits, and underscores, so `PLAYER_TERRAIN_2` does not contain
+/// `PLAYER_TERRAIN`.
+fn rewrite_tokens(s: &str, rewrite: impl Fn(&str, &mut String)) -> String {
+    let is_token = |c: char| c.is_ascii_alphanumeric() || c == '_';
+    let mut output = String::with_capacity(s.len());
+    let mut rest = s;
+    while let Some(i) = rest.find(is_token) {
+        output.push_str(&rest[..i]);
+        rest = &rest[i..];
+        let j = rest.find(|c| !is_token(c)).unwrap_or(rest.len());
+        rewrite(&rest[..j], &mut output);
+        rest = &rest[j..];
+    }
+    output.push_str(rest);
+    output
+}
+
+/// Returns `s` with every whole token equal to an alias name replaced by the
+/// alias's value.
+fn substitute_line_aliases(s: &str, aliases: &HashMap<String, String>) -> String {
+    rewrite_tokens(s, |token, output| {
+        output.push_str(aliases.get(token).map_or(token, String::as_str))
+    })
+}
+
+/// Removes the `#alias NAME VALUE` directives from `lines` and replaces each
+/// whole token `NAME` with `VALUE` in the remaining lines.
+/// Returns an error if an alias is malformed or defined more than once.
+fn substitute_aliases(lines: Vec<Line>) -> Result<Vec<Line>, PreprocessError> {
+    let mut aliases = HashMap::new();
+    for line in &lines {
+        if let Some((name, value)) = parse_alias(line)? {
+            if aliases
+                .insert(name.to_string(), value.to_string())
+                .is_some()
+            {
+                return Err(PreprocessError::AliasRedefined {
+                    line: line.number,
+                    name: name.to_string(),
+                });
+            }
+        }
+    }
+    if aliases.is_empty() {
+        return Ok(lines);
+    }
+    let mut output = Vec::with_capacity(lines.len());
+    for line in lines {
+        if parse_alias(&line)?.is_none() {
+            output.push(Line {
+                number: line.number,
+                text: substitute_line_aliases(&line.text, &aliases),
+            });
+        }
+    }
+    Ok(output)
+}
+
+/// The stems of the labels defined by the generator macros. Each generated
+/// label is one of these stems followed by a number.
+const GENERATED_LABELS: [&str; 5] = [
+    "P1_POINT_",
+    "P1_SLOT_",
+    "P2_OFFSET_",
+    "P2_POS_",
+    "DIRECTION",
+];
+
+/// Returns whether `token` is a label defined by a generator macro.
+fn is_generated_label(token: &str) -> bool {
+    GENERATED_LABELS.iter().any(|stem| {
+        token
+            .strip_prefix(stem)
+            .is_some_and(|n| !n.is_empty() && n.bytes().all(|b| b.is_ascii_digit()))
+    })
+}
+
+/// Returns the prefix of the `#LABEL_SCOPE` or `#LABEL_SCOPE(PREFIX)`
+/// directive `line`, or `None` if `line` is not such a directive. The prefix
+/// is empty if it is not given.
+fn label_scope(line: &str) -> Option<&str> {
+    match strip_directive(line, "#LABEL_SCOPE")? {
+        "" => Some(""),
+        rest => rest.strip_prefix('(')?.strip_suffix(')').map(str::trim),
+    }
+}
+
+/// Renames the generated labels inside of each `#LABEL_SCOPE ...
+/// #END_LABEL_SCOPE` block of `lines`, so the random blocks of several
+/// generators do not interfere. Each label is prefixed with the block's
+/// `#LABEL_SCOPE(PREFIX)` argument, or with a fresh label from `next_label`
+/// if no prefix is given. Blocks may be nested, the innermost prefix is used.
+/// Returns an error if the blocks are unbalanced.
+fn scope_labels(lines: Vec<Line>) -> Result<Vec<Line>, PreprocessError> {
+    // Pairs of the opening line number and the prefix of each open block.
+    let mut scopes: Vec<(usize, String)> = vec![];
+    let mut label = None;
+    let mut output = Vec::with_capacity(lines.len());
+    for line in lines {
+        if let Some(prefix) = label_scope(&line.text) {
+            let prefix = match prefix {
+                "" => {
+                    let next = next_label(&label.as_deref());
+                    label = Some(next.clone());
+                    next
+                }
+                prefix => prefix.to_string(),
+            };
+            scopes.push((line.number, prefix));
+        } else if is_directive(&line.text, "#END_LABEL_SCOPE") {
+            scopes
+                .pop()
+                .ok_or(PreprocessError::UnexpectedEndLabelScope { line: line.number })?;
+        } else if let Some((_, prefix)) = scopes.last() {
+            let text = rewrite_tokens(&line.text, |token, output| {
+                if is_generated_label(token) {
+                    output.push_str(prefix);
+                    output.push('_');
+                }
+                output.push_str(token);
+            });
+            output.push(Line {
+                number: line.number,
+                text,
+            });
+        } else {
+            output.push(line);
+        }
+    }
+    match scopes.first() {
+        Some(&(line, _)) => Err(PreprocessError::UnterminatedLabelScope { line }),
+        None => Ok(output),
+    }
+}
+
+/// Returns whether the `#IF_PLAYERS` directive `line` holds for `players`
+/// players. The condition is a number `n`, optionally preceded by one of the
+/// operators `==`, `!=`, `<`, `<=`, `>`, or `>=`. A number without an operator
+/// is compared with `==`.
+/// Returns an error if the condition is malformed or `players` is `None`.
+fn player_condition(line: &Line, players: Option<u32>) -> Result<bool, PreprocessError> {
+    let condition = strip_directive(&line.text, "#IF_PLAYERS")
+        .unwrap_or_default()
+        .trim();
+    let error = || PreprocessError::BadPlayerCondition {
+        line: line.number,
+        text: line.text.clone(),
+    };
+    let i = condition
+        .find(|c: char| c.is_ascii_digit())
+        .ok_or_else(error)?;
+    let n = condition[i..].trim().parse::<u32>().map_err(|_| error())?;
+    let players = players.ok_or(PreprocessError::MissingPlayerCount { line: line.number })?;
+    match condition[..i].trim() {
+        "" | "==" => Ok(players == n),
+        "!=" => Ok(players != n),
+        "<" => Ok(players < n),
+        "<=" => Ok(players <= n),
+        ">" => Ok(players > n),
+        ">=" => Ok(players >= n),
+        _ => Err(error()),
+    }
+}
+
+/// An open `#IF_PLAYERS` block.
+struct PlayerCondition {
+    /// The number of the `#IF_PLAYERS` line that opens the block.
+    start: usize,
+    /// Whether the enclosing blocks are all selected.
+    parent: bool,
+    /// Whether the block's condition holds.
+    holds: bool,
+    /// Whether the block's `#ELSE` has been reached.
+    in_else: bool,
+}
+
+impl PlayerCondition {
+    /// Returns whether the lines currently in the block are selected.
+    fn selected(&self) -> bool {
+        self.parent && self.holds != self.in_else
+    }
+}
+
+/// Resolves the `#IF_PLAYERS cond ... #ELSE ... #ENDIF_PLAYERS` blocks of
+/// `lines` for `players` players, keeping the lines of the branches that hold
+/// and removing the directives. Blocks may be nested, and `#ELSE` is optional.
+/// Returns an error if a block is malformed or unterminated, or if `lines`
+/// has a block but `players` is `None`.
+fn select_player_branches(
+    lines: Vec<Line>,
+    players: Option<u32>,
+) -> Result<Vec<Line>, PreprocessError> {
+    let mut blocks: Vec<PlayerCondition> = vec![];
+    let mut output = Vec::with_capacity(lines.len());
+    for line in lines {
+        let selected = blocks.last().is_none_or(PlayerCondition::selected);
+        let directive = line.text.split_whitespace().next().unwrap_or_default();
+        let unexpected = || PreprocessError::UnexpectedPlayerDirective {
+            line: line.number,
+            text: line.text.clone(),
+        };
+        if directive.eq_ignore_ascii_case("#IF_PLAYERS") {
+            // Conditions of unselected blocks are not evaluated.
+            let holds = selected && player_condition(&line, players)?;
+            blocks.push(PlayerCondition {
+                start: line.number,
+                parent: selected,
+                holds,
+                in_else: false,
+            });
+        } else if is_directive(&line.text, "#ELSE") {
+            match blocks.last_mut() {
+                Some(block) if !block.in_else => block.in_else = true,
+                _ => return Err(unexpected()),
+            }
+        } else if is_directive(&line.text, "#ENDIF_PLAYERS") {
+            blocks.pop().ok_or_else(unexpected)?;
+        } else if selected {
+            output.push(line);
+        }
+    }
+    match blocks.first() {
+        Some(block) => Err(PreprocessError::UnterminatedPlayerCondition { line: block.start }),
+        None => Ok(output),
+    }
+}
+
+/// Returns `lines` with debug-only lines handled according to `mode`.
+/// A debug-only line begins with `#DEBUG `. In release mode such lines are
+/// removed, and in debug mode only their `#DEBUG ` prefix is removed.
+/// Leading whitespace before the prefix is ignored.
+fn filter_debug_lines(lines: Vec<Line>, mode: BuildMode) -> Vec<Line> {
+    lines
+        .into_iter()
+        .filter_map(
+            |Line { number, text }| match text.trim_start().strip_prefix("#DEBUG ") {
+                None => Some(Line { number, text }),
+                Some(_) if mode == BuildMode::Release => None,
+                Some(rest) => Some(Line {
+                    number,
+                    text: rest.to_string(),
+                }),
+            },
+        )
         .collect()
 }
 
+/// A function returning the lines of a macro's expansion, given the macro's
+/// arguments and its `line`, for which an error is returned if the arguments
+/// cannot be parsed. The number of arguments is the arity of the macro's entry
+/// in `MACROS`.
+type Expansion = fn(&[&str], &Line) -> Result<Vec<String>, PreprocessError>;
+
+/// Returns the `radius` and `angle` arguments of a circle macro. The `angle`
+/// is in degrees, or in turns if suffixed with `t`.
+fn circle_args(args: &[&str], line: &Line) -> Result<(f64, u32), PreprocessError> {
+    Ok((parse_arg(args[0], line)?, parse_angle(args[1], line)?))
+}
+
+/// Returns the `radius`, `angle`, and `inset` arguments of a square macro, as
+/// in `circle_args`. The `inset` is the distance in tiles from the edges of
+/// the map to the sides of the square of positions. It must be below `50`,
+/// since an `inset` of `50` shrinks the square to the single center point.
+fn inset_args(args: &[&str], line: &Line) -> Result<(f64, u32, u32), PreprocessError> {
+    let (radius, angle) = circle_args(args, line)?;
+    Ok((radius, angle, parse_in(args[2], line, 0..=49)?))
+}
+
+/// Returns an error for the macro `line` unless `feasible`, whether the macro
+/// can place p2 at least its angle from p1, so infeasible arguments are
+/// reported instead of panicking.
+fn check_placement(feasible: bool, line: &Line) -> Result<(), PreprocessError> {
+    match feasible {
+        true => Ok(()),
+        false => Err(PreprocessError::BadMacroArgs {
+            line: line.number,
+            text: line.text.clone(),
+        }),
+    }
+}
+
+/// Returns the `constant`, `count`, and `radius` arguments of an object macro.
+/// The `radius` may be omitted, defaulting to `0`.
+fn object_args<'a>(args: &[&'a str], line: &Line) -> Result<(&'a str, u32, u32), PreprocessError> {
+    let radius = match args.get(2) {
+        Some(radius) => parse_arg(radius, line)?,
+        None => 0,
+    };
+    Ok((args[0].trim(), parse_arg(args[1], line)?, radius))
+}
+
+/// Every macro, paired with the number of arguments it takes and the function
+/// that parses its arguments and expands it. A macro taking different numbers
+/// of arguments has an entry for each.
+const MACROS: &[(&str, usize, Expansion)] = &[
+    ("#POSITION_LABELS", 0, |_, _| Ok(landgen::define_labels())),
+    ("#POSITION_P1", 0, |_, _| Ok(landgen::p1_position())),
+    ("#POSITION_P2", 0, |_, _| Ok(landgen::p2_position())),
+    ("#SQUARE_AVOID_CLIFFS", 0, |_, _| {
+        Ok(circlegen::square_avoid_cliffs())
+    }),
+    ("#ROCKGEN", 0, |_, _| Ok(landgen::rock_border())),
+    ("#MKCONSTS", 0, |_, _| Ok(actorgen::make_constants())),
+    ("#SETPHATTR", 0, |_, _| {
+        Ok(actorgen::set_placeholder_attributes())
+    }),
+    ("#SETPHATTR4SEASONS", 0, |_, _| {
+        Ok(actorgen::set_placeholder_attributes_four_seasons())
+    }),
+    ("#TCCENTER", 0, |_, _| Ok(actorgen::tc_center())),
+    ("#TCBOXES", 0, |_, _| Ok(actorgen::tc_boxes())),
+    ("#TCMULTIBOXES", 0, |_, _| Ok(actorgen::tc_multiboxes())),
+    ("#VISION", 0, |_, _| Ok(actorgen::vision())),
+    ("#TC9VILS", 0, |_, _| Ok(actorgen::vils_9_tc())),
+    (
+        "#TC9VILSZEWALL",
+        0,
+        |_, _| Ok(actorgen::vils_9_tc_ze_wall()),
+    ),
+    ("#TCMULTI9VILS", 0, |_, _| Ok(actorgen::multi_vils_9_tc())),
+    ("#HOUSEGAP3", 0, |_, _| Ok(actorgen::house_gap_3())),
+    ("#MULTIHOUSES", 0, |_, _| Ok(actorgen::multi_houses())),
+    ("#HUTGAP3", 0, |_, _| Ok(actorgen::hut_gap_3())),
+    (
+        "#STRAGGLER9VILS",
+        0,
+        |_, _| Ok(actorgen::vils_9_straggler()),
+    ),
+    ("#STRAGGLER9VILSSOCOTRA", 0, |_, _| {
+        Ok(actorgen::vils_9_straggler_socotra())
+    }),
+    ("#MULTISTRAGGLER9VILS", 0, |_, _| {
+        Ok(actorgen::multi_stragglers())
+    }),
+    ("#OBJECTS9VILS", 0, |_, _| Ok(actorgen::objects_9_vils())),
+    ("#OBJECTS9VILSZEWALL", 0, |_, _| {
+        Ok(actorgen::objects_9_vils_ze_wall(
+            actorgen::ObjectGrouping::PerObject,
+        ))
+    }),
+    ("#OBJECTS9VILSZEWALLBYLAND", 0, |_, _| {
+        Ok(actorgen::objects_9_vils_ze_wall(
+            actorgen::ObjectGrouping::PerLand,
+        ))
+    }),
+    ("#ARENACIRCLES2V2", 0, |_, _| {
+        Ok(landgen::arena_circles_2v2())
+    }),
+    ("#DIRLABELS", 0, |_, _| {
+        Ok(landgen::direction_labels(Directions::default()))
+    }),
+    ("#SNAKELANDS", 0, |_, _| {
+        Ok(landgen::snake_lands(
+            Directions::default(),
+            landgen::SNAKE_WAVE_TERRAIN,
+            landgen::SNAKE_WAVE_BASE_SIZE,
+            landgen::SNAKE_WAVE_AMPLITUDE,
+            landgen::SNAKE_WAVE_FREQUENCY,
+        ))
+    }),
+    ("#SNAKEBORDERS", 0, |_, _| Ok(landgen::snake_borders())),
+    ("#ARENALANDS", 0, |_, _| {
+        Ok(landgen::arena_lands(Directions::default()))
+    }),
+    ("#FOURSEASONSLANDS", 0, |_, _| {
+        Ok(landgen::four_seasons_lands(
+            landgen::FOUR_SEASONS_DIVIDER_TERRAIN,
+            landgen::FOUR_SEASONS_DIVIDER_THICKNESS,
+        ))
+    }),
+    ("#FOURSEASONSLAKES", 0, |_, _| {
+        Ok(landgen::four_seasons_lakes(None))
+    }),
+    ("#ARENA_CIRCLE_GAPS", 0, |_, _| {
+        Ok(landgen::arena_circle_gaps(landgen::ARENA_FOREST_TOLERANCE))
+    }),
+    ("#ARENA_PLAYERS_GAPS", 0, |_, _| {
+        Ok(landgen::arena_players_gaps())
+    }),
+    ("#BFLANDS", 0, |_, _| {
+        Ok(landgen::bf_lands_2(
+            Directions::default(),
+            36.0,
+            landgen::BF_POND_DISTANCE,
+            landgen::BF_POND_COUNT,
+        ))
+    }),
+    // `(land_id)`
+    ("#FOURSEASONSLAKES", 1, |args, line| {
+        Ok(landgen::four_seasons_lakes(Some(parse_arg(args[0], line)?)))
+    }),
+    // `(tolerance)`, in radians.
+    ("#ARENA_CIRCLE_GAPS", 1, |args, line| {
+        Ok(landgen::arena_circle_gaps(parse_arg(args[0], line)?))
+    }),
+    // `(radius,angle)`
+    ("#CIRCLE_LABELS", 2, |args, line| {
+        let (radius, angle) = circle_args(args, line)?;
+        check_placement(circlegen::circle_placement_feasible(radius, angle), line)?;
+        Ok(circlegen::list_random_definitions(radius, angle))
+    }),
+    ("#CIRCLE_SETUP", 2, |args, line| {
+        let (radius, angle) = circle_args(args, line)?;
+        check_placement(circlegen::circle_placement_feasible(radius, angle), line)?;
+        Ok(circlegen::full_layout(radius, angle).labels)
+    }),
+    ("#CIRCLE_POSITION_P1", 2, |args, line| {
+        let (radius, _) = circle_args(args, line)?;
+        Ok(circlegen::list_p1_positions(radius))
+    }),
+    ("#CIRCLE_POSITION_P2", 2, |args, line| {
+        let (radius, angle) = circle_args(args, line)?;
+        check_placement(circlegen::circle_placement_feasible(radius, angle), line)?;
+        Ok(circlegen::list_p2_positions(radius, angle))
+    }),
+    ("#SQUARE_LABELS", 2, |args, line| {
+        let (radius, angle) = circle_args(args, line)?;
+        check_placement(circlegen::square_placement_feasible(angle), line)?;
+        Ok(circlegen::list_square_definitions(radius, angle))
+    }),
+    ("#SQUARE_POSITION_P1", 2, |args, line| {
+        let (radius, _) = circle_args(args, line)?;
+        Ok(circlegen::square_p1_positions(radius))
+    }),
+    ("#SQUARE_POSITION_P2", 2, |args, line| {
+        let (radius, angle) = circle_args(args, line)?;
+        check_placement(circlegen::square_placement_feasible(angle), line)?;
+        Ok(circlegen::square_p2_positions(radius, angle))
+    }),
+    ("#MIGRA_LABELS", 2, |args, line| {
+        let (radius, angle) = circle_args(args, line)?;
+        check_placement(circlegen::migra_placement_feasible(angle), line)?;
+        Ok(circlegen::list_square_definitions_migra(radius, angle))
+    }),
+    ("#MIGRA_POSITION_P1", 2, |args, line| {
+        let (radius, _) = circle_args(args, line)?;
+        Ok(circlegen::square_p1_positions_migra(radius))
+    }),
+    ("#MIGRA_POSITION_P2", 2, |args, line| {
+        let (radius, angle) = circle_args(args, line)?;
+        check_placement(circlegen::migra_placement_feasible(angle), line)?;
+        Ok(circlegen::square_p2_positions_migra(radius, angle))
+    }),
+    // `(radius,angle,inset)`
+    ("#SQUARE_LABELS", 3, |args, line| {
+        let (radius, angle, inset) = inset_args(args, line)?;
+        check_placement(
+            circlegen::square_inset_placement_feasible(angle, inset),
+            line,
+        )?;
+        Ok(circlegen::list_square_definitions_inset(
+            radius, angle, inset,
+        ))
+    }),
+    ("#SQUARE_POSITION_P1", 3, |args, line| {
+        let (radius, _, inset) = inset_args(args, line)?;
+        Ok(circlegen::square_p1_positions_inset(radius, inset))
+    }),
+    ("#SQUARE_POSITION_P2", 3, |args, line| {
+        let (radius, angle, inset) = inset_args(args, line)?;
+        check_placement(
+            circlegen::square_inset_placement_feasible(angle, inset),
+            line,
+        )?;
+        Ok(circlegen::square_p2_positions_inset(radius, angle, inset))
+    }),
+    // `(terrain,thickness)`, where the `thickness` is at least `1`.
+    ("#FOURSEASONSLANDS", 2, |args, line| {
+        let thickness = parse_in(args[1], line, 1..=u32::MAX)?;
+        Ok(landgen::four_seasons_lands(args[0].trim(), thickness))
+    }),
+    // `(terrain,percent)`, where the `percent` is at most `100`.
+    ("#FILL", 2, |args, line| {
+        let percent = parse_in(args[1], line, 0..=100)?;
+        Ok(landgen::fill_land(args[0].trim(), percent))
+    }),
+    // `(count,distance)`
+    ("#VISION", 2, |args, line| {
+        let (count, distance) = (parse_arg(args[0], line)?, parse_arg(args[1], line)?);
+        Ok(actorgen::vision_with(count, distance))
+    }),
+    // `(constant,count,radius)`, where the `radius` may be omitted.
+    ("#CENTER_OBJECT", 2, |args, line| {
+        let (constant, count, radius) = object_args(args, line)?;
+        Ok(actorgen::center_object(constant, count, radius))
+    }),
+    ("#CENTER_OBJECT", 3, |args, line| {
+        let (constant, count, radius) = object_args(args, line)?;
+        Ok(actorgen::center_object(constant, count, radius))
+    }),
+    ("#RING_OBJECTS", 2, |args, line| {
+        let (constant, count, radius) = object_args(args, line)?;
+        Ok(circlegen::ring_object_positions(constant, count, radius))
+    }),
+    ("#RING_OBJECTS", 3, |args, line| {
+        let (constant, count, radius) = object_args(args, line)?;
+        Ok(circlegen::ring_object_positions(constant, count, radius))
+    }),
+    // `(directions,radius,pond_distance)`
+    ("#BFLANDS", 3, |args, line| {
+        let directions = Directions::new(parse_arg(args[0], line)?);
+        let (radius, distance) = (parse_arg(args[1], line)?, parse_arg(args[2], line)?);
+        Ok(landgen::bf_lands_2(
+            directions,
+            radius,
+            distance,
+            landgen::BF_POND_COUNT,
+        ))
+    }),
+    // `(target,attr,value)`, where the `target` is a single word and the
+    // `attr` the name of an attribute, such as `ATTR_HITPOINTS`.
+    ("#SET_ATTR", 3, |args, line| {
+        let target = parse_word(args[0], line, actorgen::is_effect_word)?;
+        let attr = parse_word(args[1], line, actorgen::is_attribute)?;
+        let value = parse_arg(args[2], line)?;
+        Ok(vec![actorgen::set_attribute(target, attr, value)])
+    }),
+    // `(num_players,radius,clear)`, where the player lands must stay outside
+    // of the center land.
+    ("#RINGPLAYERS", 3, |args, line| {
+        let num_players = parse_in(args[0], line, 1..=usize::MAX)?;
+        let (radius, clear) = (parse_in(args[1], line, 0..=50)?, parse_arg(args[2], line)?);
+        if !landgen::ring_clears_center(radius, clear) {
+            return Err(PreprocessError::BadMacroArgs {
+                line: line.number,
+                text: line.text.clone(),
+            });
+        }
+        Ok(landgen::ring_players(num_players, radius, clear))
+    }),
+    // `(name,x,y,radius)`
+    ("#ACTOR_AREA", 4, |args, line| {
+        let (x, y) = (parse_arg(args[1], line)?, parse_arg(args[2], line)?);
+        let radius = parse_arg(args[3], line)?;
+        Ok(vec![actorgen::create_actor_area(
+            args[0].trim(),
+            x,
+            y,
+            radius,
+        )])
+    }),
+    // `(terrain,base_size,amplitude,frequency)`
+    ("#SNAKELANDS", 4, |args, line| {
+        let base_size = parse_arg(args[1], line)?;
+        let (amplitude, frequency) = (parse_arg(args[2], line)?, parse_arg(args[3], line)?);
+        Ok(landgen::snake_lands(
+            Directions::default(),
+            args[0].trim(),
+            base_size,
+            amplitude,
+            frequency,
+        ))
+    }),
+];
+
+/// Returns the macro argument `arg` parsed as a `T`, ignoring surrounding
+/// whitespace. Returns an error for the macro `line` if `arg` cannot be parsed.
+fn parse_arg<T: std::str::FromStr>(arg: &str, line: &Line) -> Result<T, PreprocessError> {
+    arg.trim()
+        .parse()
+        .map_err(|_| PreprocessError::BadMacroArgs {
+            line: line.number,
+            text: line.text.clone(),
+        })
+}
+
+/// Returns the angle argument `arg` of a circle macro in degrees, ignoring
+/// surrounding whitespace. A bare number is in degrees. A number suffixed with
+/// `t` is a fraction of a full turn, such as `0.35t` for `126` degrees, and is
+/// rounded to the nearest degree. Returns an error for the macro `line` if
+/// `arg` is not a non-negative angle in either form.
+fn parse_angle(arg: &str, line: &Line) -> Result<u32, PreprocessError> {
+    let Some(turns) = arg.trim().strip_suffix('t') else {
+        return parse_arg(arg, line);
+    };
+    let turns: f64 = parse_arg(turns, line)?;
+    if !(turns.is_finite() && turns >= 0.0) {
+        return Err(PreprocessError::BadMacroArgs {
+            line: line.number,
+            text: line.text.clone(),
+        });
+    }
+    Ok((turns * 360.0).round() as u32)
+}
+
+/// Returns the macro argument `arg` parsed as a `T` as in `parse_arg`.
+/// Returns an error for the macro `line` if `arg` cannot be parsed or is not
+/// in `range`.
+fn parse_in<T: std::str::FromStr + PartialOrd>(
+    arg: &str,
+    line: &Line,
+    range: RangeInclusive<T>,
+) -> Result<T, PreprocessError> {
+    let value = parse_arg(arg, line)?;
+    if !range.contains(&value) {
+        return Err(PreprocessError::BadMacroArgs {
+            line: line.number,
+            text: line.text.clone(),
+        });
+    }
+    Ok(value)
+}
+
+/// Returns the macro argument `arg` without surrounding whitespace.
+/// Returns an error for the macro `line` if `arg` does not satisfy `is_valid`.
+fn parse_word<'a>(
+    arg: &'a str,
+    line: &Line,
+    is_valid: fn(&str) -> bool,
+) -> Result<&'a str, PreprocessError> {
+    let arg = arg.trim();
+    if is_valid(arg) {
+        Ok(arg)
+    } else {
+        Err(PreprocessError::BadMacroArgs {
+            line: line.number,
+            text: line.text.clone(),
+        })
+    }
+}
+
+/// Returns the expansion function of the macro named `name`, ignoring case,
+/// that takes `arity` arguments, or `None` if no such macro exists.
+fn find_macro(name: &str, arity: usize) -> Option<Expansion> {
+    MACROS
+        .iter()
+        .find(|&&(macro_name, n, _)| n == arity && macro_name.eq_ignore_ascii_case(name))
+        .map(|&(_, _, expand)| expand)
+}
+
+/// Returns the comma-separated arguments of the parenthesized list `list`, or
+/// no arguments if `list` is blank.
+fn macro_args(list: &str) -> Vec<&str> {
+    match list.trim().is_empty() {
+        true => vec![],
+        false => list.split(',').collect(),
+    }
+}
+
+/// Returns the numbers of arguments taken by the macro named `name`, in
+/// increasing order, or no numbers if no such macro exists.
+fn macro_arg_counts(name: &str) -> Vec<usize> {
+    let mut counts: Vec<usize> = MACROS
+        .iter()
+        .filter(|(macro_name, _, _)| macro_name.eq_ignore_ascii_case(name))
+        .map(|&(_, arity, _)| arity)
+        .collect();
+    counts.sort_unstable();
+    counts.dedup();
+    counts
+}
+
+/// Returns the line `source` unchanged if no macro is named `name`, so lines
+/// that only resemble macros are passed through. Returns an error if such a
+/// macro exists but does not take `found` arguments.
+fn check_arg_count(
+    name: &str,
+    found: usize,
+    source: &Line,
+) -> Result<Vec<String>, PreprocessError> {
+    let expected = macro_arg_counts(name);
+    if expected.is_empty() {
+        return Ok(vec![source.text.trim().to_string()]);
+    }
+    Err(PreprocessError::MacroArgCount {
+        line: source.number,
+        name: name.to_string(),
+        expected,
+        found,
+    })
+}
+
 /// Returns a vector of lines resulting from expanding macros in `line`.
 /// If `line` has no macros, then the vector contians a single element
 /// equivalent to the input `line`.
-fn expand_line(line: &str) -> Vec<String> {
-    let upper = &line.to_uppercase()[..];
-    if let Some(i) = line.find('(') {
-        let j = line.find(',');
-        if j.is_none() {
-            return vec![line.to_string()];
-        }
-        let j = j.unwrap();
-        let k = line.find(')');
-        if k.is_none() {
-            return vec![line.to_string()];
-        }
-        let k = k.unwrap();
-        let radius = (&line[i + 1..j]).parse::<f64>().unwrap();
-        let angle = (&line[j + 1..k]).parse::<u32>().unwrap();
-        match &upper[..i] {
-            "#CIRCLE_LABELS" => circlegen::list_random_definitions(radius, angle),
-            "#CIRCLE_POSITION_P1" => circlegen::list_p1_positions(radius),
-            "#CIRCLE_POSITION_P2" => circlegen::list_p2_positions(radius, angle),
-            "#SQUARE_LABELS" => circlegen::list_square_definitions(radius, angle),
-            "#SQUARE_POSITION_P1" => circlegen::square_p1_positions(radius),
-            "#SQUARE_POSITION_P2" => circlegen::square_p2_positions(radius, angle),
-            "#MIGRA_LABELS" => circlegen::list_square_definitions_migra(radius, angle),
-            "#MIGRA_POSITION_P1" => circlegen::square_p1_positions_migra(radius),
-            "#MIGRA_POSITION_P2" => circlegen::square_p2_positions_migra(radius, angle),
-            _ => vec![line.to_string()],
-        }
-    } else {
-        match upper {
-            "#POSITION_LABELS" => landgen::define_labels(),
-            "#POSITION_P1" => landgen::p1_position(),
-            "#POSITION_P2" => landgen::p2_position(),
-            "#SQUARE_AVOID_CLIFFS" => circlegen::square_avoid_cliffs(),
-            "#ROCKGEN" => landgen::rock_border(),
-            "#MKCONSTS" => actorgen::make_constants(),
-            "#SETPHATTR" => actorgen::set_placeholder_attributes(),
-            "#SETPHATTR4SEASONS" => actorgen::set_placeholder_attributes_four_seasons(),
-            "#TCCENTER" => actorgen::tc_center(),
-            "#TCBOXES" => actorgen::tc_boxes(),
-            "#TCMULTIBOXES" => actorgen::tc_multiboxes(),
-            "#VISION" => actorgen::vision(),
-            "#TC9VILS" => actorgen::vils_9_tc(),
-            "#TC9VILSZEWALL" => actorgen::vils_9_tc_ze_wall(),
-            "#TCMULTI9VILS" => actorgen::multi_vils_9_tc(),
-            "#HOUSEGAP3" => actorgen::house_gap_3(),
-            "#MULTIHOUSES" => actorgen::multi_houses(),
-            "#HUTGAP3" => actorgen::hut_gap_3(),
-            "#STRAGGLER9VILS" => actorgen::vils_9_straggler(),
-            "#STRAGGLER9VILSSOCOTRA" => actorgen::vils_9_straggler_socotra(),
-            "#MULTISTRAGGLER9VILS" => actorgen::multi_stragglers(),
-            "#OBJECTS9VILS" => actorgen::objects_9_vils(),
-            "#OBJECTS9VILSZEWALL" => actorgen::objects_9_vils_ze_wall(),
-            "#ARENACIRCLES2V2" => landgen::arena_circles_2v2(),
-            "#DIRLABELS" => landgen::direction_labels(),
-            "#SNAKELANDS" => landgen::snake_lands(),
-            "#SNAKEBORDERS" => landgen::snake_borders(),
-            "#ARENALANDS" => landgen::arena_lands(),
-            "#FOURSEASONSLANDS" => landgen::four_seasons_lands(),
-            "#FOURSEASONSLAKES" => landgen::four_seasons_lakes(),
-            "#ARENA_CIRCLE_GAPS" => landgen::arena_circle_gaps(),
-            "#ARENA_PLAYERS_GAPS" => landgen::arena_players_gaps(),
-            "#BFLANDS" => landgen::bf_lands_2(100, 36.0),
-            _ => vec![line.to_string()],
-        }
+///
+/// Macro names are matched case-insensitively.
+/// Returns an error if `line` is a macro whose arguments cannot be parsed, or
+/// that is given a number of arguments that it does not take.
+fn expand_line(source: &Line) -> Result<Vec<String>, PreprocessError> {
+    let line = source.text.trim();
+    // Every macro name begins with `#`, so other lines are never expanded.
+    if !line.starts_with('#') {
+        return Ok(vec![line.to_string()]);
+    }
+    let Some(i) = line.find('(') else {
+        return match find_macro(line, 0) {
+            Some(expand) => expand(&[], source),
+            None => Ok(vec![line.to_string()]),
+        };
+    };
+    let Some(k) = line.find(')') else {
+        return Ok(vec![line.to_string()]);
+    };
+    let name = &line[..i];
+    let args = macro_args(&line[i + 1..k]);
+    match find_macro(name, args.len()) {
+        Some(expand) => expand(&args, source),
+        None => check_arg_count(name, args.len(), source),
     }
 }
 
 /// Inserts preprocessor commands into `lines`.
 /// Commands include `#POSITION_LABELS`, `#POSITION_P1`, and `#POSITION_P2`.
-fn insert_macros(lines: Vec<String>) -> Vec<String> {
-    lines.iter().flat_map(|line| expand_line(&line)).collect()
+/// Returns an error if a macro's arguments cannot be parsed.
+/// Each line of an expansion keeps the number of the macro's line.
+fn insert_macros(lines: Vec<Line>) -> Result<Vec<Line>, PreprocessError> {
+    let count = lines.len();
+    let mut output = Vec::with_capacity(count);
+    for line in lines {
+        let number = line.number;
+        let expansion = expand_line(&line)?;
+        output.extend(number_expansion(expansion, number));
+    }
+    log_event!(
+        debug,
+        "Expanded macros, {count} lines became {}.",
+        output.len()
+    );
+    Ok(output)
+}
+
+/// Returns the lines of `expansion`, each numbered `number`.
+fn number_expansion(expansion: Vec<String>, number: usize) -> impl Iterator<Item = Line> {
+    expansion.into_iter().map(move |text| Line { number, text })
 }
 
 #[derive(Debug, PartialEq, Eq)]
 /// Represents a list of lines to be repeated.
 struct RepeatLines {
+    /// The number of the `#REPEAT` line that opens the block.
+    start: usize,
     /// The number of times to repeat the lines.
     count: usize,
+    /// The value of each repetition of a `#REPEAT_EACH` block, substituted for
+    /// `#V`. Empty for a `#REPEAT` block.
+    values: Vec<String>,
     /// The lines to repeat. Lines must have comments removed and have minimal
     /// whitespace.
-    lines: Vec<String>,
+    lines: Vec<Line>,
+    /// The numbers of the lines of the `{` of the blocks opened in this list
+    /// but not yet closed.
+    open_braces: Vec<usize>,
 }
 
 impl RepeatLines {
-    /// Returns a new `RepeatLines` struct that is initially empty without any
-    /// lines.
-    fn new(count: usize) -> Self {
-        return RepeatLines {
+    /// Returns a new `RepeatLines` struct opened on line `start` that is
+    /// initially empty without any lines.
+    fn new(start: usize, count: usize) -> Self {
+        RepeatLines {
+            start,
             count,
+            values: vec![],
             lines: vec![],
-        };
+            open_braces: vec![],
+        }
+    }
+
+    /// Returns a new `RepeatLines` struct opened on line `start` that repeats
+    /// once for each of the `values`, initially empty without any lines.
+    fn with_values(start: usize, values: Vec<String>) -> Self {
+        RepeatLines {
+            count: values.len(),
+            values,
+            ..RepeatLines::new(start, 0)
+        }
     }
 
     /// Adds `line` to the end of this list, taking ownership of it.
-    fn push_line(&mut self, line: String) {
-        self.lines.push(line.to_string());
+    /// Returns an error if `line` has a `}` closing a block opened before this
+    /// list.
+    fn push_line(&mut self, line: Line) -> Result<(), PreprocessError> {
+        for c in line.text.chars() {
+            match c {
+                '{' => self.open_braces.push(line.number),
+                '}' if self.open_braces.pop().is_none() => {
+                    return Err(PreprocessError::RepeatSplitsBlock {
+                        line: self.start,
+                        brace: line.number,
+                    });
+                }
+                _ => (),
+            }
+        }
+        self.lines.push(line);
+        Ok(())
+    }
+
+    /// Returns an error if a block opened in this list is never closed in it.
+    fn check_closed(&self) -> Result<(), PreprocessError> {
+        match self.open_braces.first() {
+            Some(&brace) => Err(PreprocessError::RepeatSplitsBlock {
+                line: self.start,
+                brace,
+            }),
+            None => Ok(()),
+        }
     }
 
-    /// Returns the contents of this list, repeated and joined by new lines.
-    /// The text does not contain a leading newline.
-    fn get_text(&self) -> String {
-        if self.count == 0 {
-            return String::new();
+    /// Returns the contents of this list, repeated `count` times.
+    /// Each `#I` in the `i`th repetition is replaced with the index `i`, and
+    /// each `#V` with the `i`th value, if the block has values.
+    fn get_lines(&self) -> Vec<Line> {
+        let mut lines = Vec::with_capacity(self.count * self.lines.len());
+        for i in 0..self.count {
+            lines.extend(self.lines.iter().map(|line| {
+                let text = substitute_index(&line.text, i);
+                let text = match self.values.get(i) {
+                    Some(value) => substitute_placeholder(&text, "#V", value),
+                    None => text,
+                };
+                Line {
+                    number: line.number,
+                    text,
+                }
+            }));
         }
-        let joined = self.lines.join("\n");
-        let repeated = format!("\n{joined}").repeat(self.count - 1);
-        format!("{joined}{repeated}")
+        lines
     }
 }
 
-/// Returns the repeat count, parsed from `repeat_line`.
-/// Requires that `repeat_line` has exactly one set of parentheses enclosing a
-/// `usize` literal, such as `"REPEAT(5)"`.
-fn parse_repeat_count(repeat_line: &str) -> usize {
-    let i = repeat_line.find('(').unwrap();
-    let j = repeat_line.rfind(')').unwrap();
-    let s = &repeat_line[i + 1..j];
-    s.parse::<usize>().unwrap()
+/// Returns a copy of `line` with every `#I` replaced by `index`.
+/// An `#I` followed by a letter, digit, or underscore is part of a longer name,
+/// such as `#INCLUDE`, and is not replaced.
+fn substitute_index(line: &str, index: usize) -> String {
+    substitute_placeholder(line, "#I", &index.to_string())
 }
 
-/// Returns a copy of `lines` with all repeat blocks included the indicated
-/// number of times.
-fn repeat_lines(lines: Vec<String>) -> Vec<String> {
-    // Stack of lines to repeat.
-    // The element with the highest index is the top of the stack.
-    // Each element is a `RepeatLines` struct. Lines are added to
-    // this list as they are encountered. A new vector is pushed to the stack
-    // when a repeat line is opened. And when a vector is popped, all of those
-    // lines are added repeatedly to the previous vector.
-    let mut repeats: Vec<RepeatLines> = vec![];
-    let mut output: Vec<String> = vec![];
-    for line in lines {
-        if line.to_uppercase().starts_with("#REPEAT(") {
-            repeats.push(RepeatLines::new(parse_repeat_count(&line)));
-        } else if line.eq_ignore_ascii_case("#END_REPEAT") {
-            let last = repeats.pop().expect("Unexpected end repeat.");
-            for text in last.get_text().split("\n") {
-                match repeats.last_mut() {
-                    Some(prev) => prev.push_line(text.to_string()),
-                    None => output.push(text.to_string()),
-                }
-            }
+/// Returns a copy of `line` with every `placeholder` replaced by `value`.
+/// A `placeholder` followed by a letter, digit, or underscore is part of a
+/// longer name, such as `#VISION` for `#V`, and is not replaced.
+fn substitute_placeholder(line: &str, placeholder: &str, value: &str) -> String {
+    if !line.contains(placeholder) {
+        return line.to_string();
+    }
+    let mut output = String::with_capacity(line.len());
+    let mut rest = line;
+    while let Some(i) = rest.find(placeholder) {
+        let after = &rest[i + placeholder.len()..];
+        output.push_str(&rest[..i]);
+        if after.starts_with(|c: char| c.is_ascii_alphanumeric() || c == '_') {
+            output.push_str(placeholder);
         } else {
-            match repeats.last_mut() {
-                Some(repeat_list) => repeat_list.push_line(line),
-                None => output.push(line),
-            }
+            output.push_str(value);
         }
+        rest = after;
     }
-    assert!(
-        repeats.is_empty(),
-        "Repeats is nonempty. Not all lines are written to output."
-    );
+    output.push_str(rest);
     output
 }
 
-/// Divides `m` into `n` numbers of equal probability. The first `n % m` numbers
-/// have value `n / m + 1`, the remaining have value `n / m`.
-pub fn probs(n: u32, m: u32) -> Vec<u32> {
-    let q = n / m;
-    let r = n % m;
-    let mut results = Vec::with_capacity(m as usize);
-    for _ in 0..r {
-        results.push(q + 1);
+/// Returns the comma-separated values of `args`, each without surrounding
+/// whitespace. A value enclosed in double quotes may contain commas, and its
+/// quotes are removed. Returns `None` if a quote is never closed.
+fn split_repeat_values(args: &str) -> Option<Vec<String>> {
+    let mut values = vec![];
+    let mut value = String::new();
+    let mut quoted = false;
+    for c in args.chars() {
+        match c {
+            '"' => quoted = !quoted,
+            ',' if !quoted => values.push(std::mem::take(&mut value).trim().to_string()),
+            c => value.push(c),
+        }
     }
-    for _ in r..m {
-        results.push(q);
+    values.push(value.trim().to_string());
+    (!quoted).then_some(values)
+}
+
+/// Returns the values of the `#REPEAT_EACH` line `line`, such as `GRASS` and
+/// `SNOW` from `"#REPEAT_EACH(GRASS, SNOW)"`.
+/// Returns an error if the values are not enclosed in parentheses, if a quote
+/// is never closed, or if a value is empty.
+fn parse_repeat_values(line: &Line) -> Result<Vec<String>, PreprocessError> {
+    let error = || PreprocessError::BadRepeatValues {
+        line: line.number,
+        text: line.text.clone(),
+    };
+    let args = strip_directive(&line.text, "#REPEAT_EACH(")
+        .and_then(|rest| rest.strip_suffix(')'))
+        .ok_or_else(error)?;
+    match split_repeat_values(args) {
+        Some(values) if values.iter().all(|value| !value.is_empty()) => Ok(values),
+        _ => Err(error()),
     }
-    results
 }
 
-/// Returns the next label name immediately succeeding `label`.
-///
-/// Labels are used for control flow statements in random map scripts. This
-/// function provides the ability to generate unique label names. These names
-/// are not designed to be human-readable, but they do still follow the
-/// convention of using only capital letters and a leading underscore. Passing
-/// `&None` as an argument returns a default label. Starting with that label,
-/// the results of subsequent calls to this function may continue to be passed
-/// to generate unique labels.
-///
-/// Note it is also possible that the map script itself may define a label with
-/// such a name, so a user of this function should ensure they do not begin
-/// their own label names with underscores.
-pub fn next_label(label: &Option<&str>) -> String {
-    if let Some(label) = label {
-        let n = label.len();
-        let last = label.as_bytes()[n - 1];
-        match last {
-            b'Z' => format!("{label}A"),
-            c => format!("{}{}", &label[..n - 1], (c + 1) as char),
+/// Returns the repeat count, parsed from `repeat_line`, such as `5` from
+/// `"#REPEAT(5)"`. A count of `0` is allowed and repeats the block zero times.
+/// Returns an error if `repeat_line` does not have one set of parentheses
+/// enclosing a non-negative integer literal.
+fn parse_repeat_count(line: &Line) -> Result<usize, PreprocessError> {
+    let repeat_line = &line.text[..];
+    let error = || PreprocessError::BadRepeatCount {
+        line: line.number,
+        text: repeat_line.to_string(),
+    };
+    let (Some(i), Some(j)) = (repeat_line.find('('), repeat_line.rfind(')')) else {
+        return Err(error());
+    };
+    let s = repeat_line.get(i + 1..j).unwrap_or_default().trim();
+    s.parse::<usize>().map_err(|_| error())
+}
+
+/// The `#const` definitions of a script.
+#[derive(Debug, Default)]
+struct Consts<'a> {
+    /// The value of each const, from its last definition.
+    values: HashMap<&'a str, &'a str>,
+    /// The names of the consts defined inside of a `start_random` or `if`
+    /// block, whose values depend on the branch the game takes.
+    conditional: HashSet<&'a str>,
+}
+
+/// Returns the `#const NAME VALUE` definitions of `lines`, from anywhere in the
+/// script, so a const may be used before its definition, as the game allows.
+/// Warns of each const that is redefined outside of any `start_random` or `if`
+/// block, keeping the last definition. A const defined in each branch of such
+/// a block is not redefined.
+fn collect_consts(lines: &[Line]) -> Consts<'_> {
+    let mut consts = Consts::default();
+    // The number of open `start_random` and `if` blocks.
+    let mut depth = 0usize;
+    for line in lines {
+        let words: Vec<&str> = line.text.split_whitespace().collect();
+        for (i, word) in words.iter().enumerate() {
+            match *word {
+                "start_random" | "if" => depth += 1,
+                "end_random" | "endif" => depth = depth.saturating_sub(1),
+                "#const" => {
+                    let (Some(name), Some(value)) = (words.get(i + 1), words.get(i + 2)) else {
+                        continue;
+                    };
+                    let redefined = consts.values.insert(*name, *value).is_some();
+                    if depth > 0 {
+                        consts.conditional.insert(*name);
+                    } else if redefined {
+                        warning!("line {}: const `{name}` is redefined.", line.number);
+                    }
+                }
+                _ => (),
+            }
         }
-    } else {
-        "_A".to_string()
     }
+    consts
 }
 
-/// Returns the random block for the script's preamble for a `rnd(min,max)`
-/// instructions. Requires `min < max`. `label` is the prefix of the label
-/// defined by the precent random block. The labels have the form `label_k`,
+/// Replaces the count of each `#REPEAT(NAME)` line of `lines` with the value
+/// of the const `NAME`, defined anywhere in the script. Counts that are not
+/// consts are unchanged. Returns an error if `NAME` is defined inside of a
+/// `start_random` or `if` block, since the count would depend on the branch
+/// the game takes.
+fn resolve_repeat_consts(lines: Vec<Line>) -> Result<Vec<Line>, PreprocessError> {
+    let consts = collect_consts(&lines);
+    let mut resolved: Vec<Option<String>> = Vec::with_capacity(lines.len());
+    for line in &lines {
+        let name = strip_directive(&line.text, "#REPEAT(")
+            .and_then(|rest| rest.strip_suffix(')'))
+            .map(str::trim);
+        let Some(name) = name.filter(|name| consts.values.contains_key(name)) else {
+            resolved.push(None);
+            continue;
+        };
+        if consts.conditional.contains(name) {
+            return Err(PreprocessError::ConditionalRepeatCount {
+                line: line.number,
+                name: name.to_string(),
+            });
+        }
+        resolved.push(Some(format!("#REPEAT({})", consts.values[name])));
+    }
+    Ok(lines
+        .into_iter()
+        .zip(resolved)
+        .map(|(line, text)| match text {
+            Some(text) => Line {
+                number: line.number,
+                text,
+            },
+            None => line,
+        })
+        .collect())
+}
+
+/// The order in which macros are expanded relative to unrolling `#REPEAT`
+/// blocks.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum MacroOrder {
+    /// Expands macros first, so a macro inside of a repeat block is expanded
+    /// once and its expansion is repeated.
+    #[default]
+    BeforeRepeat,
+    /// Unrolls repeat blocks first, so a macro inside of a repeat block is
+    /// expanded separately for each repetition, after its `#I` is replaced
+    /// with the repetition's index. Enabled by the `#EXPAND_AFTER_REPEAT` flag.
+    AfterRepeat,
+}
+
+/// Returns whether `lines` contains the flag `flag`, matched ignoring case,
+/// along with `lines` with every occurrence of the flag removed.
+fn take_flag(lines: Vec<Line>, flag: &str) -> (bool, Vec<Line>) {
+    let n = lines.len();
+    let output: Vec<Line> = lines
+        .into_iter()
+        .filter(|line| !is_directive(&line.text, flag))
+        .collect();
+    (output.len() < n, output)
+}
+
+/// Returns the macro order of `lines`, along with `lines` with the
+/// `#EXPAND_AFTER_REPEAT` flag removed.
+fn take_macro_order(lines: Vec<Line>) -> (MacroOrder, Vec<Line>) {
+    match take_flag(lines, "#EXPAND_AFTER_REPEAT") {
+        (true, lines) => (MacroOrder::AfterRepeat, lines),
+        (false, lines) => (MacroOrder::BeforeRepeat, lines),
+    }
+}
+
+/// Whether the `"macros"` and `"repeat"` passes and the `"check_labels"` lint
+/// of the pipeline are applied. A script disables the passes with the
+/// `#NO_MACROS` and `#NO_REPEAT` flags, and enables the lint with the
+/// `#CHECK_LABELS` flag.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Passes {
+    /// Whether macros are expanded.
+    macros: bool,
+    /// Whether repeat blocks are unrolled.
+    repeats: bool,
+    /// Whether the labels of the output are checked.
+    labels: bool,
+}
+
+impl Default for Passes {
+    /// Enables every pass, but not the opt-in lint.
+    fn default() -> Self {
+        Passes {
+            macros: true,
+            repeats: true,
+            labels: false,
+        }
+    }
+}
+
+/// Returns the passes enabled for `lines`, along with `lines` with the
+/// `#NO_MACROS`, `#NO_REPEAT`, and `#CHECK_LABELS` flags removed.
+fn take_passes(lines: Vec<Line>) -> (Passes, Vec<Line>) {
+    let (no_macros, lines) = take_flag(lines, "#NO_MACROS");
+    let (no_repeats, lines) = take_flag(lines, "#NO_REPEAT");
+    let (labels, lines) = take_flag(lines, "#CHECK_LABELS");
+    let passes = Passes {
+        macros: !no_macros,
+        repeats: !no_repeats,
+        labels,
+    };
+    (passes, lines)
+}
+
+/// Returns the macro order and the passes enabled for the body `lines` of a
+/// map script. The flags are read from the lines that remain after comments,
+/// `#IF_PLAYERS` blocks, and debug-only lines are resolved for `options`, and
+/// are removed by the `"flags"` pass. Returns an error if resolving the lines
+/// does.
+fn pipeline_flags(
+    lines: &[Line],
+    options: &Options,
+) -> Result<(MacroOrder, Passes), PreprocessError> {
+    let lines = strip_comments(lines.to_vec())?;
+    let lines = select_player_branches(lines, options.players)?;
+    let lines = filter_debug_lines(lines, options.mode);
+    let (order, lines) = take_macro_order(lines);
+    let (passes, _) = take_passes(lines);
+    Ok((order, passes))
+}
+
+/// Returns `lines` with the `#EXPAND_AFTER_REPEAT`, `#NO_MACROS`,
+/// `#NO_REPEAT`, and `#CHECK_LABELS` flags removed. See `pipeline_flags`.
+fn remove_pipeline_flags(lines: Vec<Line>) -> Vec<Line> {
+    let (_, lines) = take_macro_order(lines);
+    let (_, lines) = take_passes(lines);
+    lines
+}
+
+/// Returns the players `lo..=hi` of the directive `#FOR_PLAYERS(lo,hi)` in
+/// `line`, or `None` if `line` is not such a directive. Returns an error if the
+/// arguments are not two player numbers, each at least `1`.
+fn for_players_range(line: &Line) -> Result<Option<RangeInclusive<usize>>, PreprocessError> {
+    let Some(rest) = strip_directive(&line.text, "#FOR_PLAYERS(") else {
+        return Ok(None);
+    };
+    let error = || PreprocessError::BadMacroArgs {
+        line: line.number,
+        text: line.text.clone(),
+    };
+    let (lo, hi) = rest
+        .strip_suffix(')')
+        .and_then(|args| args.split_once(','))
+        .ok_or_else(error)?;
+    let (lo, hi): (usize, usize) = (parse_arg(lo, line)?, parse_arg(hi, line)?);
+    if lo == 0 {
+        return Err(error());
+    }
+    Ok(Some(lo..=hi))
+}
+
+/// Returns `line` with the value of each `assign_to_player` replaced by
+/// `player`. An `assign_to_player` without a value is given the value.
+fn assign_line_to_player(line: &str, player: usize) -> String {
+    let mut words = vec![];
+    let mut assign = false;
+    for word in line.split_whitespace() {
+        if assign {
+            assign = false;
+            if word.parse::<usize>().is_ok() {
+                continue;
+            }
+        }
+        if word == "assign_to_player" {
+            assign = true;
+            words.push(landgen::assign_to_player_line(player));
+        } else {
+            words.push(word.to_string());
+        }
+    }
+    words.join(" ")
+}
+
+/// Duplicates the lines of each `#FOR_PLAYERS(lo,hi) ... #END_FOR_PLAYERS`
+/// block of `lines` for each player from `lo` to `hi`, inclusive, with each
+/// `assign_to_player` assigned to the player. Blocks may not be nested.
+/// Returns an error if a block's arguments are invalid or if the blocks are
+/// unbalanced.
+fn expand_player_blocks(lines: Vec<Line>) -> Result<Vec<Line>, PreprocessError> {
+    // The number of the opening line, players, and lines of the open block.
+    let mut block: Option<(usize, RangeInclusive<usize>, Vec<Line>)> = None;
+    let mut output = Vec::with_capacity(lines.len());
+    for line in lines {
+        if let Some(players) = for_players_range(&line)? {
+            if let Some((start, _, _)) = block {
+                return Err(PreprocessError::UnterminatedForPlayers { line: start });
+            }
+            block = Some((line.number, players, vec![]));
+        } else if is_directive(&line.text, "#END_FOR_PLAYERS") {
+            let (_, players, body) = block
+                .take()
+                .ok_or(PreprocessError::UnexpectedEndForPlayers { line: line.number })?;
+            output.extend(landgen::for_players(players, |player| {
+                body.iter()
+                    .map(|line| Line {
+                        number: line.number,
+                        text: assign_line_to_player(&line.text, player),
+                    })
+                    .collect()
+            }));
+        } else {
+            match &mut block {
+                Some((_, _, body)) => body.push(line),
+                None => output.push(line),
+            }
+        }
+    }
+    match block {
+        Some((start, _, _)) => Err(PreprocessError::UnterminatedForPlayers { line: start }),
+        None => Ok(output),
+    }
+}
+
+/// Returns `(attribute, min, max)` of the directive
+/// `#RND_COUNT(attribute,min,max)` in `line`, or `None` if `line` is not such
+/// a directive. Returns an error if the bounds are not integers with
+/// `min <= max`.
+fn rnd_count_args(line: &Line) -> Result<Option<(&str, u32, u32)>, PreprocessError> {
+    let Some(rest) = strip_directive(&line.text, "#RND_COUNT(") else {
+        return Ok(None);
+    };
+    let error = || PreprocessError::BadMacroArgs {
+        line: line.number,
+        text: line.text.clone(),
+    };
+    let args: Vec<&str> = rest
+        .strip_suffix(')')
+        .ok_or_else(error)?
+        .split(',')
+        .collect();
+    let [attribute, min, max] = args[..] else {
+        return Err(error());
+    };
+    let (min, max): (u32, u32) = (parse_arg(min, line)?, parse_arg(max, line)?);
+    if attribute.trim().is_empty() || min > max {
+        return Err(error());
+    }
+    Ok(Some((attribute.trim(), min, max)))
+}
+
+/// Expands each `#RND_COUNT(attribute,min,max)` directive of `lines` into a
+/// random block defining a fresh label for each value and the conditional
+/// setting `attribute` to one of `min..=max` with equal chance. The expansion
+/// may be placed inside of a `create_object` or `create_land` block, where
+/// `rnd` is not allowed for every attribute. If `min == max`, the attribute is
+/// set directly.
+/// Returns an error if a directive's arguments are invalid.
+fn expand_rnd_counts(lines: Vec<Line>) -> Result<Vec<Line>, PreprocessError> {
+    let mut label = None;
+    let mut output = Vec::with_capacity(lines.len());
+    for line in lines {
+        let Some((attribute, min, max)) = rnd_count_args(&line)? else {
+            output.push(line);
+            continue;
+        };
+        if min == max {
+            let text = format!("{attribute} {min}");
+            output.extend(number_expansion(vec![text], line.number));
+            continue;
+        }
+        let next = next_label(&label.as_deref());
+        let prefix = format!("RND_COUNT{next}");
+        label = Some(next);
+        let expansion = [
+            prob_definitions(&prefix, min, max),
+            prob_conditional(&prefix, attribute, min, max),
+        ]
+        .join("\n");
+        let expansion = expansion.lines().map(String::from).collect();
+        output.extend(number_expansion(expansion, line.number));
+    }
+    Ok(output)
+}
+
+/// The default threshold above which `#WARN_OBJECT_COUNTS` warns of a
+/// `number_of_objects`.
+const OBJECT_COUNT_THRESHOLD: u32 = 50;
+
+/// Returns the threshold of the directive `#WARN_OBJECT_COUNTS` or
+/// `#WARN_OBJECT_COUNTS(threshold)` in `line`, or `None` if `line` is not such
+/// a directive. Returns an error if the threshold is not a nonnegative integer.
+fn object_count_threshold(line: &Line) -> Result<Option<u32>, PreprocessError> {
+    match strip_directive(&line.text, "#WARN_OBJECT_COUNTS") {
+        None => Ok(None),
+        Some("") => Ok(Some(OBJECT_COUNT_THRESHOLD)),
+        Some(rest) => match rest.strip_prefix('(').and_then(|r| r.strip_suffix(')')) {
+            Some(threshold) => parse_arg(threshold, line).map(Some),
+            None => Ok(None),
+        },
+    }
+}
+
+/// Returns the line number and count of each `number_of_objects` of `lines`
+/// whose count is a number above `threshold`.
+fn excessive_object_counts(lines: &[Line], threshold: u32) -> Vec<(usize, u32)> {
+    let mut counts = vec![];
+    for line in lines {
+        let words: Vec<&str> = line.text.split_whitespace().collect();
+        for pair in words.windows(2) {
+            if let ["number_of_objects", count] = pair {
+                if let Ok(count) = count.parse::<u32>() {
+                    if count > threshold {
+                        counts.push((line.number, count));
+                    }
+                }
+            }
+        }
+    }
+    counts
+}
+
+/// Removes the `#WARN_OBJECT_COUNTS` directive from `lines`. If the directive
+/// is present, prints a warning for each `number_of_objects` above its
+/// threshold, such as a leftover `number_of_objects 99`. Runs before macros
+/// are expanded, so only the script's own objects are checked, not the
+/// generators' output.
+/// Returns an error if the directive's threshold is malformed.
+fn warn_object_counts(lines: Vec<Line>) -> Result<Vec<Line>, PreprocessError> {
+    let mut threshold = None;
+    let mut output = Vec::with_capacity(lines.len());
+    for line in lines {
+        match object_count_threshold(&line)? {
+            Some(t) => threshold = Some(t),
+            None => output.push(line),
+        }
+    }
+    if let Some(threshold) = threshold {
+        for (line, count) in excessive_object_counts(&output, threshold) {
+            warning!("line {line}: `number_of_objects {count}` is above {threshold}.");
+        }
+    }
+    Ok(output)
+}
+
+/// The directives that take a parenthesized argument list but are not macros,
+/// so they remain after macros are expanded.
+const PAREN_DIRECTIVES: [&str; 6] = [
+    "#REPEAT",
+    "#REPEAT_EACH",
+    "#SCALE",
+    "#LABEL_SCOPE",
+    "#FOR_PLAYERS",
+    "#RND_COUNT",
+];
+
+/// Returns whether `line` has the form of a macro with arguments, `#NAME(...)`,
+/// where `NAME` is letters, digits, and underscores, but is not one of the
+/// `PAREN_DIRECTIVES`.
+fn is_unknown_macro(line: &str) -> bool {
+    let line = line.trim();
+    let Some((name, _)) = line.split_once('(') else {
+        return false;
+    };
+    let is_name = name
+        .strip_prefix('#')
+        .is_some_and(|n| !n.is_empty() && n.chars().all(|c| c.is_ascii_alphanumeric() || c == '_'));
+    is_name
+        && line.ends_with(')')
+        && !PAREN_DIRECTIVES
+            .iter()
+            .any(|directive| directive.eq_ignore_ascii_case(name))
+}
+
+/// Returns an error for the first line of `lines` that has the form of a macro
+/// with arguments but was not expanded, which is almost always a misspelled
+/// macro. Unknown directives without arguments are allowed.
+/// Requires that macros have been expanded.
+fn check_unknown_macros(lines: &[Line]) -> Result<(), PreprocessError> {
+    match lines.iter().find(|line| is_unknown_macro(&line.text)) {
+        Some(line) => Err(PreprocessError::UnknownMacro {
+            line: line.number,
+            text: line.text.clone(),
+        }),
+        None => Ok(()),
+    }
+}
+
+/// Returns `lines` with macros expanded. If `strict` is set, also returns an
+/// error for a line that looks like a macro call but names no macro.
+fn expand_macros(lines: Vec<Line>, strict: bool) -> Result<Vec<Line>, PreprocessError> {
+    let lines = insert_macros(lines)?;
+    if strict {
+        check_unknown_macros(&lines)?;
+    }
+    Ok(lines)
+}
+
+/// Returns a copy of `lines` with all repeat blocks included the indicated
+/// number of times, or once for each value of a `#REPEAT_EACH` block.
+/// A block such as a `create_object` must be wholly inside or outside of each
+/// repeat block, though a repeat block may hold lines of an enclosing block.
+/// Returns an error if a repeat count is invalid, if the repeat blocks are
+/// unbalanced, or if a block crosses the boundary of a repeat block.
+fn repeat_lines(lines: Vec<Line>) -> Result<Vec<Line>, PreprocessError> {
+    // Stack of lines to repeat.
+    // The element with the highest index is the top of the stack.
+    // Each element is a `RepeatLines` struct. Lines are added to
+    // this list as they are encountered. A new vector is pushed to the stack
+    // when a repeat line is opened. And when a vector is popped, all of those
+    // lines are added repeatedly to the previous vector.
+    let mut repeats: Vec<RepeatLines> = vec![];
+    let mut output: Vec<Line> = vec![];
+    for line in lines {
+        if strip_directive(&line.text, "#REPEAT(").is_some() {
+            let count = parse_repeat_count(&line)?;
+            repeats.push(RepeatLines::new(line.number, count));
+        } else if strip_directive(&line.text, "#REPEAT_EACH(").is_some() {
+            let values = parse_repeat_values(&line)?;
+            repeats.push(RepeatLines::with_values(line.number, values));
+        } else if is_directive(&line.text, "#END_REPEAT") {
+            let last = repeats
+                .pop()
+                .ok_or(PreprocessError::UnexpectedEndRepeat { line: line.number })?;
+            last.check_closed()?;
+            log_event!(
+                debug,
+                "Line {}: unrolled repeat block {} times.",
+                last.start,
+                last.count
+            );
+            match repeats.last_mut() {
+                Some(prev) => prev.lines.extend(last.get_lines()),
+                None => output.extend(last.get_lines()),
+            }
+        } else {
+            match repeats.last_mut() {
+                Some(repeat_list) => repeat_list.push_line(line)?,
+                None => output.push(line),
+            }
+        }
+    }
+    if let Some(unterminated) = repeats.last() {
+        return Err(PreprocessError::UnterminatedRepeat {
+            line: unterminated.start,
+        });
+    }
+    Ok(output)
+}
+
+/// Divides `m` into `n` numbers of equal probability. The first `n % m` numbers
+/// have value `n / m + 1`, the remaining have value `n / m`.
+pub fn probs(n: u32, m: u32) -> Vec<u32> {
+    let q = n / m;
+    let r = n % m;
+    let mut results = Vec::with_capacity(m as usize);
+    for _ in 0..r {
+        results.push(q + 1);
+    }
+    for _ in r..m {
+        results.push(q);
+    }
+    results
+}
+
+/// Returns the next label name immediately succeeding `label`.
+///
+/// Labels are used for control flow statements in random map scripts. This
+/// function provides the ability to generate unique label names. These names
+/// are not designed to be human-readable, but they do still follow the
+/// convention of using only capital letters and a leading underscore. Passing
+/// `&None` as an argument returns a default label. Starting with that label,
+/// the results of subsequent calls to this function may continue to be passed
+/// to generate unique labels.
+///
+/// Note it is also possible that the map script itself may define a label with
+/// such a name, so a user of this function should ensure they do not begin
+/// their own label names with underscores.
+pub fn next_label(label: &Option<&str>) -> String {
+    if let Some(label) = label {
+        let n = label.len();
+        let last = label.as_bytes()[n - 1];
+        match last {
+            b'Z' => format!("{label}A"),
+            c => format!("{}{}", &label[..n - 1], (c + 1) as char),
+        }
+    } else {
+        "_A".to_string()
+    }
+}
+
+/// Returns the random block for the script's preamble for a `rnd(min,max)`
+/// instructions. Requires `min < max`. `label` is the prefix of the label
+/// defined by the precent random block. The labels have the form `label_k`,
 /// where `k` is an integer making each label unique.
 pub fn prob_definitions(label: &str, min: u32, max: u32) -> String {
     assert!(min < max, "Requires {min} < {max}.");
@@ -397,24 +1940,47 @@ pub fn prob_conditional(label: &str, instruction: &str, min: u32, max: u32) -> S
     lines.join("\n")
 }
 
-/// Returns `(instruction, min, max)`.
-pub fn extract_random_line(line: &str) -> (&str, u32, u32) {
-    let h = line.find(' ').unwrap();
-    let i = line.find('(').unwrap();
-    let j = line.find(',').unwrap();
-    let k = line.find(')').unwrap();
+/// Returns the index of the first `rnd(` token in `line`, or `None` if `line`
+/// has no such token. An `rnd` preceded by a letter, digit, or underscore is
+/// part of a longer name, such as `grnd_region`, and is not a token.
+fn find_rnd(line: &str) -> Option<usize> {
+    line.match_indices("rnd(")
+        .map(|(i, _)| i)
+        .find(|&i| !line[..i].ends_with(|c: char| c.is_ascii_alphanumeric() || c == '_'))
+}
+
+/// Returns `(instruction, min, max)` for a line of the form
+/// `instruction rnd(min,max)`. The instruction is everything before the `rnd`,
+/// including any arguments preceding it, such as `land_position 30`.
+/// Whitespace around `min` and `max` is ignored.
+/// Returns an error if the `rnd` is missing or its bounds are not non-negative
+/// integers.
+pub fn extract_random_line(line: &Line) -> Result<(&str, u32, u32), PreprocessError> {
+    let text = &line.text[..];
+    let parse = || {
+        let h = find_rnd(text)?;
+        let i = h + "rnd".len();
+        let j = i + text[i..].find(',')?;
+        let k = j + text[j..].find(')')?;
 
-    let instruction = &line[..h];
-    let min = (&line[i + 1..j]).parse::<u32>().unwrap();
-    let max = (&line[j + 1..k]).parse::<u32>().unwrap();
-    (instruction, min, max)
+        let instruction = text[..h].trim_end();
+        let min = text[i + 1..j].trim().parse::<u32>().ok()?;
+        let max = text[j + 1..k].trim().parse::<u32>().ok()?;
+        Some((instruction, min, max))
+    };
+    parse().ok_or_else(|| PreprocessError::BadRnd {
+        line: line.number,
+        text: text.to_string(),
+    })
 }
 
 /// Applies the `#SET_PLACE_FOR_EVERY_PLAYER` macro, copying the object and
 /// assigning it to individual lands for each player. This allows player lands
 /// to have a `land_id` and still "use" the `set_place_for_every_player`
-/// instruction.
-fn assign_objects(lines: Vec<String>) -> Vec<String> {
+/// instruction. An object that already has a `place_on_specific_land_id` is
+/// kept as is, with a warning, instead of being assigned a second land.
+/// Returns an error if the macro occurs outside of a `create_object` command.
+fn assign_objects(lines: Vec<Line>) -> Result<Vec<Line>, PreprocessError> {
     // The loop has two states: `object` is empty or `object` is nonempty.
     // A line is added to object upon reaching the first line of `create_object`
     // command, and all lines from the command are collected until the command's
@@ -428,27 +1994,45 @@ fn assign_objects(lines: Vec<String>) -> Vec<String> {
     let mut num_players = 2;
     for line in lines {
         if object.is_empty() {
-            assert!(
-                !line.eq_ignore_ascii_case("#SET_PLACE_FOR_EVERY_PLAYER"),
-                "Macro encountered outside of create_object command."
-            );
-            if line.starts_with("create_object") {
+            if is_directive(&line.text, "#SET_PLACE_FOR_EVERY_PLAYER") {
+                return Err(PreprocessError::PlacementOutsideObject { line: line.number });
+            }
+            if line.text.trim_start().starts_with("create_object") {
                 object.push_back(line);
             } else {
                 output.push(line);
             }
             continue;
         }
-        match &line[..] {
+        let number = line.number;
+        let close = || Line {
+            number,
+            text: "}".to_string(),
+        };
+        match line.text.trim() {
             "}" => {
-                if every_player {
+                let pinned = object.iter().any(|line| {
+                    line.text
+                        .split_whitespace()
+                        .any(|word| word == "place_on_specific_land_id")
+                });
+                if every_player && pinned {
+                    warning!(
+                        "`{}` already has a `place_on_specific_land_id`, not placing it for every player.",
+                        object[0].text.trim()
+                    );
+                }
+                if every_player && !pinned {
                     // Pushes the object for each player, adding the land id.
                     for land_id in 1..=num_players {
                         for s in &object {
                             output.push(s.clone());
                         }
-                        output.push(format!("place_on_specific_land_id {land_id}"));
-                        output.push("}".to_string());
+                        output.push(Line {
+                            number,
+                            text: format!("place_on_specific_land_id {land_id}"),
+                        });
+                        output.push(close());
                     }
                     object.clear();
                 } else {
@@ -456,7 +2040,7 @@ fn assign_objects(lines: Vec<String>) -> Vec<String> {
                     while !object.is_empty() {
                         output.push(object.pop_front().unwrap());
                     }
-                    output.push("}".to_string());
+                    output.push(close());
                 }
                 every_player = false
             }
@@ -471,57 +2055,403 @@ fn assign_objects(lines: Vec<String>) -> Vec<String> {
             _ => object.push_back(line),
         }
     }
-    assert!(object.is_empty(), "Object not closed, missing `}}`.");
-    output
+    // Passes an unclosed object through as is, for the brace check to report.
+    output.extend(object);
+    Ok(output)
+}
+
+/// The token marking the end of land generation when extracting `rnd`
+/// commands, unless the script sets a different one.
+const EXTRACT_RND_MARKER: &str = "ELEVATION_GENERATION";
+
+/// Returns the marker token of the directive `#EXTRACT_RND_AFTER <token>`, or
+/// `None` if `line` is not such a directive.
+fn extract_rnd_marker(line: &str) -> Option<&str> {
+    let (name, token) = line.trim().split_once(' ')?;
+    name.eq_ignore_ascii_case("#EXTRACT_RND_AFTER")
+        .then_some(token)
 }
 
 /// Moves the `rnd` commands from every line after land generation to be a
 /// random block at the start of the file, along with an if statement where the
 /// rnd was located. Requires that every line after land generation as at most
-/// one `rnd` command.
-fn extract_rnd(lines: Vec<String>) -> Vec<String> {
+/// one `rnd` command. Names containing `rnd`, such as `grnd_region`, are not
+/// `rnd` commands.
+///
+/// Land generation ends at the first line containing `ELEVATION_GENERATION`,
+/// or the token given by an `#EXTRACT_RND_AFTER <token>` directive, which also
+/// enables extraction. If no line contains the marker, then `rnd` commands are
+/// extracted from every line, and a warning is printed.
+/// Returns an error if an `rnd` command cannot be parsed.
+fn extract_rnd(lines: Vec<Line>) -> Result<Vec<Line>, PreprocessError> {
+    let mut extract = false;
+    let mut marker = EXTRACT_RND_MARKER.to_string();
+    for line in &lines {
+        if is_directive(&line.text, "#EXTRACT_RND") {
+            extract = true;
+        } else if let Some(token) = extract_rnd_marker(&line.text) {
+            extract = true;
+            marker = token.to_string();
+        }
+    }
     // If the script does not specify to extract the `rnd` instructions for
     // debugging, then don't extract them.
-    if lines
+    if !extract {
+        return Ok(lines);
+    }
+
+    let is_rnd_directive =
+        |line: &str| is_directive(line, "#EXTRACT_RND") || extract_rnd_marker(line).is_some();
+    let mut finished_land = !lines
         .iter()
-        .all(|line| !line.eq_ignore_ascii_case("#EXTRACT_RND"))
-    {
-        return lines;
+        .any(|line| !is_rnd_directive(&line.text) && line.text.contains(&marker[..]));
+    if finished_land {
+        warning!("marker `{marker}` not found, extracting every `rnd`.");
     }
 
     let mut preamble = Vec::new();
     let mut body = Vec::new();
     let mut label = next_label(&None);
-    let mut finished_land = false;
     for line in lines {
-        // Avoids copying the extract random flag to the final output.
-        if line.eq_ignore_ascii_case("#EXTRACT_RND") {
+        // Avoids copying the extract random directives to the final output.
+        if is_rnd_directive(&line.text) {
             continue;
         }
         if !finished_land {
-            finished_land = line.contains("ELEVATION_GENERATION");
+            finished_land = line.text.contains(&marker[..]);
             body.push(line);
             continue;
         }
-        if !line.contains("rnd") {
+        if find_rnd(&line.text).is_none() {
             body.push(line);
             continue;
         }
-        let (instruction, min, max) = extract_random_line(&line);
-        preamble.push(prob_definitions(&label, min, max));
-        body.push(prob_conditional(&label, instruction, min, max));
+        let (instruction, min, max) = extract_random_line(&line)?;
+        preamble.push(Line {
+            number: line.number,
+            text: prob_definitions(&label, min, max),
+        });
+        body.push(Line {
+            number: line.number,
+            text: prob_conditional(&label, instruction, min, max),
+        });
         label = next_label(&Some(&label));
     }
     let mut output = preamble;
     output.append(&mut body);
-    output
+    Ok(output)
+}
+
+/// An axis across which land positions are reflected.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Axis {
+    /// Reflects the `x` coordinate, replacing `x` with `100 - x`.
+    X,
+    /// Reflects the `y` coordinate, replacing `y` with `100 - y`.
+    Y,
+}
+
+/// Returns the land position coordinate `coordinate`, either a number `v` or
+/// `rnd(a,b)`, with `f` applied. The result is `f(v)` or `rnd(f(a),f(b))`, with
+/// the bounds of the `rnd` reordered if `f` reverses them.
+/// Returns `None` if `coordinate` has neither form.
+fn map_coordinate(coordinate: &str, f: impl Fn(i32) -> i32) -> Option<String> {
+    if let Some(range) = coordinate
+        .strip_prefix("rnd(")
+        .and_then(|c| c.strip_suffix(')'))
+    {
+        let (a, b) = range.split_once(',')?;
+        let (a, b) = (a.trim().parse::<i32>().ok()?, b.trim().parse::<i32>().ok()?);
+        let (a, b) = (f(a), f(b));
+        Some(format!("rnd({},{})", a.min(b), a.max(b)))
+    } else {
+        Some(format!("{}", f(coordinate.parse::<i32>().ok()?)))
+    }
+}
+
+/// Returns the reflection of the land position coordinate `coordinate`,
+/// either a number `v` or `rnd(a,b)`. The reflection of `v` is `100 - v`, and
+/// the reflection of `rnd(a,b)` is `rnd(100 - b,100 - a)`.
+/// Returns `None` if `coordinate` has neither form.
+fn mirror_coordinate(coordinate: &str) -> Option<String> {
+    map_coordinate(coordinate, |v| 100 - v)
+}
+
+/// Returns a copy of `line` with the coordinate of its `land_position`
+/// reflected across `axis`, or `None` if `line` does not have a
+/// `land_position` with reflectable coordinates.
+fn mirror_line(line: &str, axis: Axis) -> Option<String> {
+    let i = line.find("land_position ")? + "land_position ".len();
+    let mut words = line[i..].splitn(3, ' ');
+    let (x, y) = (words.next()?, words.next()?);
+    let rest = words
+        .next()
+        .map_or(String::new(), |rest| format!(" {rest}"));
+    let (x, y) = match axis {
+        Axis::X => (mirror_coordinate(x)?, y.to_string()),
+        Axis::Y => (x.to_string(), mirror_coordinate(y)?),
+    };
+    Some(format!("{}{x} {y}{rest}", &line[..i]))
+}
+
+/// Applies the `#MIRROR_X` and `#MIRROR_Y` flags, following every
+/// `create_land` command that has a `land_position` with a copy whose
+/// position is reflected across the flag's axis. If both flags are present,
+/// the lands are reflected across both axes, producing four copies of each.
+/// Lines outside of `create_land` commands are emitted once, unchanged.
+/// A flag given more than once reflects the lands only once. Returns an error
+/// if a `create_land` command is never closed.
+fn mirror_lands(lines: Vec<Line>) -> Result<Vec<Line>, PreprocessError> {
+    // The axes in the order of their first flags, each at most once.
+    let mut axes = vec![];
+    let mut output = Vec::with_capacity(lines.len());
+    for line in lines {
+        let axis = if is_directive(&line.text, "#MIRROR_X") {
+            Axis::X
+        } else if is_directive(&line.text, "#MIRROR_Y") {
+            Axis::Y
+        } else {
+            output.push(line);
+            continue;
+        };
+        if !axes.contains(&axis) {
+            axes.push(axis);
+        }
+    }
+    for axis in axes {
+        let mut mirrored = Vec::with_capacity(output.len());
+        // The lines of the `create_land` command currently being parsed.
+        let mut land: Vec<Line> = vec![];
+        for line in output {
+            if land.is_empty() && !line.text.starts_with("create_land") {
+                mirrored.push(line);
+                continue;
+            }
+            let closed = line.text.contains('}');
+            land.push(line);
+            if !closed {
+                continue;
+            }
+            let reflected: Vec<Option<String>> = land
+                .iter()
+                .map(|line| mirror_line(&line.text, axis))
+                .collect();
+            let has_position = reflected.iter().any(Option::is_some);
+            let copy: Vec<Line> = reflected
+                .into_iter()
+                .zip(&land)
+                .map(|(r, line)| Line {
+                    number: line.number,
+                    text: r.unwrap_or_else(|| line.text.clone()),
+                })
+                .collect();
+            mirrored.append(&mut land);
+            if has_position {
+                mirrored.extend(copy);
+            }
+        }
+        if let Some(line) = land.first() {
+            return Err(PreprocessError::UnclosedLand { line: line.number });
+        }
+        output = mirrored;
+    }
+    Ok(output)
+}
+
+/// Applies the `#AUTO_ZONE` flag, giving each `create_land` command that is
+/// assigned to a player or team but has no `zone` the next unused zone. Zones
+/// are numbered from `1` in the order of the commands, skipping the zones that
+/// the script declares explicitly. Returns an error if a `create_land` command
+/// is never closed.
+fn assign_zones(lines: Vec<Line>) -> Result<Vec<Line>, PreprocessError> {
+    let (auto_zone, lines) = take_flag(lines, "#AUTO_ZONE");
+    if !auto_zone {
+        return Ok(lines);
+    }
+    let mut used = HashSet::new();
+    for line in &lines {
+        let words: Vec<&str> = line.text.split_whitespace().collect();
+        for pair in words.windows(2) {
+            if let ["zone", zone] = pair {
+                used.extend(zone.parse::<u32>());
+            }
+        }
+    }
+    let mut zone = 0;
+    let mut output = Vec::with_capacity(lines.len());
+    // The lines of the `create_land` command currently being parsed.
+    let mut land: Vec<Line> = vec![];
+    for line in lines {
+        if land.is_empty() && !line.text.trim_start().starts_with("create_land") {
+            output.push(line);
+            continue;
+        }
+        let closed = line.text.contains('}');
+        land.push(line);
+        if !closed {
+            continue;
+        }
+        let tokens: Vec<&str> = land
+            .iter()
+            .flat_map(|line| line.text.split_whitespace())
+            .collect();
+        let assigned = tokens
+            .iter()
+            .any(|&token| token == "assign_to" || token == "assign_to_player");
+        if assigned && !tokens.contains(&"zone") {
+            zone += 1;
+            while used.contains(&zone) {
+                zone += 1;
+            }
+            let Line { number, text } = land.pop().unwrap();
+            if text.trim() == "}" {
+                let zone = format!("zone {zone}");
+                land.push(Line { number, text: zone });
+                land.push(Line { number, text });
+            } else {
+                let i = text.rfind('}').unwrap();
+                let text = format!("{}zone {zone} {}", &text[..i], &text[i..]);
+                land.push(Line { number, text });
+            }
+        }
+        output.append(&mut land);
+    }
+    match land.first() {
+        Some(line) => Err(PreprocessError::UnclosedLand { line: line.number }),
+        None => Ok(output),
+    }
+}
+
+/// Returns `value` multiplied by `factor`, rounded, and clamped to
+/// `lo..=hi`.
+fn scale_value(value: i32, factor: f64, lo: u32, hi: u32) -> i32 {
+    utils::round_clamped(value as f64 * factor, lo, hi) as i32
+}
+
+/// Returns a copy of `line` with the coordinates of its `land_position` and its
+/// `actor_area_radius` multiplied by `factor`. Coordinates are clamped to
+/// `0..=100`. Arguments that are not numbers or `rnd(a,b)` are unchanged.
+fn scale_line(line: &str, factor: f64) -> String {
+    let mut words: Vec<String> = line.split(' ').map(String::from).collect();
+    for i in 0..words.len() {
+        // The number of arguments to scale and their upper bound.
+        let (count, hi) = match &words[i][..] {
+            "land_position" => (2, 100),
+            "actor_area_radius" => (1, i32::MAX as u32),
+            _ => continue,
+        };
+        for word in words.iter_mut().skip(i + 1).take(count) {
+            if let Some(scaled) = map_coordinate(word, |v| scale_value(v, factor, 0, hi)) {
+                *word = scaled;
+            }
+        }
+    }
+    words.join(" ")
+}
+
+/// Returns the factor of the directive `#SCALE(factor)` in `line`, or `None`
+/// if `line` is not such a directive. Returns an error if the factor is not a
+/// finite nonnegative number.
+fn scale_factor(line: &Line) -> Result<Option<f64>, PreprocessError> {
+    let Some(rest) = strip_directive(&line.text, "#SCALE(") else {
+        return Ok(None);
+    };
+    let factor = rest.strip_suffix(')').map(str::trim);
+    match factor.and_then(|f| f.parse::<f64>().ok()) {
+        Some(f) if f.is_finite() && f >= 0.0 => Ok(Some(f)),
+        _ => Err(PreprocessError::BadScaleFactor {
+            line: line.number,
+            text: line.text.clone(),
+        }),
+    }
+}
+
+/// Applies the `#SCALE(factor)` directive, multiplying every `land_position`
+/// coordinate and `actor_area_radius` by `factor`, including the bounds of
+/// `rnd(a,b)` coordinates. Runs after macro expansion and mirroring, so the
+/// positions those passes generate are scaled too. Returns an error if a
+/// factor is invalid.
+fn scale_positions(lines: Vec<Line>) -> Result<Vec<Line>, PreprocessError> {
+    let mut factor = None;
+    let mut output = Vec::with_capacity(lines.len());
+    for line in lines {
+        match scale_factor(&line)? {
+            Some(f) => factor = factor.or(Some(f)),
+            None => output.push(line),
+        }
+    }
+    let Some(factor) = factor else {
+        return Ok(output);
+    };
+    // Scales each line of the text, as a macro expands into several lines.
+    let scale = |line: Line| Line {
+        number: line.number,
+        text: line
+            .text
+            .split('\n')
+            .map(|text| scale_line(text, factor))
+            .collect::<Vec<_>>()
+            .join("\n"),
+    };
+    Ok(output.into_iter().map(scale).collect())
+}
+
+/// The ID number of the first named actor area, unless the header sets
+/// `actor_area_base`. High, to avoid conflicts with the actor areas of DE maps.
+pub const ACTOR_AREA_BASE: u32 = 20_000;
+
+/// The ID numbers of the named actor areas of a script, assigned in the order
+/// in which the names first occur. The names are kept in that order, so
+/// iterating them never depends on the order of a hash map.
+#[derive(Debug, Default)]
+struct ActorAreas {
+    /// The ID number of the first name.
+    base: u32,
+    /// The names, in the order of their ID numbers.
+    names: Vec<String>,
+    /// The ID number of each name.
+    ids: HashMap<String, u32>,
+}
+
+impl ActorAreas {
+    /// Returns an empty set of actor areas numbered starting from `base`.
+    fn new(base: u32) -> Self {
+        ActorAreas {
+            base,
+            ..ActorAreas::default()
+        }
+    }
+
+    /// Assigns the next ID number to `name`, unless it already has one.
+    fn insert(&mut self, name: &str) {
+        if !self.ids.contains_key(name) {
+            let id = self.base + self.names.len() as u32;
+            self.ids.insert(name.to_string(), id);
+            self.names.push(name.to_string());
+        }
+    }
+
+    /// Returns the ID number of `name`, or `None` if it has none.
+    fn get(&self, name: &str) -> Option<u32> {
+        self.ids.get(name).copied()
+    }
+
+    /// Returns whether no names have ID numbers.
+    fn is_empty(&self) -> bool {
+        self.names.is_empty()
+    }
+
+    /// Returns the ID numbers and names, in order of ID number.
+    fn iter(&self) -> impl Iterator<Item = (u32, &str)> {
+        (self.base..).zip(self.names.iter().map(String::as_str))
+    }
 }
 
 /// Replaces actor areas in `lines` with names. Allows for string-named actor
-/// areas instead of pure numbers.
-fn substitute_actor_area_names(lines: Vec<String>) -> Vec<String> {
-    let mut next_id = 20_000; // Start at a high number to avoid conflicts with DE maps.
-    let mut actor_areas = HashMap::new();
+/// areas instead of pure numbers. The names are numbered starting from `base`.
+/// Also returns the ID number assigned to each name.
+fn substitute_actor_area_names(lines: Vec<String>, base: u32) -> (Vec<String>, ActorAreas) {
+    let mut actor_areas = ActorAreas::new(base);
     // Assigns a unique ID number to each named actor area.
     for line in &lines[..] {
         if !line.starts_with("actor_area ") && !line.starts_with("create_actor_area ") {
@@ -535,15 +2465,12 @@ fn substitute_actor_area_names(lines: Vec<String>) -> Vec<String> {
             let i = &line[..j].rfind(' ').unwrap();
             &line[i + 1..j]
         };
-        if actor_areas.contains_key(name) {
-            continue; // Don't re-insert the key if the actor area exists.
-        }
-        actor_areas.insert(name.to_string(), next_id);
-        next_id += 1;
+        // Names that already have an ID number keep it.
+        actor_areas.insert(name);
     }
 
     // Replaces the actor areas with their ID numbers.
-    lines
+    let lines = lines
         .iter()
         .map(|line| {
             let Some(i) = line.find(' ') else {
@@ -575,66 +2502,836 @@ fn substitute_actor_area_names(lines: Vec<String>) -> Vec<String> {
                         line.to_string()
                     }
                 }
-                _ => return line.to_string(),
+                _ => line.to_string(),
             }
         })
+        .collect();
+    (lines, actor_areas)
+}
+
+/// Returns a table of the actor area names of `actor_areas` and their ID
+/// numbers, sorted by ID number, with one `id name` row per line.
+fn actor_area_table(actor_areas: &ActorAreas) -> Vec<String> {
+    actor_areas
+        .iter()
+        .map(|(id, name)| format!("{id} {name}"))
         .collect()
 }
 
+/// Returns the `create_object` blocks of `lines`, each from its
+/// `create_object` line through its closing `}` line.
+fn object_blocks(lines: &[String]) -> Vec<&[String]> {
+    let mut blocks = vec![];
+    let mut start = None;
+    for (i, line) in lines.iter().enumerate() {
+        if line.starts_with("create_object ") {
+            start = Some(i);
+        }
+        if let Some(j) = start.filter(|_| line.ends_with('}')) {
+            blocks.push(&lines[j..=i]);
+            start = None;
+        }
+    }
+    blocks
+}
+
+/// Returns the number of `create_object` blocks of `lines` that are identical
+/// to the previous block, except for at most one line. Such blocks, as emitted
+/// by the multi-TC macros, are candidates for merging by hand.
+fn count_similar_objects(lines: &[String]) -> usize {
+    object_blocks(lines)
+        .windows(2)
+        .filter(|pair| {
+            let (a, b) = (pair[0], pair[1]);
+            a.len() == b.len() && a.iter().zip(b).filter(|(x, y)| x != y).count() <= 1
+        })
+        .count()
+}
+
+/// Removes the `#REPORT_OBJECTS` flag from `lines`. If the flag is present,
+/// prints the number of `create_object` blocks that differ from the previous
+/// block in at most one line, to help decide which objects to merge.
+fn report_similar_objects(lines: Vec<Line>) -> Vec<Line> {
+    let (report, lines) = take_flag(lines, "#REPORT_OBJECTS");
+    if report {
+        let texts: Vec<String> = lines.iter().map(|line| line.text.clone()).collect();
+        let similar = count_similar_objects(&texts);
+        note!("{similar} create_object blocks differ from the previous block in at most one line.");
+    }
+    lines
+}
+
+/// Labels defined by the game rather than by a script, which may be referenced
+/// without a definition.
+const GAME_LABELS: [&str; 12] = [
+    "TINY_MAP",
+    "SMALL_MAP",
+    "MEDIUM_MAP",
+    "LARGE_MAP",
+    "HUGE_MAP",
+    "GIGANTIC_MAP",
+    "LUDIKRIS_MAP",
+    "REGICIDE",
+    "DEATH_MATCH",
+    "KING_OT_HILL",
+    "WONDER_RACE",
+    "DEFEND_WONDER",
+];
+
+/// The definitions and references of the labels of a script, each paired with
+/// the number of its line, starting from `1`.
+#[derive(Debug, Default, PartialEq, Eq)]
+struct LabelUses<'a> {
+    /// The labels defined by `#define NAME`.
+    definitions: Vec<(usize, &'a str)>,
+    /// The labels referenced by `if NAME` or `elseif NAME`.
+    references: Vec<(usize, &'a str)>,
+}
+
+/// Returns the definitions and references of the labels of `lines`. Line
+/// numbers are those of the output, counting the lines within each element of
+/// `lines`, as in `check_braces`.
+fn label_uses(lines: &[String]) -> LabelUses<'_> {
+    let mut uses = LabelUses::default();
+    let output_lines = lines.iter().flat_map(|line| line.split('\n'));
+    for (i, line) in output_lines.enumerate() {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        for pair in words.windows(2) {
+            match pair {
+                ["#define", label] => uses.definitions.push((i + 1, label)),
+                ["if" | "elseif", label] => uses.references.push((i + 1, label)),
+                _ => (),
+            }
+        }
+    }
+    uses
+}
+
+/// The definitions whose labels are never referenced, and the references whose
+/// labels are never defined, other than the `GAME_LABELS`.
+type Orphans<'a> = (Vec<(usize, &'a str)>, Vec<(usize, &'a str)>);
+
+/// Returns the orphaned labels of `lines`, as described by `Orphans`.
+fn orphan_labels(lines: &[String]) -> Orphans<'_> {
+    let uses = label_uses(lines);
+    let defined: HashSet<&str> = uses.definitions.iter().map(|&(_, label)| label).collect();
+    let referenced: HashSet<&str> = uses.references.iter().map(|&(_, label)| label).collect();
+    let unreferenced = uses
+        .definitions
+        .into_iter()
+        .filter(|(_, label)| !referenced.contains(label))
+        .collect();
+    let undefined = uses
+        .references
+        .into_iter()
+        .filter(|(_, label)| !defined.contains(label) && !GAME_LABELS.contains(label))
+        .collect();
+    (unreferenced, undefined)
+}
+
+/// Prints a warning for each label of the output `lines` that is defined but
+/// never referenced, and for each label that is referenced but never defined,
+/// such as after editing only one half of a positioning system. Line numbers
+/// are those of the output, as in `label_uses`. This is the `"check_labels"`
+/// lint of the pipeline, enabled by the `#CHECK_LABELS` flag.
+fn check_labels(lines: &[String]) {
+    let (unreferenced, undefined) = orphan_labels(lines);
+    for (line, label) in unreferenced {
+        warning!("line {line}: label `{label}` is defined but never referenced.");
+    }
+    for (line, label) in undefined {
+        warning!("line {line}: label `{label}` is referenced but never defined.");
+    }
+}
+
+/// Replaces each `#SECTION name` and `#END_SECTION` marker in `lines` with the
+/// comments `/* SECTION name */` and `/* END SECTION name */`, so the regions of
+/// the output may be traced back to their source. Markers of nested sections
+/// are indented by two spaces for each enclosing section.
+/// Returns an error if a section is unended, ended without being started, or
+/// has a name containing a comment delimiter.
+fn annotate_sections(lines: Vec<Line>) -> Result<Vec<Line>, PreprocessError> {
+    // Stack of the opening line numbers and names of the open sections, with
+    // the innermost on top.
+    let mut sections: Vec<(usize, String)> = vec![];
+    let mut output = Vec::with_capacity(lines.len());
+    for line in lines {
+        let number = line.number;
+        if is_directive(&line.text, "#END_SECTION") {
+            let (_, name) = sections
+                .pop()
+                .ok_or(PreprocessError::UnexpectedEndSection { line: number })?;
+            let indent = "  ".repeat(sections.len());
+            let text = format!("{indent}/* END SECTION {name} */");
+            output.push(Line { number, text });
+        } else if let Some(name) = strip_directive(&line.text, "#SECTION ").map(str::to_string) {
+            if name.contains("/*") || name.contains("*/") {
+                return Err(PreprocessError::BadSectionName { line: number, name });
+            }
+            let indent = "  ".repeat(sections.len());
+            let text = format!("{indent}/* SECTION {name} */");
+            output.push(Line { number, text });
+            sections.push((number, name));
+        } else {
+            output.push(line);
+        }
+    }
+    match sections.pop() {
+        Some((line, name)) => Err(PreprocessError::UnterminatedSection { line, name }),
+        None => Ok(output),
+    }
+}
+
+/// Returns `text` with its comment delimiters broken up, so `text` may be
+/// placed inside of a comment.
+fn escape_comment_delimiters(text: &str) -> String {
+    text.replace("/*", "/ *").replace("*/", "* /")
+}
+
+/// Wraps the lines of each `#COMMENT_OUT ... #END_COMMENT_OUT` region of
+/// `lines` in a single comment, so the region is kept in the output but
+/// ignored by the game. Comment delimiters inside of a region are broken up so
+/// the comment stays valid. Runs after the section markers are annotated, so
+/// their comments are also wrapped.
+/// Returns an error if a region is nested, unended, or ended without being
+/// started.
+fn comment_out_regions(lines: Vec<Line>) -> Result<Vec<Line>, PreprocessError> {
+    // The number of the line opening the current region, if any.
+    let mut region: Option<usize> = None;
+    let mut output = Vec::with_capacity(lines.len());
+    for line in lines {
+        let number = line.number;
+        if is_directive(&line.text, "#COMMENT_OUT") {
+            if region.is_some() {
+                return Err(PreprocessError::NestedCommentOut { line: number });
+            }
+            region = Some(number);
+            let text = String::from("/*");
+            output.push(Line { number, text });
+        } else if is_directive(&line.text, "#END_COMMENT_OUT") {
+            region
+                .take()
+                .ok_or(PreprocessError::UnexpectedEndCommentOut { line: number })?;
+            let text = String::from("*/");
+            output.push(Line { number, text });
+        } else if region.is_some() {
+            let text = escape_comment_delimiters(&line.text);
+            output.push(Line { number, text });
+        } else {
+            output.push(line);
+        }
+    }
+    match region {
+        Some(line) => Err(PreprocessError::UnterminatedCommentOut { line }),
+        None => Ok(output),
+    }
+}
+
+/// Returns whether `line` opens a `create_land` or `create_object` block
+/// that it does not also close.
+fn opens_block(line: &str) -> bool {
+    (line.starts_with("create_land ") || line.starts_with("create_object "))
+        && line.contains('{')
+        && !line.contains('}')
+}
+
+/// Removes the `#COMPACT` flag from `lines`. If the flag is present, joins
+/// the lines of each multi-line `create_land` and `create_object` block into a
+/// single line, keeping the order of the attributes. Runs last, so the blocks
+/// generated by every other pass are joined. The lines of a block that is
+/// never closed are kept unjoined, so `check_braces` reports it.
+fn compact_blocks(lines: Vec<Line>) -> Vec<Line> {
+    let (compact, lines) = take_flag(lines, "#COMPACT");
+    if !compact {
+        return lines;
+    }
+    let mut output = Vec::with_capacity(lines.len());
+    let mut block: Option<Vec<Line>> = None;
+    for line in lines {
+        match &mut block {
+            Some(block_lines) => {
+                let closed = line.text.contains('}');
+                block_lines.push(line);
+                if closed {
+                    let block_lines = block.take().unwrap();
+                    let texts: Vec<&str> = block_lines.iter().map(|line| &line.text[..]).collect();
+                    output.push(Line {
+                        number: block_lines[0].number,
+                        text: texts.join(" "),
+                    });
+                }
+            }
+            None if opens_block(&line.text) => block = Some(vec![line]),
+            None => output.push(line),
+        }
+    }
+    output.extend(block.into_iter().flatten());
+    output
+}
+
+/// The RMS instructions, section-independent keywords, and directives that
+/// `#LOWERCASE_KEYWORDS` writes in lowercase. Constants, labels, and actor area
+/// names are never keywords.
+pub const RMS_KEYWORDS: &[&str] = &[
+    "#const",
+    "#define",
+    "#include_drs",
+    "if",
+    "elseif",
+    "else",
+    "endif",
+    "start_random",
+    "percent_chance",
+    "end_random",
+    "random_placement",
+    "grouped_by_team",
+    "direct_placement",
+    "nomad_resources",
+    "behavior_version",
+    "override_map_size",
+    "create_player_lands",
+    "create_land",
+    "land_percent",
+    "number_of_tiles",
+    "base_size",
+    "land_position",
+    "circle_radius",
+    "circle_placement",
+    "terrain_type",
+    "base_terrain",
+    "base_layer",
+    "border_fuzziness",
+    "clumping_factor",
+    "left_border",
+    "right_border",
+    "top_border",
+    "bottom_border",
+    "zone",
+    "set_zone_by_team",
+    "set_zone_randomly",
+    "other_zone_avoidance_distance",
+    "min_placement_distance",
+    "land_id",
+    "assign_to",
+    "assign_to_player",
+    "create_terrain",
+    "spacing_to_other_terrain_types",
+    "set_avoid_player_start_areas",
+    "height_limits",
+    "set_scale_by_groups",
+    "set_scale_by_size",
+    "number_of_clumps",
+    "terrain_mask",
+    "create_elevation",
+    "set_scaling_to_map_size",
+    "spacing",
+    "enable_balanced_elevation",
+    "create_connect_all_players_land",
+    "create_connect_teams_lands",
+    "create_connect_all_lands",
+    "create_connect_same_land_zones",
+    "create_connect_to_nonplayer_land",
+    "replace_terrain",
+    "terrain_cost",
+    "terrain_size",
+    "default_terrain_replacement",
+    "create_object",
+    "number_of_objects",
+    "number_of_groups",
+    "group_variance",
+    "group_placement_radius",
+    "set_tight_grouping",
+    "set_loose_grouping",
+    "set_gaia_object_only",
+    "set_place_for_every_player",
+    "place_on_specific_land_id",
+    "set_scaling_to_player_number",
+    "terrain_to_place_on",
+    "layer_to_place_on",
+    "ignore_terrain_restrictions",
+    "max_distance_to_other_zones",
+    "min_distance_to_players",
+    "max_distance_to_players",
+    "min_distance_group_placement",
+    "temp_min_distance_group_placement",
+    "find_closest",
+    "second_object",
+    "resource_delta",
+    "actor_area",
+    "actor_area_radius",
+    "actor_area_to_place_in",
+    "avoid_actor_area",
+    "avoid_all_actor_areas",
+    "avoid_forest_zone",
+    "avoid_cliff_zone",
+    "create_actor_area",
+    "effect_amount",
+    "effect_percent",
+    "guard_state",
+    "terrain_state",
+    "weather_type",
+    "color_correction",
+    "min_number_of_cliffs",
+    "max_number_of_cliffs",
+    "min_length_of_cliff",
+    "max_length_of_cliff",
+    "cliff_curliness",
+    "min_distance_cliffs",
+    "min_terrain_distance",
+];
+
+/// The keywords whose arguments include a name, such as a label or an actor
+/// area, paired with the position of the name among the arguments, starting
+/// from `1`. A name is never written as a keyword, even if it matches one.
+pub const NAMING_KEYWORDS: &[(&str, usize)] = &[
+    ("#const", 1),
+    ("#define", 1),
+    ("if", 1),
+    ("elseif", 1),
+    ("actor_area", 1),
+    ("actor_area_to_place_in", 1),
+    ("avoid_actor_area", 1),
+    ("create_actor_area", 3),
+];
+
+/// Returns `lines` with each whitespace-separated word that matches one of the
+/// `keywords`, ignoring case, written as that keyword. Words inside of comments
+/// and the names of `NAMING_KEYWORDS` are not changed, and the whitespace
+/// between words is kept.
+pub fn lowercase_keywords(lines: Vec<String>, keywords: &[&str]) -> Vec<String> {
+    let mut in_comment = false;
+    // The number of words until the name of the last naming keyword, if any.
+    let mut until_name: Option<usize> = None;
+    lines
+        .into_iter()
+        .map(|line| {
+            let mut output = String::with_capacity(line.len());
+            for piece in line.split_inclusive(char::is_whitespace) {
+                let word = piece.trim_end();
+                match word {
+                    "/*" => in_comment = true,
+                    "*/" => in_comment = false,
+                    "" => {
+                        output.push_str(piece);
+                        continue;
+                    }
+                    _ => (),
+                }
+                let is_name = until_name == Some(1);
+                until_name = until_name.and_then(|n| n.checked_sub(1)).filter(|&n| n > 0);
+                let keyword = keywords
+                    .iter()
+                    .find(|keyword| keyword.eq_ignore_ascii_case(word))
+                    .filter(|_| !in_comment && !is_name);
+                match keyword {
+                    Some(keyword) => {
+                        if let Some(&(_, position)) =
+                            NAMING_KEYWORDS.iter().find(|(naming, _)| naming == keyword)
+                        {
+                            until_name = Some(position);
+                        }
+                        output.push_str(keyword);
+                        output.push_str(&piece[word.len()..]);
+                    }
+                    None => output.push_str(piece),
+                }
+            }
+            output
+        })
+        .collect()
+}
+
+/// Removes the `#LOWERCASE_KEYWORDS` flag from `lines`. If the flag is present,
+/// writes each of the `RMS_KEYWORDS` in lowercase, such as `create_land` for
+/// `CREATE_LAND`, leaving constants, labels, and actor area names unchanged.
+fn normalize_keywords(lines: Vec<Line>) -> Vec<Line> {
+    match take_flag(lines, "#LOWERCASE_KEYWORDS") {
+        (true, lines) => map_texts(lines, |texts| lowercase_keywords(texts, RMS_KEYWORDS)),
+        (false, lines) => lines,
+    }
+}
+
+/// Records where `#BREAK` truncated the output of a map script.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Truncation {
+    /// The line number of the `#BREAK` line in the output, starting from `1`.
+    pub line: usize,
+    /// The number of lines after the `#BREAK` line that were not written.
+    pub dropped: usize,
+}
+
+/// The keyword of the line at which the output is truncated, unless
+/// `Options::break_keyword` sets a different one.
+pub const DEFAULT_BREAK: &str = "#BREAK";
+
 /// Writes every line of `lines` to `dest`, including a single new line between
-/// each line but not at the end. Stops writing lines if a line contains
-/// `"#BREAK"`.
-fn write_until_break(lines: Vec<String>, dest: &mut BufWriter<File>) -> std::io::Result<()> {
+/// each line but not at the end. Stops writing lines at a line that is only
+/// the keyword `keyword`, ignoring case and surrounding whitespace. A line that
+/// merely contains the keyword, such as a name including it, is written.
+/// Lines are written as they are taken from `lines`, so the output does not
+/// need to be collected into a single vector before writing.
+///
+/// Returns where the output was truncated, or `None` if there is no break line.
+/// Line numbers count the lines within each element of `lines`, such as those
+/// of an expanded macro.
+fn write_until_break<W: Write>(
+    lines: impl IntoIterator<Item = String>,
+    dest: &mut W,
+    keyword: &str,
+) -> std::io::Result<Option<Truncation>> {
+    let output_lines = |line: &String| line.split('\n').count();
     let mut delim = ""; // Initially empty delim prevents a trailing newline.
-    for line in lines {
-        if line.to_uppercase().contains("#BREAK") {
-            break;
+    let mut written = 0;
+    let mut lines = lines.into_iter();
+    while let Some(line) = lines.next() {
+        if is_directive(&line, keyword) {
+            return Ok(Some(Truncation {
+                line: written + 1,
+                dropped: lines.map(|line| output_lines(&line)).sum(),
+            }));
         }
         write!(dest, "{delim}{line}")?;
         delim = "\n"; // Use a newline as a leading delim after the first line.
+        written += output_lines(&line);
+    }
+    Ok(None)
+}
+
+/// Applies the preprocessing steps to the lines of a map script, returning
+/// the header comment followed by the processed lines of the script.
+///
+/// `#BREAK` lines are not applied, they are only handled when writing the
+/// output.
+///
+/// A script containing a `#RAW` line is passed through verbatim, with only the
+/// `#RAW` lines removed.
+///
+/// Header lines of the form `key: value` are metadata and are not written to
+/// the output. The `actor_area_base` key sets the ID number of the first named
+/// actor area, other keys are ignored.
+///
+/// The header's lines are formatted as given by `options.header_format`.
+///
+/// Debug-only lines are kept if `options.mode` is `BuildMode::Debug`, and
+/// `#IF_PLAYERS` blocks are resolved for `options.players` players. If
+/// `options.verbose` is set, the ID number of each named actor area is
+/// printed.
+///
+/// Returns an error if the script is malformed.
+pub fn process_lines(
+    lines: Vec<String>,
+    options: &Options,
+) -> Result<Vec<String>, PreprocessError> {
+    process_lines_with(lines, options, Ok)
+}
+
+/// Applies the preprocessing steps to the lines of a map script as in
+/// `process_lines`, but with the passes of the standard pipeline replaced by
+/// the result of `customize`, which may insert, remove, or reorder passes.
+///
+/// The header is split from the script before the passes are run, and the
+/// passes receive the body's lines numbered by their positions in the source.
+/// The `#EXPAND_AFTER_REPEAT`, `#NO_MACROS`, and `#NO_REPEAT` flags of the
+/// script are applied to the standard pipeline before it is customized.
+/// Returns an error if the script is malformed or if `customize` does.
+pub fn process_lines_with<'a>(
+    lines: Vec<String>,
+    options: &'a Options,
+    customize: impl FnOnce(Pipeline<'a>) -> Result<Pipeline<'a>, PreprocessError>,
+) -> Result<Vec<String>, PreprocessError> {
+    let is_raw = |line: &String| is_directive(line, "#RAW");
+    if lines.iter().any(is_raw) {
+        return Ok(lines.into_iter().filter(|line| !is_raw(line)).collect());
+    }
+    let total = lines.len();
+    let (header, body) = collect_header_comment(lines)?;
+    let (metadata, header) = take_header_metadata(header);
+    let mut header = format_header(header, options.header_format);
+    let actor_area_base = metadata_value(&metadata, "actor_area_base", ACTOR_AREA_BASE)?;
+    let first = total - body.len() + 1;
+    let body = number_lines(body, first);
+    let (order, passes) = pipeline_flags(&body, options)?;
+    let mut pipeline = Pipeline::standard(options, actor_area_base);
+    if order == MacroOrder::AfterRepeat {
+        pipeline = pipeline.move_after("macros", "repeat")?;
+    }
+    if !passes.macros {
+        pipeline = pipeline.remove("macros")?;
+    }
+    if !passes.repeats {
+        pipeline = pipeline.remove("repeat")?;
+    }
+    if !passes.labels {
+        pipeline = pipeline.remove_lint("check_labels")?;
+    }
+    let pipeline = customize(pipeline)?;
+    let lines = pipeline.run(body)?;
+    header.extend(lines.into_iter().map(|line| line.text));
+    // Runs after the header is added, so line numbers are those of the output.
+    pipeline.lint(&header)?;
+    log_event!(info, "Processed {total} lines into {}.", header.len());
+    Ok(header)
+}
+
+/// Returns an error if a block of the output `lines`, such as a `create_land`
+/// or `create_object`, is opened by a `{` but never closed, or if a `}` closes
+/// no block. Braces inside of comments are ignored. Line numbers are those of
+/// the output, counting the lines within each element of `lines`. This is the
+/// `"check_braces"` lint of the pipeline.
+fn check_braces(lines: &[String]) -> Result<(), PreprocessError> {
+    // Stack of the line numbers and texts of the open blocks.
+    let mut blocks: Vec<(usize, &str)> = vec![];
+    let mut in_comment = false;
+    let output_lines = lines.iter().flat_map(|line| line.split('\n'));
+    for (i, line) in output_lines.enumerate() {
+        let number = i + 1;
+        let mut chars = line.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '/' if !in_comment && chars.peek() == Some(&'*') => {
+                    chars.next();
+                    in_comment = true;
+                }
+                '*' if in_comment && chars.peek() == Some(&'/') => {
+                    chars.next();
+                    in_comment = false;
+                }
+                '{' if !in_comment => blocks.push((number, line.trim())),
+                '}' if !in_comment => {
+                    blocks
+                        .pop()
+                        .ok_or(PreprocessError::UnexpectedCloseBrace { line: number })?;
+                }
+                _ => (),
+            }
+        }
+    }
+    match blocks.first() {
+        Some(&(line, text)) => Err(PreprocessError::UnclosedBlock {
+            line,
+            text: text.to_string(),
+        }),
+        None => Ok(()),
+    }
+}
+
+/// Returns the number of the first line and the `min_distance_to_players` and
+/// `max_distance_to_players` of each `create_object` block of `lines` in which
+/// both are numbers and the minimum is greater than the maximum, so the object
+/// is never placed.
+fn inverted_distances(lines: &[Line]) -> Vec<(usize, u32, u32)> {
+    let mut inverted = vec![];
+    // The first line number, minimum, and maximum of the open block.
+    let mut block: Option<(usize, Option<u32>, Option<u32>)> = None;
+    for line in lines {
+        if line.text.starts_with("create_object ") {
+            block = Some((line.number, None, None));
+        }
+        let Some((start, min, max)) = &mut block else {
+            continue;
+        };
+        let words: Vec<&str> = line.text.split_whitespace().collect();
+        for pair in words.windows(2) {
+            match pair {
+                ["min_distance_to_players", n] => *min = n.parse().ok(),
+                ["max_distance_to_players", n] => *max = n.parse().ok(),
+                _ => (),
+            }
+        }
+        if line.text.contains('}') {
+            if let (Some(min), Some(max)) = (*min, *max) {
+                if min > max {
+                    inverted.push((*start, min, max));
+                }
+            }
+            block = None;
+        }
+    }
+    inverted
+}
+
+/// Warns of each `create_object` block of `lines` whose
+/// `min_distance_to_players` is greater than its `max_distance_to_players`.
+/// Returns an error for the first such block instead if `strict` is set.
+fn check_object_distances(lines: &[Line], strict: bool) -> Result<(), PreprocessError> {
+    for (line, min, max) in inverted_distances(lines) {
+        if strict {
+            return Err(PreprocessError::InvertedDistances { line, min, max });
+        }
+        warning!(
+            "line {line}: `min_distance_to_players {min}` is greater than `max_distance_to_players {max}`."
+        );
     }
     Ok(())
 }
 
+/// Replaces the named actor areas of `lines` with ID numbers starting from
+/// `base`, printing the ID number of each name if `verbose` is set.
+fn number_actor_areas(lines: Vec<Line>, base: u32, verbose: bool) -> Vec<Line> {
+    let mut actor_areas = ActorAreas::default();
+    let lines = map_texts(lines, |texts| {
+        let (texts, areas) = substitute_actor_area_names(texts, base);
+        actor_areas = areas;
+        texts
+    });
+    if verbose && !actor_areas.is_empty() {
+        // Prints the table at once, so it is not interleaved with other output.
+        note!(
+            "Actor areas:\n  {}",
+            actor_area_table(&actor_areas).join("\n  ")
+        );
+    }
+    lines
+}
+
+/// The default template of the watermark comment. See `render_watermark`.
+pub const DEFAULT_WATERMARK: &str = "GENERATED FROM {src} AT {time} - DO NOT EDIT";
+
+/// Returns the watermark `template` with each `{src}` replaced by `src` and each
+/// `{time}` replaced by `time`.
+pub fn render_watermark(template: &str, src: &str, time: &str) -> String {
+    template.replace("{src}", src).replace("{time}", time)
+}
+
+/// Options for processing a map script.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Options {
+    /// Whether debug-only lines are kept.
+    pub mode: BuildMode,
+    /// The text of a comment written at the very top of the output, before the
+    /// header comment, or `None` for no such comment.
+    pub watermark: Option<String>,
+    /// Whether to print details of the processing, such as actor area IDs.
+    pub verbose: bool,
+    /// The number of players against which `#IF_PLAYERS` blocks are
+    /// resolved, or `None` if the script may not have such blocks.
+    pub players: Option<u32>,
+    /// Whether a line of the form `#NAME(...)` that is not a known macro is
+    /// an error instead of being passed through, and whether an object with a
+    /// `min_distance_to_players` above its `max_distance_to_players` is an
+    /// error instead of a warning.
+    pub strict: bool,
+    /// How the lines of the header comment are formatted.
+    pub header_format: HeaderFormat,
+    /// The keyword of the line at which the output is truncated, or `None`
+    /// for `DEFAULT_BREAK`.
+    pub break_keyword: Option<String>,
+    /// Whether a comment with the SHA-1 hash of the source and the version of
+    /// the preprocessor is written at the top of the output, after the
+    /// watermark.
+    pub stamp: bool,
+}
+
+/// Returns `text` as a single line RMS comment. Comment delimiters inside of
+/// `text` are broken up so the comment stays valid.
+fn watermark_comment(text: &str) -> String {
+    let text = escape_comment_delimiters(text);
+    format!("/* {} */", text.lines().collect::<Vec<_>>().join(" "))
+}
+
+/// Returns the lines of `text`. Lines end with `\n` or `\r\n`, as in
+/// `str::lines`, unless the text has no `\n`, in which case each lone `\r`
+/// ends a line, as in scripts saved with old Mac line endings.
+fn split_lines(text: &str) -> Vec<String> {
+    if text.contains('\n') || !text.contains('\r') {
+        return text.lines().map(String::from).collect();
+    }
+    let text = text.strip_suffix('\r').unwrap_or(text);
+    text.split('\r').map(String::from).collect()
+}
+
+/// Returns a comment recording the SHA-1 hash of the source `text` and the
+/// version of the preprocessor, so an output can be traced to its source.
+fn stamp_comment(text: &str) -> String {
+    let hash = sha1_smol::Sha1::from(text).digest();
+    format!(
+        "/* src-hash: {hash}, version: {} */",
+        env!("CARGO_PKG_VERSION")
+    )
+}
+
 /// Reads the map script in `src`, applies preprocessing steps, and writes the
-/// output to `dest`.
-pub fn process_script(
-    src: &mut BufReader<File>,
-    dest: &mut BufWriter<File>,
-) -> std::io::Result<()> {
-    // This doesn't feel very idomatic, at least without a pipe operator.
-    let lines = src.lines().collect::<std::io::Result<Vec<String>>>()?;
-    let (header, lines) = collect_header_comment(lines);
-    // Including files feels more trouble than it's worth, just do the Boars
-    // in the maps themselves.
-    // let lines = strip_comments(lines);
-    // let lines = condense_whitespace(lines);
-    // let lines = include_files(lines)?;
-    let lines = strip_comments(lines); // Strip again for included files.
-    let lines = condense_whitespace(lines);
-    let lines = insert_macros(lines);
-    let lines = repeat_lines(lines);
-    let lines = assign_objects(lines);
-    let lines = extract_rnd(lines);
-    let lines = substitute_actor_area_names(lines);
-    let mut total = vec![];
-    for line in header {
-        total.push(line.clone());
+/// output to `dest`, as in `process_lines`. The `options.watermark` comment is
+/// written first, followed by the source's hash if `options.stamp` is set.
+///
+/// Returns where the output was truncated by a `#BREAK` line, or by a line of
+/// `options.break_keyword` if set, or `None` if the entire output was written.
+/// Returns an error if the script is malformed or if reading or writing fails.
+pub fn process_script<R: BufRead, W: Write>(
+    src: &mut R,
+    dest: &mut W,
+    options: &Options,
+) -> Result<Option<Truncation>, PreprocessError> {
+    let mut text = String::new();
+    src.read_to_string(&mut text)?;
+    let lines = process_lines(split_lines(&text), options)?;
+    // The comments written before the lines, each a single line of the output.
+    let mut comments = 0;
+    if let Some(watermark) = &options.watermark {
+        writeln!(dest, "{}", watermark_comment(watermark))?;
+        comments += 1;
     }
-    for line in lines {
-        total.push(line.clone());
+    if options.stamp {
+        writeln!(dest, "{}", stamp_comment(&text))?;
+        comments += 1;
     }
-    write_until_break(total, dest)
+    let keyword = options.break_keyword.as_deref().unwrap_or(DEFAULT_BREAK);
+    let truncation = write_until_break(lines, dest, keyword)?;
+    Ok(truncation.map(|truncation| Truncation {
+        line: truncation.line + comments,
+        ..truncation
+    }))
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::{fs::File, io::BufReader};
+
+    /// Returns `lines` as `Line`s numbered from `1`.
+    fn numbered(lines: &[&str]) -> Vec<Line> {
+        number_lines(lines.iter().map(|line| line.to_string()).collect(), 1)
+    }
+
+    /// Returns the text of each of `lines`.
+    fn texts(lines: Vec<Line>) -> Vec<String> {
+        lines.into_iter().map(|line| line.text).collect()
+    }
 
     /// Tests a generic example of condensing a string with whitespace.
     #[test]
     fn test_condense_whitespace() {
         assert_eq!(condense_line_whitespace("   "), "");
+        assert_eq!(
+            condense_line_whitespace("  create_land\t{ \r terrain_type   WATER }  "),
+            "create_land { terrain_type WATER }"
+        );
+    }
+
+    /// Tests that condensing whitespace matches the previous implementation,
+    /// which formatted each word with its preceding space.
+    #[test]
+    fn condense_whitespace_joins_words() {
+        let previous = |s: &str| -> String {
+            s.split_whitespace()
+                .enumerate()
+                .map(|(i, w)| format!("{}{}", if i > 0 { " " } else { "" }, w))
+                .collect()
+        };
+        let inputs = [
+            "",
+            "a",
+            " a ",
+            "a b",
+            "\ta\t\tb\n",
+            "  a  b   c  ",
+            "\u{3000}a\u{a0}b",
+            "#REPEAT(2)",
+            "create_object VILLAGER {   number_of_objects 3 }",
+        ];
+        for s in inputs {
+            assert_eq!(previous(s), condense_line_whitespace(s), "{s:?}");
+        }
     }
 
     /// Tests that condensing a string consisting of only whitespace is empty.
@@ -694,4 +3391,1373 @@ mod tests {
         assert_eq!("", s);
         assert_eq!(0, d);
     }
+
+    /// Tests that removing a comment between two tokens leaves them separated
+    /// by a single space.
+    #[test]
+    fn strip_comment_between_tokens() {
+        let strip = |s| condense_line_whitespace(&strip_line_comments(s, 0).0);
+        assert_eq!("a b", strip("a/*c*/b"));
+        assert_eq!("a b", strip("a /*c*/ b"));
+        assert_eq!("number_of_tiles 50", strip("number_of_tiles/*c*/50"));
+        assert_eq!("#const FOO 5", strip("/* the answer */#const FOO 5"));
+    }
+
+    /// Tests that a space is inserted only where a comment at depth `0`
+    /// separates two non-whitespace characters, and that depths are unchanged.
+    #[test]
+    fn strip_comment_join_depth_0() {
+        let cases = [
+            ("DLC_ROCK/*note*/terrain", "DLC_ROCK terrain", 0),
+            ("a /*c*/ b", "a  b", 0),
+            ("a/*c*/ b", "a b", 0),
+            ("a/* x /* y */ z */b", "a b", 0),
+            ("a/*c*//*d*/b", "a b", 0),
+            ("a/*c", "a", 1),
+        ];
+        for (line, expected, depth) in cases {
+            assert_eq!((expected.to_string(), depth), strip_line_comments(line, 0));
+        }
+    }
+
+    /// Tests inserting spaces when a line begins inside of a comment.
+    #[test]
+    fn strip_comment_join_depth_1() {
+        let cases = [
+            ("x */a/*c*/b", "a b", 0),
+            ("a/*c*/b", "", 1),
+            ("x */ */a/*c*/b", " */a b", 0),
+            ("x /* y */a/*c*/b", "", 1),
+        ];
+        for (line, expected, depth) in cases {
+            assert_eq!((expected.to_string(), depth), strip_line_comments(line, 1));
+        }
+    }
+
+    /// Tests substituting whole tokens with aliases.
+    #[test]
+    fn substitute_alias_tokens() {
+        let lines = numbered(&[
+            "#alias PLAYER_TERRAIN GRASS",
+            "terrain_type PLAYER_TERRAIN",
+            "terrain_type PLAYER_TERRAIN_2",
+            "create_land {terrain_type PLAYER_TERRAIN}",
+        ]);
+        assert_eq!(
+            vec![
+                "terrain_type GRASS",
+                "terrain_type PLAYER_TERRAIN_2",
+                "create_land {terrain_type GRASS}",
+            ],
+            texts(substitute_aliases(lines).unwrap())
+        );
+    }
+
+    /// Tests that malformed and redefined aliases are errors.
+    #[test]
+    fn alias_errors() {
+        let lines = numbered(&["#ALIAS A B", "a", "#alias A C"]);
+        assert!(matches!(
+            substitute_aliases(lines),
+            Err(PreprocessError::AliasRedefined { line: 3, .. })
+        ));
+        let lines = numbered(&["#alias A"]);
+        assert!(matches!(
+            substitute_aliases(lines),
+            Err(PreprocessError::BadAlias { line: 1, .. })
+        ));
+    }
+
+    /// Tests repeating a block containing a line that is only a closing brace.
+    #[test]
+    fn repeat_closing_brace() {
+        let lines = vec!["#REPEAT(2)", "create_object PHON {", "}", "#END_REPEAT"];
+        let lines = texts(repeat_lines(numbered(&lines)).unwrap());
+        assert_eq!(
+            vec!["create_object PHON {", "}", "create_object PHON {", "}"],
+            lines
+        );
+    }
+
+    /// Tests that repeating a block with an empty line preserves the empty line.
+    #[test]
+    fn repeat_empty_line() {
+        let lines = vec!["#REPEAT(2)", "a", "", "#END_REPEAT"];
+        let lines = texts(repeat_lines(numbered(&lines)).unwrap());
+        assert_eq!(vec!["a", "", "a", ""], lines);
+    }
+
+    /// Tests that a block repeated zero times emits nothing.
+    #[test]
+    fn repeat_zero_times() {
+        let lines = vec!["a", "#REPEAT(0)", "b", "c", "#END_REPEAT", "d"];
+        let lines = texts(repeat_lines(numbered(&lines)).unwrap());
+        assert_eq!(vec!["a", "d"], lines);
+    }
+
+    /// Tests that a block repeated once is emitted exactly once.
+    #[test]
+    fn repeat_once() {
+        let lines = vec!["#REPEAT(1)", "b", "c", "#END_REPEAT"];
+        let lines = texts(repeat_lines(numbered(&lines)).unwrap());
+        assert_eq!(vec!["b", "c"], lines);
+    }
+
+    /// Tests that negative and malformed repeat counts are rejected.
+    #[test]
+    fn repeat_count_invalid() {
+        let counts = numbered(&["#REPEAT(5)", "#REPEAT(-1)", "#REPEAT()", "#REPEAT("]);
+        assert_eq!(5, parse_repeat_count(&counts[0]).unwrap());
+        assert_eq!(
+            "Line 2: repeat count must be a non-negative integer: `#REPEAT(-1)`.",
+            parse_repeat_count(&counts[1]).unwrap_err().to_string()
+        );
+        assert!(parse_repeat_count(&counts[2]).is_err());
+        assert!(parse_repeat_count(&counts[3]).is_err());
+    }
+
+    /// Tests that a negative repeat count stops processing with an error.
+    #[test]
+    fn repeat_negative_count() {
+        let result = repeat_lines(numbered(&["#REPEAT(-1)", "#END_REPEAT"]));
+        assert!(matches!(
+            result,
+            Err(PreprocessError::BadRepeatCount { line: 1, .. })
+        ));
+    }
+
+    /// Tests replacing `#I` with the repetition index.
+    #[test]
+    fn repeat_index() {
+        let lines = vec!["#REPEAT(2)", "a #I", "#INCLUDE #I_A #I)", "#END_REPEAT"];
+        let lines = texts(repeat_lines(numbered(&lines)).unwrap());
+        assert_eq!(
+            vec!["a 0", "#INCLUDE #I_A 0)", "a 1", "#INCLUDE #I_A 1)"],
+            lines
+        );
+    }
+
+    /// Tests that a block crossing the boundary of a repeat block is an error,
+    /// while repeating the attributes inside of a block is allowed.
+    #[test]
+    fn repeat_splits_block() {
+        let lines = vec!["#REPEAT(2)", "create_object A {", "#END_REPEAT", "}"];
+        assert!(matches!(
+            repeat_lines(numbered(&lines)),
+            Err(PreprocessError::RepeatSplitsBlock { line: 1, brace: 2 })
+        ));
+        let lines = vec!["create_object A {", "#REPEAT(2)", "a", "}", "#END_REPEAT"];
+        assert!(matches!(
+            repeat_lines(numbered(&lines)),
+            Err(PreprocessError::RepeatSplitsBlock { line: 2, brace: 4 })
+        ));
+        let lines = vec!["create_object A {", "#REPEAT(2)", "a", "#END_REPEAT", "}"];
+        assert_eq!(
+            vec!["create_object A {", "a", "a", "}"],
+            texts(repeat_lines(numbered(&lines)).unwrap())
+        );
+    }
+
+    /// Tests repeating a block once for each region name, replacing `#V` and
+    /// `#I`, including a quoted value with a comma.
+    #[test]
+    fn repeat_each_value() {
+        let lines = vec![
+            "#REPEAT_EACH(GRASS, SNOW, \"DIRT,JUNGLE\")",
+            "#VISION base_terrain #V #I",
+            "#END_REPEAT",
+        ];
+        let lines = texts(repeat_lines(numbered(&lines)).unwrap());
+        assert_eq!(
+            vec![
+                "#VISION base_terrain GRASS 0",
+                "#VISION base_terrain SNOW 1",
+                "#VISION base_terrain DIRT,JUNGLE 2",
+            ],
+            lines
+        );
+        for line in ["#REPEAT_EACH()", "#REPEAT_EACH(A,,B)", "#REPEAT_EACH(\"A)"] {
+            assert!(matches!(
+                repeat_lines(numbered(&[line, "#END_REPEAT"])),
+                Err(PreprocessError::BadRepeatValues { line: 1, .. })
+            ));
+        }
+    }
+
+    /// Tests that each pass detects its directives when they are indented with
+    /// tabs and spaces, as in lines that skipped condensing whitespace.
+    #[test]
+    fn indented_directives() {
+        let lines = numbered(&["\t#REPEAT(2)", "  #VISION", "\t #END_REPEAT "]);
+        assert_eq!(
+            [actorgen::vision(), actorgen::vision()].concat(),
+            texts(repeat_lines(insert_macros(lines).unwrap()).unwrap())
+        );
+        let (flag, lines) = take_flag(numbered(&["\t#NO_MACROS ", "a"]), "#NO_MACROS");
+        assert!(flag);
+        assert_eq!(vec!["a"], texts(lines));
+
+        let lines = vec![
+            "create_object VILLAGER {",
+            "\t#SET_PLACE_FOR_EVERY_PLAYER",
+            "  }",
+        ];
+        let lines = texts(assign_objects(numbered(&lines)).unwrap());
+        assert_eq!(
+            vec![
+                "create_object VILLAGER {",
+                "place_on_specific_land_id 1",
+                "}",
+                "create_object VILLAGER {",
+                "place_on_specific_land_id 2",
+                "}"
+            ],
+            lines
+        );
+
+        let lines = vec!["\t#MIRROR_X", "create_land {", "land_position 10 20", "}"];
+        let lines = texts(mirror_lands(numbered(&lines)).unwrap());
+        assert!(lines.contains(&String::from("land_position 90 20")));
+        let line = &numbered(&["\t#SCALE(2) "])[0];
+        assert_eq!(Some(2.0), scale_factor(line).unwrap());
+        assert_eq!(Some("_B"), label_scope("  #LABEL_SCOPE(_B)\t"));
+        assert_eq!(
+            vec!["/* SECTION a */", "/* END SECTION a */"],
+            texts(annotate_sections(numbered(&["\t#SECTION a", " #END_SECTION"])).unwrap())
+        );
+    }
+
+    /// Tests that placing an object for every player outside of a
+    /// `create_object` command is an error.
+    #[test]
+    fn assign_outside_object() {
+        let lines = ["create_object GOLD {", "}", "#SET_PLACE_FOR_EVERY_PLAYER"];
+        assert!(matches!(
+            assign_objects(numbered(&lines)),
+            Err(PreprocessError::PlacementOutsideObject { line: 3 })
+        ));
+    }
+
+    /// Tests that an object already placed on a land is not placed for every
+    /// player.
+    #[test]
+    fn assign_pinned_object() {
+        let lines = vec![
+            "create_object GOLD {",
+            "#SET_PLACE_FOR_EVERY_PLAYER",
+            "place_on_specific_land_id 5",
+            "}",
+            "create_object STONE { place_on_specific_land_id 6",
+            "#PLACE8",
+            "}",
+        ];
+        let lines = texts(assign_objects(numbered(&lines)).unwrap());
+        assert_eq!(
+            vec![
+                "create_object GOLD {",
+                "place_on_specific_land_id 5",
+                "}",
+                "create_object STONE { place_on_specific_land_id 6",
+                "}"
+            ],
+            lines
+        );
+    }
+
+    /// Tests that expanding macros after repeating expands a macro separately
+    /// for each repetition index.
+    #[test]
+    fn expand_after_repeat() {
+        let lines = vec!["#REPEAT(2)", "#FOURSEASONSLAKES(3#I)", "#END_REPEAT"];
+        let lines = numbered(&lines);
+        let mut expected = landgen::four_seasons_lakes(Some(30));
+        expected.append(&mut landgen::four_seasons_lakes(Some(31)));
+        assert_eq!(
+            expected,
+            texts(insert_macros(repeat_lines(lines).unwrap()).unwrap())
+        );
+        let lines = vec!["#REPEAT(2)", "#VISION", "#END_REPEAT"];
+        let (order, lines) = take_macro_order(numbered(&lines));
+        assert_eq!(MacroOrder::BeforeRepeat, order);
+        assert_eq!(
+            [actorgen::vision(), actorgen::vision()].concat(),
+            texts(repeat_lines(insert_macros(lines).unwrap()).unwrap())
+        );
+    }
+
+    /// Tests converting nested section markers into comments.
+    #[test]
+    fn section_comments() {
+        let lines = vec![
+            "#SECTION lands",
+            "a",
+            "#section ponds",
+            "b",
+            "#END_SECTION",
+            "#END_SECTION",
+            "c",
+        ];
+        let lines = texts(annotate_sections(numbered(&lines)).unwrap());
+        assert_eq!(
+            vec![
+                "/* SECTION lands */",
+                "a",
+                "  /* SECTION ponds */",
+                "b",
+                "  /* END SECTION ponds */",
+                "/* END SECTION lands */",
+                "c",
+            ],
+            lines
+        );
+    }
+
+    /// Tests wrapping a region in a comment, including a section comment and a
+    /// stray comment delimiter inside of it.
+    #[test]
+    fn comment_out() {
+        let lines = vec![
+            "a",
+            "#COMMENT_OUT",
+            "#SECTION alt",
+            "create_land { terrain_type WATER }",
+            "b */ c",
+            "#END_SECTION",
+            "#END_COMMENT_OUT",
+            "d",
+        ];
+        let lines = annotate_sections(numbered(&lines)).unwrap();
+        assert_eq!(
+            vec![
+                "a",
+                "/*",
+                "/ * SECTION alt * /",
+                "create_land { terrain_type WATER }",
+                "b * / c",
+                "/ * END SECTION alt * /",
+                "*/",
+                "d",
+            ],
+            texts(comment_out_regions(lines).unwrap())
+        );
+    }
+
+    /// Tests that a comment out region must be ended, must be started before
+    /// it is ended, and cannot be nested.
+    #[test]
+    fn comment_out_unbalanced() {
+        assert!(matches!(
+            comment_out_regions(numbered(&["a", "#COMMENT_OUT", "b"])),
+            Err(PreprocessError::UnterminatedCommentOut { line: 2 })
+        ));
+        assert!(matches!(
+            comment_out_regions(numbered(&["a", "#END_COMMENT_OUT"])),
+            Err(PreprocessError::UnexpectedEndCommentOut { line: 2 })
+        ));
+        let lines = ["#COMMENT_OUT", "#COMMENT_OUT", "#END_COMMENT_OUT"];
+        assert!(matches!(
+            comment_out_regions(numbered(&lines)),
+            Err(PreprocessError::NestedCommentOut { line: 2 })
+        ));
+    }
+
+    /// Tests that a section must be ended, must be started before it is ended,
+    /// and cannot have a comment delimiter in its name.
+    #[test]
+    fn section_unbalanced() {
+        assert!(matches!(
+            annotate_sections(numbered(&["#SECTION lands", "#SECTION ponds", "#END_SECTION"])),
+            Err(PreprocessError::UnterminatedSection { line: 1, ref name }) if name == "lands"
+        ));
+        assert!(matches!(
+            annotate_sections(numbered(&["a", "#END_SECTION"])),
+            Err(PreprocessError::UnexpectedEndSection { line: 2 })
+        ));
+        assert!(matches!(
+            annotate_sections(numbered(&["a", "#SECTION b */ c", "#END_SECTION"])),
+            Err(PreprocessError::BadSectionName { line: 2, .. })
+        ));
+    }
+
+    /// Tests splitting the header comment from the body.
+    #[test]
+    fn header_comment() {
+        let lines = vec!["#HEADER_START", "/* a */", "b", "#header_end", "c", "d"];
+        let (header, body) =
+            collect_header_comment(lines.into_iter().map(String::from).collect()).unwrap();
+        assert_eq!(vec!["/* a */", "b"], header);
+        assert_eq!(vec!["c", "d"], body);
+    }
+
+    /// Tests that blank lines and comments before the header are kept before
+    /// the header's lines.
+    #[test]
+    fn header_comment_after_banner() {
+        let lines = vec![
+            "",
+            "/* banner",
+            "*/",
+            "#HEADER_START",
+            "/* a */",
+            "#HEADER_END",
+            "b",
+        ];
+        let (header, body) =
+            collect_header_comment(lines.into_iter().map(String::from).collect()).unwrap();
+        assert_eq!(vec!["", "/* banner", "*/", "/* a */"], header);
+        assert_eq!(vec!["b"], body);
+        let header =
+            read_header(&mut "\n/* banner */\n#HEADER_START\n/* a */\n#HEADER_END\nb".as_bytes());
+        assert_eq!(vec!["", "/* banner */", "/* a */"], header.unwrap());
+        let header = read_header(&mut "/* banner */ a\n#HEADER_START\n#HEADER_END".as_bytes());
+        assert!(header.unwrap().is_empty());
+    }
+
+    /// Tests removing the `key: value` metadata lines from a header, except for
+    /// lines inside of comments.
+    #[test]
+    fn header_metadata() {
+        let header = vec![
+            "/* Author: me",
+            "*/",
+            "actor_area_base: 30000",
+            " unknown_key :x ",
+            "/* a */ seed: 5",
+            "not a key: 1",
+        ];
+        let (metadata, header) =
+            take_header_metadata(header.into_iter().map(String::from).collect());
+        assert_eq!(vec!["/* Author: me", "*/", "not a key: 1"], header);
+        assert_eq!(3, metadata.len());
+        assert_eq!("30000", metadata["actor_area_base"]);
+        assert_eq!("x", metadata["unknown_key"]);
+        assert!(matches!(
+            metadata_value(&metadata, "actor_area_base", 1),
+            Ok(30_000)
+        ));
+        assert!(matches!(metadata_value(&metadata, "missing", 1), Ok(1)));
+        assert!(matches!(
+            metadata_value(&metadata, "unknown_key", 1),
+            Err(PreprocessError::BadHeaderValue { .. })
+        ));
+    }
+
+    /// Tests trimming and reindenting the lines of the header.
+    #[test]
+    fn header_formats() {
+        let header = || {
+            ["\t/* Map  ", "", "\t  by me\t", "  ", "\t*/"]
+                .into_iter()
+                .map(String::from)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(header(), format_header(header(), HeaderFormat::Verbatim));
+        assert_eq!(
+            vec!["\t/* Map", "", "\t  by me", "", "\t*/"],
+            format_header(header(), HeaderFormat::Trimmed)
+        );
+        assert_eq!(
+            vec!["/* Map", "", "  by me", "", "*/"],
+            format_header(header(), HeaderFormat::Reindented)
+        );
+        assert_eq!("    a", expand_indentation("  \ta"));
+        assert_eq!("        a", expand_indentation("\t    a"));
+    }
+
+    /// Tests that lines without a header are returned unmodified.
+    #[test]
+    fn header_comment_absent() {
+        let lines = vec!["a", "#HEADER_START", "#HEADER_END"];
+        let (header, body) =
+            collect_header_comment(lines.into_iter().map(String::from).collect()).unwrap();
+        assert!(header.is_empty());
+        assert_eq!(vec!["a", "#HEADER_START", "#HEADER_END"], body);
+    }
+
+    /// Tests reading only the header from a file.
+    #[test]
+    fn read_header_only() -> Result<(), PreprocessError> {
+        let path = std::env::temp_dir().join("preprocessor_read_header_only.rms");
+        std::fs::write(&path, "#HEADER_START\n/* a */\n#HEADER_END\nb\n")?;
+        let header = read_header(&mut BufReader::new(File::open(&path)?));
+        std::fs::remove_file(&path)?;
+        assert_eq!(vec!["/* a */"], header?);
+        Ok(())
+    }
+
+    /// Tests processing a script held in memory, truncated by `#BREAK`.
+    #[test]
+    fn process_in_memory_script() -> Result<(), PreprocessError> {
+        let mut src = std::io::Cursor::new("/* comment */\n  a   b  \n\n#BREAK\nc\n");
+        let mut dest = vec![];
+        let truncation = process_script(&mut src, &mut dest, &Options::default())?;
+        assert_eq!("a b", String::from_utf8(dest).unwrap());
+        assert_eq!(
+            Some(Truncation {
+                line: 2,
+                dropped: 1
+            }),
+            truncation
+        );
+        Ok(())
+    }
+
+    /// Tests splitting a script with lone `\r` line endings into lines, while
+    /// a `\r` in a script with `\n` line endings is kept.
+    #[test]
+    fn read_carriage_return_lines() -> Result<(), PreprocessError> {
+        assert_eq!(vec!["a", "b c", "", "d"], split_lines("a\rb c\r\rd\r"));
+        assert_eq!(vec!["a", "b\rc"], split_lines("a\r\nb\rc\n"));
+        assert!(split_lines("").is_empty());
+        let mut dest = vec![];
+        process_script(
+            &mut "a\r#BREAK\rb".as_bytes(),
+            &mut dest,
+            &Options::default(),
+        )?;
+        assert_eq!("a", String::from_utf8(dest).unwrap());
+        Ok(())
+    }
+
+    /// Tests that only a line that is exactly the break keyword truncates the
+    /// output, and that the keyword may be changed.
+    #[test]
+    fn break_whole_line() -> Result<(), std::io::Error> {
+        let lines = || {
+            [
+                "actor_area before_break_zone",
+                "a #BREAK b",
+                " #break ",
+                "c",
+            ]
+            .into_iter()
+            .map(String::from)
+        };
+        let mut dest = vec![];
+        let truncation = write_until_break(lines(), &mut dest, DEFAULT_BREAK)?;
+        assert_eq!(
+            "actor_area before_break_zone\na #BREAK b",
+            String::from_utf8(dest).unwrap()
+        );
+        assert_eq!(
+            Some(Truncation {
+                line: 3,
+                dropped: 1
+            }),
+            truncation
+        );
+
+        let mut dest = vec![];
+        let truncation = write_until_break(lines(), &mut dest, "#STOP")?;
+        assert_eq!(4, String::from_utf8(dest).unwrap().lines().count());
+        assert_eq!(None, truncation);
+        Ok(())
+    }
+
+    /// Tests that lines which are not macros are returned unchanged.
+    #[test]
+    fn expand_non_macro() {
+        assert_eq!(
+            vec!["rockgen"],
+            expand_line(&numbered(&["rockgen"])[0]).unwrap()
+        );
+        assert_eq!(
+            vec!["land_position 1 2"],
+            expand_line(&numbered(&["land_position 1 2"])[0]).unwrap()
+        );
+    }
+
+    /// Tests that macro names are matched case-insensitively.
+    #[test]
+    fn expand_macro_case_insensitive() {
+        assert_eq!(
+            actorgen::vision(),
+            expand_line(&numbered(&["#vision"])[0]).unwrap()
+        );
+        assert_eq!(
+            actorgen::vision(),
+            expand_line(&numbered(&["#VISION"])[0]).unwrap()
+        );
+    }
+
+    /// Tests expanding `#ARENA_CIRCLE_GAPS` with a tolerance in radians.
+    #[test]
+    fn expand_tolerance_args() {
+        assert_eq!(
+            landgen::arena_circle_gaps(0.5),
+            expand_line(&numbered(&["#ARENA_CIRCLE_GAPS(0.5)"])[0]).unwrap()
+        );
+        assert_eq!(
+            landgen::arena_circle_gaps(landgen::ARENA_FOREST_TOLERANCE),
+            expand_line(&numbered(&["#ARENA_CIRCLE_GAPS"])[0]).unwrap()
+        );
+    }
+
+    /// Tests expanding `#VISION` with a revealer count and distance.
+    #[test]
+    fn expand_vision_args() {
+        assert_eq!(
+            actorgen::vision_with(8, 5),
+            expand_line(&numbered(&["#VISION(8, 5)"])[0]).unwrap()
+        );
+        assert!(matches!(
+            expand_line(&numbered(&["#VISION(8,x)"])[0]),
+            Err(PreprocessError::BadMacroArgs { line: 1, .. })
+        ));
+    }
+
+    /// Tests finding the `number_of_objects` above a threshold, along with the
+    /// threshold of `#WARN_OBJECT_COUNTS`.
+    #[test]
+    fn object_counts() {
+        let lines = numbered(&[
+            "create_object HOUSE {",
+            "number_of_objects 99",
+            "}",
+            "create_object GOLD { number_of_objects 50 }",
+            "create_object STONE { number_of_objects 51 }",
+            "create_object FORAGE { number_of_objects rnd(60,70) }",
+        ]);
+        assert_eq!(
+            vec![(2, 99), (5, 51)],
+            excessive_object_counts(&lines, OBJECT_COUNT_THRESHOLD)
+        );
+        let thresholds = numbered(&[
+            "#WARN_OBJECT_COUNTS",
+            "#warn_object_counts(5)",
+            "a",
+            "#WARN_OBJECT_COUNTS(x)",
+        ]);
+        assert_eq!(Some(50), object_count_threshold(&thresholds[0]).unwrap());
+        assert_eq!(Some(5), object_count_threshold(&thresholds[1]).unwrap());
+        assert_eq!(None, object_count_threshold(&thresholds[2]).unwrap());
+        assert!(matches!(
+            object_count_threshold(&thresholds[3]),
+            Err(PreprocessError::BadMacroArgs { line: 4, .. })
+        ));
+        assert_eq!(
+            vec!["a"],
+            texts(warn_object_counts(thresholds[..3].to_vec()).unwrap())
+        );
+    }
+
+    /// Tests the expansion of every macro without arguments against its
+    /// snapshot in `tests/snapshots`, named after the macro, so refactoring a
+    /// generator does not change its output by accident. Run with the
+    /// `UPDATE_SNAPSHOTS` environment variable set to write the snapshots of
+    /// the current expansions instead.
+    #[test]
+    fn macro_snapshots() -> Result<(), PreprocessError> {
+        let dir = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/snapshots");
+        let update = std::env::var_os("UPDATE_SNAPSHOTS").is_some();
+        for (name, _, expand) in MACROS.iter().filter(|&&(_, arity, _)| arity == 0) {
+            let path = dir.join(format!("{}.txt", name[1..].to_lowercase()));
+            let line = Line {
+                number: 1,
+                text: name.to_string(),
+            };
+            let expansion = expand(&[], &line)?.join("\n") + "\n";
+            if update {
+                std::fs::create_dir_all(&dir)?;
+                std::fs::write(&path, &expansion)?;
+                continue;
+            }
+            let snapshot = std::fs::read_to_string(&path)?;
+            if let Some((i, (expected, actual))) = snapshot
+                .lines()
+                .zip(expansion.lines())
+                .enumerate()
+                .find(|(_, (expected, actual))| expected != actual)
+            {
+                panic!(
+                    "{name} differs from {} at line {}:\n  expected `{expected}`\n  found `{actual}`",
+                    path.display(),
+                    i + 1
+                );
+            }
+            assert_eq!(
+                snapshot.lines().count(),
+                expansion.lines().count(),
+                "{name} differs from {} in length.",
+                path.display()
+            );
+        }
+        Ok(())
+    }
+
+    /// Tests collecting consts from anywhere in a script, with a redefined
+    /// const keeping its last value.
+    #[test]
+    fn collect_script_consts() {
+        let lines = numbered(&["#REPEAT(A)", "#const A 2", "#const B 7", "#const A 4"]);
+        let consts = collect_consts(&lines);
+        assert_eq!(2, consts.values.len());
+        assert_eq!(Some(&"4"), consts.values.get("A"));
+        assert!(consts.conditional.is_empty());
+        assert_eq!(
+            vec!["#REPEAT(4)", "#const A 2", "#const B 7", "#const A 4"],
+            texts(resolve_repeat_consts(lines).unwrap())
+        );
+    }
+
+    /// Tests that consts defined in the branches of random and conditional
+    /// blocks are not redefinitions, and cannot be used as repeat counts.
+    #[test]
+    fn conditional_consts() {
+        let lines = numbered(&[
+            "#const B 1",
+            "start_random",
+            "percent_chance 50 #const A 2",
+            "percent_chance 50 #const A 3",
+            "end_random",
+            "if TINY_MAP #const C 1 elseif SMALL_MAP #const C 2 endif",
+            "#REPEAT(B)",
+            "#REPEAT(A)",
+        ]);
+        let consts = collect_consts(&lines);
+        assert_eq!(HashSet::from(["A", "C"]), consts.conditional);
+        assert!(matches!(
+            resolve_repeat_consts(lines),
+            Err(PreprocessError::ConditionalRepeatCount { line: 8, name }) if name == "A"
+        ));
+    }
+
+    /// Tests finding objects whose minimum distance to players is greater than
+    /// their maximum distance.
+    #[test]
+    fn inverted_object_distances() {
+        let lines = numbered(&[
+            "create_object VILLAGER {",
+            "min_distance_to_players 6",
+            "max_distance_to_players 4",
+            "}",
+            "create_object GOLD { min_distance_to_players 4 max_distance_to_players 6 }",
+            "create_object STONE { min_distance_to_players 9 }",
+            "create_object SHEEP { max_distance_to_players 2 min_distance_to_players 3 }",
+            "max_distance_to_players 1",
+        ]);
+        assert_eq!(vec![(1, 6, 4), (7, 3, 2)], inverted_distances(&lines));
+        assert!(check_object_distances(&lines, false).is_ok());
+        assert!(matches!(
+            check_object_distances(&lines, true),
+            Err(PreprocessError::InvertedDistances {
+                line: 1,
+                min: 6,
+                max: 4
+            })
+        ));
+    }
+
+    /// Tests detecting lines with the form of an unknown macro.
+    #[test]
+    fn unknown_macros() {
+        assert!(is_unknown_macro("#MYTHING(3,4)"));
+        assert!(is_unknown_macro("  #VISON(8,5) "));
+        assert!(!is_unknown_macro("#MYTHING"));
+        assert!(!is_unknown_macro("#repeat(2)"));
+        assert!(!is_unknown_macro("#SCALE(1.5)"));
+        assert!(!is_unknown_macro("#(3)"));
+        assert!(!is_unknown_macro(
+            "create_land { land_position rnd(1,2) 3 }"
+        ));
+        let lines = numbered(&["a", "#MYTHING", "#MYTHING(3,4)"]);
+        assert!(matches!(
+            check_unknown_macros(&lines),
+            Err(PreprocessError::UnknownMacro { line: 3, .. })
+        ));
+    }
+
+    /// Tests expanding `#BFLANDS` with a pond distance.
+    #[test]
+    fn expand_bf_lands_args() {
+        assert_eq!(
+            landgen::bf_lands_2(Directions::new(4), 30.0, 10.0, landgen::BF_POND_COUNT),
+            expand_line(&numbered(&["#BFLANDS(4, 30, 10)"])[0]).unwrap()
+        );
+    }
+
+    /// Tests expanding `#CENTER_OBJECT` with and without a radius.
+    #[test]
+    fn expand_center_object() {
+        let expected = vec![
+            "create_actor_area 50 50 center_object_0 0",
+            "create_object RELIC {",
+            "set_gaia_object_only",
+            "number_of_objects 1",
+            "actor_area_to_place_in center_object_0",
+            "}",
+        ];
+        assert_eq!(
+            expected,
+            expand_line(&numbered(&["#CENTER_OBJECT(RELIC,1)"])[0]).unwrap()
+        );
+        assert_eq!(
+            actorgen::center_object("MONUMENT", 3, 4),
+            expand_line(&numbered(&["#CENTER_OBJECT(MONUMENT, 3, 4)"])[0]).unwrap()
+        );
+    }
+
+    /// Tests that the line of `#ACTOR_AREA` is numbered by
+    /// `substitute_actor_area_names` with the name in the expected position.
+    #[test]
+    fn actor_area_round_trip() {
+        let mut lines = expand_line(&numbered(&["#ACTOR_AREA(ruins, 20, 30, 4)"])[0]).unwrap();
+        assert_eq!(vec!["create_actor_area 20 30 ruins 4"], lines);
+        lines.push("actor_area_to_place_in ruins".to_string());
+        let (lines, actor_areas) = substitute_actor_area_names(lines, 20_000);
+        assert_eq!(
+            vec![
+                "create_actor_area 20 30 20000 4",
+                "actor_area_to_place_in 20000"
+            ],
+            lines
+        );
+        assert_eq!(Some(20_000), actor_areas.get("ruins"));
+    }
+
+    /// Tests finding labels that are defined but never referenced, and labels
+    /// that are referenced but never defined, numbered by the lines of the
+    /// output after a multi-line expansion.
+    #[test]
+    fn orphan_label_lines() {
+        let lines = vec![
+            "start_random",
+            "percent_chance 50 #define A",
+            "percent_chance 50 #define B",
+            "end_random",
+            "if A",
+            "elseif C",
+            "elseif TINY_MAP",
+            "endif",
+        ];
+        let lines: Vec<String> = lines.into_iter().map(String::from).collect();
+        assert_eq!((vec![(3, "B")], vec![(6, "C")]), orphan_labels(&lines));
+
+        let lines: Vec<String> = lines
+            .into_iter()
+            .map(|line| line.replace('C', "B"))
+            .collect();
+        assert_eq!((vec![], vec![]), orphan_labels(&lines));
+        let lines: Vec<String> = [
+            "start_random\npercent_chance 50 #define A\nend_random",
+            "if B",
+        ]
+        .into_iter()
+        .map(String::from)
+        .collect();
+        assert_eq!((vec![(2, "A")], vec![(4, "B")]), orphan_labels(&lines));
+        let (passes, lines) = take_passes(numbered(&["#CHECK_LABELS", "a"]));
+        assert!(passes.labels);
+        assert_eq!(vec!["a"], texts(lines));
+    }
+
+    /// Tests reporting the opener of an unclosed block and a stray `}` of the
+    /// output, ignoring braces in comments.
+    #[test]
+    fn unbalanced_braces() {
+        let lines: Vec<String> = ["/* { */", "create_object A {", "}", "create_land {"]
+            .into_iter()
+            .map(String::from)
+            .collect();
+        assert!(check_braces(&lines[..3]).is_ok());
+        assert!(matches!(
+            check_braces(&lines),
+            Err(PreprocessError::UnclosedBlock { line: 4, .. })
+        ));
+        let lines = vec!["create_land { a }\n}".to_string()];
+        assert!(matches!(
+            check_braces(&lines),
+            Err(PreprocessError::UnexpectedCloseBrace { line: 2 })
+        ));
+    }
+
+    /// Tests that a macro given a number of arguments that it does not take is
+    /// an error naming the expected counts, while unknown names pass through.
+    #[test]
+    fn macro_arg_count() {
+        let error = expand_line(&numbered(&["#CIRCLE_LABELS(30,112,oops)"])[0]).unwrap_err();
+        assert_eq!(
+            "Line 1: `#CIRCLE_LABELS` expected 2 arguments, got 3.",
+            error.to_string()
+        );
+        let error = expand_line(&numbered(&["#SQUARE_LABELS(1)"])[0]).unwrap_err();
+        assert_eq!(
+            "Line 1: `#SQUARE_LABELS` expected 2 or 3 arguments, got 1.",
+            error.to_string()
+        );
+        for inset in [50, 60] {
+            let line = format!("#SQUARE_LABELS(30,112,{inset})");
+            let error = expand_line(&numbered(&[&line])[0]).unwrap_err();
+            assert_eq!(
+                format!("Line 1: invalid macro arguments: `{line}`."),
+                error.to_string()
+            );
+        }
+        assert!(expand_line(&numbered(&["#SQUARE_LABELS(30,112,49)"])[0]).is_ok());
+        for line in [
+            "#SQUARE_LABELS(0,200,10)",
+            "#SQUARE_POSITION_P2(0,200)",
+            "#MIGRA_LABELS(0,181)",
+            "#CIRCLE_LABELS(100,112)",
+            "#CIRCLE_SETUP(30,270)",
+            "#CIRCLE_POSITION_P2(0,112)",
+        ] {
+            assert!(matches!(
+                expand_line(&numbered(&[line])[0]),
+                Err(PreprocessError::BadMacroArgs { line: 1, .. })
+            ));
+        }
+        assert!(expand_line(&numbered(&["#SQUARE_LABELS(0,112,10)"])[0]).is_ok());
+        let error = expand_line(&numbered(&["#FOURSEASONSLAKES(1,2)"])[0]).unwrap_err();
+        assert!(matches!(
+            error,
+            PreprocessError::MacroArgCount { ref expected, found: 2, .. } if expected == &[0, 1]
+        ));
+        assert_eq!(
+            actorgen::vision(),
+            expand_line(&numbered(&["#VISION()"])[0]).unwrap()
+        );
+        assert_eq!(
+            vec!["#NOT_A_MACRO(1,2,3)"],
+            expand_line(&numbered(&["#NOT_A_MACRO(1,2,3)"])[0]).unwrap()
+        );
+    }
+
+    /// Tests that no two entries of `MACROS` share a name and a number of
+    /// arguments, so each is reachable.
+    #[test]
+    fn macro_entries_distinct() {
+        let mut entries = HashSet::new();
+        for &(name, arity, _) in MACROS {
+            assert!(
+                entries.insert((name.to_uppercase(), arity)),
+                "{name} takes {arity} arguments twice."
+            );
+        }
+    }
+
+    /// Tests that the first 1000 labels generated by `next_label` are distinct
+    /// and are each an underscore followed by capital letters.
+    #[test]
+    fn next_label_sequence() {
+        let mut labels = vec![next_label(&None)];
+        while labels.len() < 1000 {
+            let label = next_label(&labels.last().map(String::as_str));
+            labels.push(label);
+        }
+        assert_eq!(["_A", "_B"], labels[..2]);
+        assert_eq!(["_Z", "_ZA"], labels[25..27]);
+        for label in &labels {
+            let letters = label.strip_prefix('_').unwrap();
+            assert!(!letters.is_empty() && letters.bytes().all(|c| c.is_ascii_uppercase()));
+        }
+        let distinct: HashSet<&String> = labels.iter().collect();
+        assert_eq!(labels.len(), distinct.len());
+    }
+
+    /// Tests expanding `#RINGPLAYERS`.
+    #[test]
+    fn expand_ring_players() {
+        assert_eq!(
+            landgen::ring_players(6, 35, 12),
+            expand_line(&numbered(&["#RINGPLAYERS(6, 35, 12)"])[0]).unwrap()
+        );
+        let invalid = [
+            "#RINGPLAYERS(2,10,10)",
+            "#RINGPLAYERS(4,11,10)",
+            "#RINGPLAYERS(0,35,12)",
+            "#RINGPLAYERS(4,51,12)",
+        ];
+        for line in invalid {
+            let error = expand_line(&numbered(&[line])[0]).unwrap_err();
+            assert!(
+                matches!(error, PreprocessError::BadMacroArgs { .. }),
+                "{line}"
+            );
+        }
+    }
+
+    /// Tests parsing circle angles in degrees and in turns.
+    #[test]
+    fn angle_units() {
+        let line = &numbered(&["#CIRCLE_POSITION_P2(30, 0.35t)"])[0];
+        assert_eq!(112, parse_angle(" 112", line).unwrap());
+        assert_eq!(126, parse_angle("0.35t", line).unwrap());
+        assert_eq!(90, parse_angle(" 0.25t ", line).unwrap());
+        for angle in ["t", "-0.5t", "1.5", "0.35tt", "NaNt"] {
+            assert!(parse_angle(angle, line).is_err(), "{angle}");
+        }
+        assert_eq!(
+            circlegen::list_p2_positions(30.0, 126),
+            expand_line(line).unwrap()
+        );
+    }
+
+    /// Tests expanding `#FILL`.
+    #[test]
+    fn expand_fill() {
+        assert_eq!(
+            landgen::fill_land("GRASS", 75),
+            expand_line(&numbered(&["#FILL(GRASS, 75)"])[0]).unwrap()
+        );
+        let error = expand_line(&numbered(&["#FILL(GRASS,150)"])[0]).unwrap_err();
+        assert_eq!(
+            "Line 1: invalid macro arguments: `#FILL(GRASS,150)`.",
+            error.to_string()
+        );
+    }
+
+    /// Tests expanding `#RING_OBJECTS`.
+    #[test]
+    fn expand_ring_objects() {
+        assert_eq!(
+            circlegen::ring_object_positions("RUINS", 6, 30),
+            expand_line(&numbered(&["#RING_OBJECTS(RUINS, 6, 30)"])[0]).unwrap()
+        );
+    }
+
+    /// Tests that `#SNAKELANDS` without arguments uses the default wave and
+    /// that the arguments are passed through.
+    #[test]
+    fn expand_snake_lands() {
+        let defaults = expand_line(&numbered(&["#SNAKELANDS(SHALLOW, 1, 2.0, 1.0)"])[0]);
+        assert_eq!(
+            expand_line(&numbered(&["#SNAKELANDS"])[0]).unwrap(),
+            defaults.unwrap()
+        );
+        assert_eq!(
+            landgen::snake_lands(Directions::default(), "ICE", 2, 3.0, 0.25),
+            expand_line(&numbered(&["#SNAKELANDS(ICE, 2, 3, 0.25)"])[0]).unwrap()
+        );
+    }
+
+    /// Tests expanding `#SET_ATTR` and rejecting invalid targets and
+    /// attributes.
+    #[test]
+    fn expand_set_attr() {
+        assert_eq!(
+            vec!["effect_amount SET_ATTRIBUTE PHOFF ATTR_HITPOINTS -1"],
+            expand_line(&numbered(&["#SET_ATTR(PHOFF, ATTR_HITPOINTS, -1)"])[0]).unwrap()
+        );
+        for line in [
+            "#SET_ATTR(PHOFF, HITPOINTS, 0)",
+            "#SET_ATTR(PH OFF, ATTR_HITPOINTS, 0)",
+            "#SET_ATTR(PHOFF, ATTR_HITPOINTS, x)",
+        ] {
+            assert!(matches!(
+                expand_line(&numbered(&[line])[0]),
+                Err(PreprocessError::BadMacroArgs { .. })
+            ));
+        }
+    }
+
+    /// Tests extracting the instruction and range of `rnd` lines, including
+    /// spaces inside of the `rnd` and arguments before it.
+    #[test]
+    fn extract_random_lines() {
+        let lines = numbered(&[
+            "number_of_tiles rnd(80,90)",
+            "base_elevation rnd(4, 6)",
+            "land_position 30 rnd( 10 , 20 )",
+            "land_position rnd(4,x) 5",
+        ]);
+        assert_eq!(
+            ("number_of_tiles", 80, 90),
+            extract_random_line(&lines[0]).unwrap()
+        );
+        assert_eq!(
+            ("base_elevation", 4, 6),
+            extract_random_line(&lines[1]).unwrap()
+        );
+        assert_eq!(
+            ("land_position 30", 10, 20),
+            extract_random_line(&lines[2]).unwrap()
+        );
+        assert!(matches!(
+            extract_random_line(&lines[3]),
+            Err(PreprocessError::BadRnd { line: 4, .. })
+        ));
+    }
+
+    /// Tests that only `rnd(` tokens are extracted, not names containing `rnd`.
+    #[test]
+    fn extract_rnd_tokens() {
+        let lines = vec![
+            "#EXTRACT_RND",
+            "<ELEVATION_GENERATION>",
+            "create_actor_area 50 50 grnd_region 3",
+            "actor_area_to_place_in grnd_region",
+            "terrain_to_place_on grnd(1,2)",
+            "number_of_objects rnd(1,2)",
+        ];
+        let lines = texts(extract_rnd(numbered(&lines)).unwrap());
+        assert_eq!(
+            vec![
+                "start_random",
+                "percent_chance 50 #define _A_0",
+                "percent_chance 50 #define _A_1",
+                "end_random",
+            ],
+            lines[0].lines().collect::<Vec<_>>()
+        );
+        assert_eq!(
+            vec![
+                "<ELEVATION_GENERATION>",
+                "create_actor_area 50 50 grnd_region 3",
+                "actor_area_to_place_in grnd_region",
+                "terrain_to_place_on grnd(1,2)",
+            ],
+            lines[1..5]
+        );
+        assert_eq!(
+            "if _A_0\nnumber_of_objects 1\nelseif _A_1\nnumber_of_objects 2\nendif",
+            lines[5]
+        );
+        assert_eq!(6, lines.len());
+    }
+
+    /// Tests extracting `rnd` commands after a marker set by a directive, and
+    /// from every line if the marker is missing.
+    #[test]
+    fn extract_rnd_after_marker() {
+        let lines = vec![
+            "#EXTRACT_RND_AFTER OBJECTS_GENERATION",
+            "<ELEVATION_GENERATION>",
+            "base_elevation rnd(1,2)",
+            "<OBJECTS_GENERATION>",
+            "number_of_objects rnd(3,4)",
+        ];
+        let lines = texts(extract_rnd(numbered(&lines)).unwrap());
+        assert_eq!(5, lines.len());
+        assert_eq!(
+            vec![
+                "<ELEVATION_GENERATION>",
+                "base_elevation rnd(1,2)",
+                "<OBJECTS_GENERATION>",
+            ],
+            lines[1..4]
+        );
+        assert!(lines[4].starts_with("if _A_0\nnumber_of_objects 3"));
+
+        let lines = vec!["#EXTRACT_RND", "base_elevation rnd(1,2)"];
+        let lines = texts(extract_rnd(numbered(&lines)).unwrap());
+        assert_eq!(2, lines.len());
+        assert!(lines[1].starts_with("if _A_0\nbase_elevation 1"));
+    }
+
+    /// Tests counting `create_object` blocks that differ from the previous
+    /// block in at most one line.
+    #[test]
+    fn similar_objects() {
+        let lines: Vec<String> = [
+            "create_object PHON {",
+            "actor_area 1",
+            "actor_area_radius 2",
+            "}",
+            "create_object PHON {",
+            "actor_area 2",
+            "actor_area_radius 2",
+            "}",
+            "create_object PHON {",
+            "actor_area 3",
+            "actor_area_radius 3",
+            "}",
+            "create_object PHON { actor_area 3 actor_area_radius 3 }",
+            "create_object PHON {",
+            "actor_area 3",
+            "actor_area_radius 3",
+            "}",
+        ]
+        .into_iter()
+        .map(String::from)
+        .collect();
+        assert_eq!(5, object_blocks(&lines).len());
+        assert_eq!(1, count_similar_objects(&lines));
+        assert_eq!(0, count_similar_objects(&actorgen::vision()));
+    }
+
+    /// Tests rendering the watermark and keeping it a single valid comment.
+    #[test]
+    fn watermark_text() {
+        assert_eq!(
+            "GENERATED FROM a.rms AT 12 - DO NOT EDIT",
+            render_watermark(DEFAULT_WATERMARK, "a.rms", "12")
+        );
+        assert_eq!("/* a * / b / * c d */", watermark_comment("a */ b /* c\nd"));
+    }
+
+    /// Tests scaling exact and random land positions and actor area radii.
+    #[test]
+    fn scale_land_positions() {
+        assert_eq!(
+            "land_position 60 rnd(20,40)",
+            scale_line("land_position 30 rnd(10,20)", 2.0)
+        );
+        assert_eq!(
+            "create_land { land_position 100 2 base_size 5 }",
+            scale_line("create_land { land_position 60 1 base_size 5 }", 2.0)
+        );
+        assert_eq!(
+            "actor_area_radius 5",
+            scale_line("actor_area_radius 3", 1.5)
+        );
+        assert_eq!("land_position a b", scale_line("land_position a b", 2.0));
+        let lines = vec!["#scale(0.5)", "land_position 30 31"];
+        assert_eq!(
+            vec!["land_position 15 16"],
+            texts(scale_positions(numbered(&lines)).unwrap())
+        );
+        for factor in ["-1", "x", "inf", ""] {
+            let lines = numbered(&["a", &format!("#SCALE({factor})")]);
+            assert!(matches!(
+                scale_positions(lines),
+                Err(PreprocessError::BadScaleFactor { line: 2, .. })
+            ));
+        }
+    }
+
+    /// Tests that actor area names are listed by ID number.
+    #[test]
+    fn actor_area_ids() {
+        let lines = vec![
+            "create_actor_area 50 50 tc_r0 3",
+            "actor_area box0",
+            "avoid_actor_area tc_r0",
+        ];
+        let (lines, actor_areas) =
+            substitute_actor_area_names(lines.into_iter().map(String::from).collect(), 20_000);
+        assert_eq!("avoid_actor_area 20000", lines[2]);
+        assert_eq!(
+            vec!["20000 tc_r0", "20001 box0"],
+            actor_area_table(&actor_areas)
+        );
+    }
+
+    /// Tests that actor area ID numbers follow the order in which the names
+    /// first occur, and that processing the same lines twice gives identical
+    /// output.
+    #[test]
+    fn actor_area_ids_deterministic() {
+        let lines: Vec<String> = (0..50)
+            .rev()
+            .map(|i| format!("create_actor_area 10 10 area{i} 1"))
+            .chain(["actor_area area49".to_string()])
+            .collect();
+        let (first, actor_areas) = substitute_actor_area_names(lines.clone(), 100);
+        let (second, _) = substitute_actor_area_names(lines, 100);
+        assert_eq!(first, second);
+        assert_eq!("create_actor_area 10 10 100 1", first[0]);
+        assert_eq!("actor_area 100", first[50]);
+        assert_eq!(Some(149), actor_areas.get("area0"));
+        let ids: Vec<u32> = actor_areas.iter().map(|(id, _)| id).collect();
+        assert_eq!((100..150).collect::<Vec<_>>(), ids);
+    }
+
+    /// Tests reflecting exact and random land positions.
+    #[test]
+    fn mirror_land_positions() {
+        assert_eq!(
+            Some(String::from("land_position 70 rnd(10,20)")),
+            mirror_line("land_position 30 rnd(10,20)", Axis::X)
+        );
+        assert_eq!(
+            Some(String::from("land_position 30 rnd(80,90)")),
+            mirror_line("land_position 30 rnd(10,20)", Axis::Y)
+        );
+        assert_eq!(
+            Some(String::from(
+                "create_land { land_position 5 60 base_size 1 }"
+            )),
+            mirror_line("create_land { land_position 5 40 base_size 1 }", Axis::Y)
+        );
+        assert_eq!(None, mirror_line("base_size 1", Axis::X));
+    }
+
+    /// Tests that `#MIRROR_X` copies lands with positions and leaves other
+    /// lines unchanged.
+    #[test]
+    fn mirror_lands_x() {
+        let lines = [
+            "#MIRROR_X",
+            "<LAND_GENERATION>",
+            "create_land {",
+            "land_position rnd(10,20) 50",
+            "}",
+            "create_land { terrain_type WATER }",
+            "create_land { land_position 25 50 }",
+        ];
+        let lines = numbered(&lines);
+        assert_eq!(
+            vec![
+                "<LAND_GENERATION>",
+                "create_land {",
+                "land_position rnd(10,20) 50",
+                "}",
+                "create_land {",
+                "land_position rnd(80,90) 50",
+                "}",
+                "create_land { terrain_type WATER }",
+                "create_land { land_position 25 50 }",
+                "create_land { land_position 75 50 }",
+            ],
+            texts(mirror_lands(lines).unwrap())
+        );
+    }
+
+    /// Tests that a repeated mirror flag reflects the lands only once, and that
+    /// an unclosed land is an error.
+    #[test]
+    fn mirror_lands_repeated_flags() {
+        let lines = [
+            "#MIRROR_X",
+            "#MIRROR_Y",
+            "#MIRROR_X",
+            "create_land { land_position 10 20 }",
+        ];
+        assert_eq!(
+            vec![
+                "create_land { land_position 10 20 }",
+                "create_land { land_position 10 80 }",
+                "create_land { land_position 90 20 }",
+                "create_land { land_position 90 80 }",
+            ],
+            texts(mirror_lands(numbered(&lines)).unwrap())
+        );
+        let lines = ["#MIRROR_X", "a", "create_land {", "land_position 10 20"];
+        assert!(matches!(
+            mirror_lands(numbered(&lines)),
+            Err(PreprocessError::UnclosedLand { line: 3 })
+        ));
+    }
+
+    /// Tests that the Four Seasons lakes accept an optional `land_id`.
+    #[test]
+    fn expand_four_seasons_lakes() {
+        assert_eq!(
+            landgen::four_seasons_lakes(None),
+            expand_line(&numbered(&["#FOURSEASONSLAKES"])[0]).unwrap()
+        );
+        assert_eq!(
+            landgen::four_seasons_lakes(Some(30)),
+            expand_line(&numbered(&["#FOURSEASONSLAKES(30)"])[0]).unwrap()
+        );
+    }
+
+    /// Tests that the Four Seasons divider accepts a terrain and a positive
+    /// thickness, defaulting to a single-tile rock divider.
+    #[test]
+    fn expand_four_seasons_divider() {
+        assert_eq!(
+            landgen::four_seasons_lands("DLC_ROCK", 1),
+            expand_line(&numbered(&["#FOURSEASONSLANDS"])[0]).unwrap()
+        );
+        assert_eq!(
+            landgen::four_seasons_lands("WATER", 2),
+            expand_line(&numbered(&["#FOURSEASONSLANDS(WATER, 2)"])[0]).unwrap()
+        );
+        assert!(matches!(
+            expand_line(&numbered(&["#FOURSEASONSLANDS(WATER,0)"])[0]),
+            Err(PreprocessError::BadMacroArgs { line: 1, .. })
+        ));
+    }
 }
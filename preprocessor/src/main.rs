@@ -1,11 +1,22 @@
 //! Build script for TTL2 maps.
 
 use std::{
+    collections::BTreeSet,
     fs::{self, File},
-    io::{BufReader, BufWriter},
-    path::Path,
+    io::{self, BufReader, BufWriter, Write},
+    num::NonZeroUsize,
+    path::{Path, PathBuf},
+    process::ExitCode,
+    sync::{mpsc, Mutex},
+    thread,
+    time::{Duration, SystemTime, UNIX_EPOCH},
 };
 
+use notify::{Event, EventKind, RecursiveMode, Watcher};
+use preprocessor::{BuildMode, HeaderFormat, Options, PreprocessError, DEFAULT_WATERMARK};
+use serde::Serialize;
+use similar::TextDiff;
+
 /// Relative path to the map scripts.
 const SCRIPTS: &str = "../scripts";
 
@@ -16,41 +27,625 @@ const RMS_DIR: &str =
 /// Absolute path to the TC mod directory.
 const TC_DIR: &str = "C:/Users/twest/Games/Age of Empires 2 DE/76561198003545293/mods/local/TCMapsTest/resources/_common/random-map-scripts";
 
-/// Runs preprocessing steps and writes the output to the test mod.
-/// Overwrites all files in the test mod.
-/// Does not remove unnecessary files from the test mod.
-fn main() -> std::io::Result<()> {
+/// The time to wait for further changes after a script changes in watch mode,
+/// so a burst of saves rebuilds the script once.
+const DEBOUNCE: Duration = Duration::from_millis(200);
+
+/// Returns the path of the scripts directory.
+fn scripts_dir() -> &'static Path {
     // Supports running from different locations.
-    let paths = match fs::read_dir(SCRIPTS) {
-        Ok(ps) => ps,
-        Err(_) => fs::read_dir("scripts").unwrap(),
-    };
+    match Path::new(SCRIPTS).is_dir() {
+        true => Path::new(SCRIPTS),
+        false => Path::new("scripts"),
+    }
+}
 
-    let mut directories = vec![paths];
+/// Returns the paths of every map script in the scripts directory and its
+/// subdirectories, sorted, so scripts are processed in a consistent order.
+fn script_paths() -> io::Result<Vec<PathBuf>> {
+    let mut scripts = vec![];
+    let mut directories = vec![fs::read_dir(scripts_dir())?];
     while let Some(dir) = directories.pop() {
         for path in dir {
-            let path = path?;
-
-            let src_path = path.path();
+            let src_path = path?.path();
             if src_path.is_dir() {
                 directories.push(fs::read_dir(src_path)?);
-                continue;
+            } else {
+                scripts.push(src_path);
             }
-            let src_file = File::open(src_path)?;
-            let mut src_reader = BufReader::new(src_file);
+        }
+    }
+    scripts.sort();
+    Ok(scripts)
+}
 
-            let map_name = path.file_name();
-            let str_name = map_name.to_str().unwrap();
-            let out_path = if str_name.starts_with("TC") {
-                TC_DIR
-            } else {
-                RMS_DIR
+/// Returns the path to which the map script named `map_name` is written.
+fn dest_path(map_name: &str) -> PathBuf {
+    let out_path = if map_name.starts_with("TC") {
+        TC_DIR
+    } else {
+        RMS_DIR
+    };
+    Path::new(out_path).join(map_name)
+}
+
+/// Returns the unified diff from the `old` output at `path` to the `new`
+/// output, or `None` if they are identical.
+fn output_diff(path: &Path, old: &str, new: &str) -> Option<String> {
+    if old == new {
+        return None;
+    }
+    let path = path.display().to_string();
+    let diff = TextDiff::from_lines(old, new)
+        .unified_diff()
+        .header(&path, &path)
+        .to_string();
+    Some(diff)
+}
+
+/// The command line flags that are followed by a value. The template of
+/// `--watermark` is optional, so it is not included, see `is_watermark_template`.
+const VALUE_FLAGS: [&str; 6] = [
+    "--jobs",
+    "--players",
+    "--header-format",
+    "--break",
+    "--file",
+    "--emit-manifest",
+];
+
+/// Returns whether the argument `arg` following `--watermark` is its template.
+/// Flags, existing files, and `.rms` paths are not templates, so
+/// `--watermark map.rms` is a watermark with the default template followed by
+/// the path of a script.
+fn is_watermark_template(arg: &str) -> bool {
+    !arg.starts_with("--") && !arg.ends_with(".rms") && !Path::new(arg).is_file()
+}
+
+/// Returns the template of `--watermark` in `args`, `Some(DEFAULT_WATERMARK)`
+/// if it is given without a template, or `None` if it is not given.
+fn watermark_template(args: &[String]) -> Option<&str> {
+    let i = args.iter().position(|arg| arg == "--watermark")?;
+    let template = args.get(i + 1).filter(|arg| is_watermark_template(arg));
+    Some(template.map_or(DEFAULT_WATERMARK, String::as_str))
+}
+
+/// Returns the first argument of `args` that is neither a flag nor the value
+/// of a flag, such as the path of a single script.
+fn positional_arg(args: &[String]) -> Option<&str> {
+    let mut after_value_flag = false;
+    let mut after_watermark = false;
+    for arg in args {
+        if arg.starts_with("--") {
+            after_value_flag = VALUE_FLAGS.contains(&arg.as_str());
+            after_watermark = arg == "--watermark";
+        } else if after_value_flag || after_watermark && is_watermark_template(arg) {
+            after_value_flag = false;
+            after_watermark = false;
+        } else {
+            return Some(arg);
+        }
+    }
+    None
+}
+
+/// The record of a script processed by a build, written to the manifest of
+/// `--emit-manifest`.
+#[derive(Serialize)]
+struct ManifestEntry {
+    /// The path of the script.
+    source: String,
+    /// The path to which the output is written, or `-` if it is not written to
+    /// a file, as with `--print` or `--diff`.
+    destination: String,
+    /// The number of bytes of the output.
+    bytes: u64,
+    /// The number of lines of the output, after any truncation by `#BREAK`.
+    line_count: u64,
+}
+
+/// A writer that counts the bytes and lines written to `inner`.
+struct CountingWriter<W> {
+    /// The writer to which the output is passed.
+    inner: W,
+    /// The number of bytes written.
+    bytes: u64,
+    /// The number of newlines written.
+    newlines: u64,
+    /// The last byte written, if any.
+    last: Option<u8>,
+}
+
+impl<W> CountingWriter<W> {
+    /// Returns a writer counting the output written to `inner`.
+    fn new(inner: W) -> Self {
+        CountingWriter {
+            inner,
+            bytes: 0,
+            newlines: 0,
+            last: None,
+        }
+    }
+
+    /// Returns the number of lines written, counting a last line without a
+    /// trailing newline.
+    fn line_count(&self) -> u64 {
+        self.newlines + u64::from(self.last.is_some_and(|byte| byte != b'\n'))
+    }
+}
+
+impl<W: Write> Write for CountingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        let written = &buf[..n];
+        self.bytes += n as u64;
+        self.newlines += written.iter().filter(|&&byte| byte == b'\n').count() as u64;
+        self.last = written.last().copied().or(self.last);
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Returns the value following the command line flag `flag` in `args`, or
+/// `None` if the flag is not given.
+/// Returns an error if the flag is the last argument or is followed by another
+/// flag, so a flag is never silently ignored.
+fn flag_value<'a>(args: &'a [String], flag: &str) -> io::Result<Option<&'a str>> {
+    let Some(i) = args.iter().position(|arg| arg == flag) else {
+        return Ok(None);
+    };
+    match args.get(i + 1) {
+        Some(value) if !value.starts_with("--") => Ok(Some(value)),
+        _ => {
+            let message = format!("`{flag}` requires a value.");
+            Err(io::Error::new(io::ErrorKind::InvalidInput, message))
+        }
+    }
+}
+
+/// The settings shared by every script of a build.
+struct Build<'a> {
+    /// Whether debug-only lines are kept.
+    mode: BuildMode,
+    /// Whether the output is written to stdout instead of the test mod.
+    print: bool,
+    /// Whether the changes to the test mod are printed instead of written.
+    diff: bool,
+    /// Whether details of the processing are printed.
+    verbose: bool,
+    /// The number of players for `#IF_PLAYERS` blocks, if any.
+    players: Option<u32>,
+    /// Whether unknown macros with arguments are errors.
+    strict: bool,
+    /// How the lines of the header comment are formatted.
+    header_format: HeaderFormat,
+    /// The keyword of the line at which the output is truncated, if not
+    /// `#BREAK`.
+    break_keyword: Option<&'a str>,
+    /// The template of the watermark comment, if any.
+    watermark: Option<&'a str>,
+    /// Whether each output records the hash of its source.
+    stamp: bool,
+    /// The time of the build, for the watermark.
+    time: String,
+    /// The path to which the manifest of the build is written, if any.
+    manifest_path: Option<&'a str>,
+    /// The records of the processed scripts, collected if a manifest is
+    /// written.
+    manifest: Mutex<Vec<ManifestEntry>>,
+}
+
+impl Build<'_> {
+    /// Processes the script at `src_path` and writes its output.
+    fn process(&self, src_path: &Path) -> Result<(), PreprocessError> {
+        let src_file = File::open(src_path)?;
+        let mut src_reader = BufReader::new(src_file);
+
+        let map_name = src_path.file_name().unwrap();
+        let str_name = map_name.to_str().unwrap();
+        let options = Options {
+            mode: self.mode,
+            watermark: self
+                .watermark
+                .map(|template| preprocessor::render_watermark(template, str_name, &self.time)),
+            verbose: self.verbose,
+            players: self.players,
+            strict: self.strict,
+            header_format: self.header_format,
+            break_keyword: self.break_keyword.map(String::from),
+            stamp: self.stamp,
+        };
+        if self.verbose {
+            eprintln!("Processing {str_name}.");
+        }
+        let (truncation, destination, counts) = if self.print {
+            // Holds the lock for the whole script, so outputs are not interleaved.
+            let mut dest_writer = CountingWriter::new(io::stdout().lock());
+            let truncation =
+                preprocessor::process_script(&mut src_reader, &mut dest_writer, &options)?;
+            let counts = (dest_writer.bytes, dest_writer.line_count());
+            // Ends the script's last line, so consecutive scripts are not joined.
+            writeln!(dest_writer.inner)?;
+            (truncation, String::from("-"), counts)
+        } else if self.diff {
+            let dest_path = dest_path(str_name);
+            let mut output = CountingWriter::new(vec![]);
+            let truncation = preprocessor::process_script(&mut src_reader, &mut output, &options)?;
+            let old = match fs::read_to_string(&dest_path) {
+                Ok(old) => old,
+                // A script that was never written is diffed as entirely new.
+                Err(e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+                Err(e) => return Err(e.into()),
             };
-            let dest_path = Path::new(out_path).join(map_name);
-            let dest_file = File::create(dest_path)?;
-            let mut dest_writer = BufWriter::new(dest_file);
-            preprocessor::process_script(&mut src_reader, &mut dest_writer)?;
+            let new = String::from_utf8_lossy(&output.inner);
+            if let Some(diff) = output_diff(&dest_path, &old, &new) {
+                // Prints the diff at once, so diffs are not interleaved.
+                print!("{diff}");
+            }
+            let counts = (output.bytes, output.line_count());
+            // The output is only compared with the test mod, not written to it.
+            (truncation, String::from("-"), counts)
+        } else {
+            let dest_path = dest_path(str_name);
+            let dest_file = File::create(&dest_path)?;
+            let mut dest_writer = CountingWriter::new(BufWriter::new(dest_file));
+            let truncation =
+                preprocessor::process_script(&mut src_reader, &mut dest_writer, &options)?;
+            dest_writer.flush()?;
+            #[cfg(feature = "logging")]
+            log::info!("Wrote {}.", dest_path.display());
+            let counts = (dest_writer.bytes, dest_writer.line_count());
+            (truncation, dest_path.display().to_string(), counts)
+        };
+        if self.manifest_path.is_some() {
+            let (bytes, line_count) = counts;
+            self.manifest.lock().unwrap().push(ManifestEntry {
+                source: src_path.display().to_string(),
+                destination,
+                bytes,
+                line_count,
+            });
+        }
+        if let Some(truncation) = truncation {
+            eprintln!(
+                "Warning: {str_name} truncated at line {}, {} lines dropped.",
+                truncation.line, truncation.dropped
+            );
         }
+        Ok(())
+    }
+
+    /// Processes every script of `paths` using `jobs` threads.
+    /// With a single job, the scripts are processed in order on the current
+    /// thread. Otherwise, returns the first error of each thread.
+    fn process_all(&self, paths: Vec<PathBuf>, jobs: usize) -> Result<(), PreprocessError> {
+        if jobs <= 1 {
+            return paths.iter().try_for_each(|path| self.process(path));
+        }
+        let queue = Mutex::new(paths);
+        thread::scope(|scope| {
+            let workers: Vec<_> = (0..jobs)
+                .map(|_| {
+                    scope.spawn(|| loop {
+                        let next = queue.lock().unwrap().pop();
+                        let Some(path) = next else {
+                            return Ok(());
+                        };
+                        self.process(&path)?;
+                    })
+                })
+                .collect();
+            workers
+                .into_iter()
+                .try_for_each(|worker| worker.join().unwrap())
+        })
+    }
+
+    /// Writes the records of the processed scripts to the manifest path as a
+    /// JSON array, ordered by source path, if a manifest path is given.
+    fn write_manifest(&self) -> Result<(), PreprocessError> {
+        let Some(path) = self.manifest_path else {
+            return Ok(());
+        };
+        let mut entries = self.manifest.lock().unwrap();
+        entries.sort_by(|a, b| a.source.cmp(&b.source));
+        let writer = BufWriter::new(File::create(path)?);
+        serde_json::to_writer_pretty(writer, &*entries).map_err(io::Error::other)?;
+        Ok(())
+    }
+
+    /// Processes the script at `src_path` and prints whether it succeeded.
+    fn rebuild(&self, src_path: &Path) {
+        let name = src_path.file_name().unwrap_or_default().to_string_lossy();
+        match self.process(src_path) {
+            Ok(()) => eprintln!("Built {name}."),
+            Err(e) => eprintln!("Error in {name}: {e}"),
+        }
+    }
+
+    /// Watches for changes to the scripts, rebuilding each changed script as
+    /// described by `Watched`. Errors are printed without stopping the watcher.
+    fn watch(&self, watched: Watched) -> Result<(), PreprocessError> {
+        let (sender, receiver) = mpsc::channel::<notify::Result<Event>>();
+        let mut watcher = notify::recommended_watcher(sender).map_err(io::Error::other)?;
+        // Watches the directory of a single script, as editors often save a
+        // file by replacing it.
+        let (dir, mode) = match &watched {
+            Watched::Script { target, .. } => (
+                target.parent().unwrap_or(target),
+                RecursiveMode::NonRecursive,
+            ),
+            Watched::Named(_) => (scripts_dir(), RecursiveMode::Recursive),
+        };
+        watcher.watch(dir, mode).map_err(io::Error::other)?;
+        eprintln!("Watching {} for changes.", dir.display());
+        while let Ok(event) = receiver.recv() {
+            // Collects the changes until none occur for the debounce time.
+            let mut changed = BTreeSet::new();
+            let mut next = Some(event);
+            while let Some(event) = next {
+                match event {
+                    Ok(Event {
+                        kind: EventKind::Create(_) | EventKind::Modify(_),
+                        paths,
+                        ..
+                    }) => changed.extend(paths),
+                    Ok(_) => (),
+                    Err(e) => eprintln!("Watch error: {e}"),
+                }
+                next = receiver.recv_timeout(DEBOUNCE).ok();
+            }
+            for path in changed {
+                // Skips removed files, such as an editor's temporary files.
+                match watched.script(&path) {
+                    Some(src_path) if src_path.is_file() => self.rebuild(src_path),
+                    _ => (),
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The scripts rebuilt by `--watch`.
+enum Watched<'a> {
+    /// Only the script given by the path `path`, whose canonical path is
+    /// `target`.
+    Script { path: &'a Path, target: PathBuf },
+    /// Each script of the scripts directory named by the file name, or every
+    /// script if `None`.
+    Named(Option<&'a str>),
+}
+
+impl<'a> Watched<'a> {
+    /// Returns the scripts of `--watch` for the single script at `single`, if
+    /// given, or otherwise for the scripts named `file`.
+    fn new(single: Option<&'a Path>, file: Option<&'a str>) -> io::Result<Self> {
+        match single {
+            Some(path) => Ok(Watched::Script {
+                path,
+                target: fs::canonicalize(path)?,
+            }),
+            None => Ok(Watched::Named(file)),
+        }
+    }
+
+    /// Returns the path of the script to rebuild when the file at `changed`
+    /// changes, or `None` if it is not watched.
+    fn script<'b>(&'b self, changed: &'b Path) -> Option<&'b Path> {
+        match self {
+            Watched::Script { path, target } => (changed == target).then_some(*path),
+            Watched::Named(file) => file
+                .is_none_or(|name| changed.file_name().is_some_and(|f| f == name))
+                .then_some(changed),
+        }
+    }
+}
+
+/// Runs preprocessing steps and writes the output to the test mod.
+/// Overwrites all files in the test mod.
+/// Does not remove unnecessary files from the test mod.
+/// Keeps `#DEBUG` lines if run with the `--debug` flag.
+/// Processes only the script named `name` if run with `--file <name>`.
+/// Processes only the script at `path`, without searching the scripts
+/// directory, if run with a `path` argument, such as
+/// `preprocessor path/to/map.rms`. Its output is written as for a script of
+/// the scripts directory with the same name.
+/// Writes the output to stdout instead of the test mod if run with `--print`.
+/// Prints a unified diff of the changes to the test mod instead of writing
+/// them if run with `--diff`. Unchanged scripts print nothing.
+/// Begins each output with a comment naming its source if run with
+/// `--watermark [template]`, where the template may contain `{src}` and
+/// `{time}`. An argument after `--watermark` that is a file or ends in `.rms`
+/// is a script path, not a template.
+/// Begins each output with a comment of the SHA-1 hash of its source and the
+/// version of the preprocessor if run with `--stamp`, after any watermark.
+/// Processes scripts on at most `n` threads if run with `--jobs <n>`, by
+/// default one per CPU. `--jobs 1` processes the scripts in order, which is
+/// the default with `--print` or `--diff`, so their output is in order too.
+/// Prints the ID number of each named actor area if run with `--verbose`.
+/// Resolves `#IF_PLAYERS` blocks for `n` players if run with `--players <n>`.
+/// Reports lines of the form `#NAME(...)` that are not macros, and objects
+/// whose minimum distance to players is above their maximum distance, as errors
+/// if run with `--strict`.
+/// Truncates the output at lines that are only `keyword` instead of `#BREAK`
+/// if run with `--break <keyword>`.
+/// Strips the trailing whitespace of the header's lines if run with
+/// `--header-format trim`, and also reindents them consistently if run with
+/// `--header-format reindent`.
+/// Writes a JSON array recording the source, destination, bytes, and lines of
+/// each output to `path` after the build if run with `--emit-manifest <path>`.
+/// Keeps running after the build if run with `--watch`, rebuilding each script
+/// when it changes, or only the script at `path` if given.
+/// Logs the progress of the pipeline, filtered by the `RUST_LOG` environment
+/// variable, if built with the `logging` feature.
+/// Prints any error and exits with a failing status.
+fn main() -> ExitCode {
+    match run() {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("Error: {e}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// Builds the scripts as described by the command line arguments, returning
+/// the first error.
+fn run() -> Result<(), PreprocessError> {
+    #[cfg(feature = "logging")]
+    env_logger::init();
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let mode = if args.iter().any(|arg| arg == "--debug") {
+        BuildMode::Debug
+    } else {
+        BuildMode::Release
+    };
+    let watermark = watermark_template(&args);
+    let print = args.iter().any(|arg| arg == "--print");
+    let diff = args.iter().any(|arg| arg == "--diff");
+    let jobs = match flag_value(&args, "--jobs")? {
+        Some(n) => n.parse().ok().filter(|&n| n > 0).ok_or_else(|| {
+            let message = format!("`--jobs` must be a positive integer: `{n}`.");
+            io::Error::new(io::ErrorKind::InvalidInput, message)
+        })?,
+        // Printed outputs are in the order of the scripts only if processed
+        // one at a time.
+        None if print || diff => 1,
+        None => thread::available_parallelism().map_or(1, NonZeroUsize::get),
+    };
+    let players = match flag_value(&args, "--players")? {
+        Some(n) => Some(n.parse().map_err(|_| {
+            let message = format!("`--players` must be a nonnegative integer: `{n}`.");
+            io::Error::new(io::ErrorKind::InvalidInput, message)
+        })?),
+        None => None,
+    };
+    let header_format = match flag_value(&args, "--header-format")? {
+        None => HeaderFormat::Verbatim,
+        Some("trim") => HeaderFormat::Trimmed,
+        Some("reindent") => HeaderFormat::Reindented,
+        Some(format) => {
+            let message = format!("`--header-format` must be `trim` or `reindent`: `{format}`.");
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, message).into());
+        }
+    };
+    let build = Build {
+        mode,
+        print,
+        diff,
+        verbose: args.iter().any(|arg| arg == "--verbose"),
+        players,
+        strict: args.iter().any(|arg| arg == "--strict"),
+        header_format,
+        break_keyword: flag_value(&args, "--break")?,
+        watermark,
+        stamp: args.iter().any(|arg| arg == "--stamp"),
+        // Seconds since the Unix epoch, to avoid depending on a date library.
+        time: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |t| t.as_secs())
+            .to_string(),
+        manifest_path: flag_value(&args, "--emit-manifest")?,
+        manifest: Mutex::new(vec![]),
+    };
+
+    let single = positional_arg(&args).map(Path::new);
+    let file = match single {
+        Some(path) => path.file_name().and_then(|name| name.to_str()),
+        None => flag_value(&args, "--file")?,
+    };
+    let paths = match single {
+        Some(path) if path.is_file() => vec![path.to_path_buf()],
+        Some(path) => {
+            let message = format!("Script `{}` not found.", path.display());
+            return Err(io::Error::new(io::ErrorKind::NotFound, message).into());
+        }
+        None => {
+            let mut paths = script_paths()?;
+            if let Some(name) = file {
+                paths.retain(|path| path.file_name().is_some_and(|file| file == name));
+                if paths.is_empty() {
+                    let message = format!("Script `{name}` not found in `{SCRIPTS}`.");
+                    return Err(io::Error::new(io::ErrorKind::NotFound, message).into());
+                }
+            }
+            paths
+        }
+    };
+    let result = build
+        .process_all(paths, jobs)
+        .and_then(|()| build.write_manifest());
+    if !args.iter().any(|arg| arg == "--watch") {
+        return result;
+    }
+    // A failed build should not stop the watcher, so its error is only printed.
+    if let Err(e) = result {
+        eprintln!("Error: {e}");
+    }
+    build.watch(Watched::new(single, file)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Returns `args` as owned command line arguments.
+    fn strings(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    /// Tests that a value flag without a value is an error instead of being
+    /// ignored.
+    #[test]
+    fn flag_without_value() -> io::Result<()> {
+        let args = strings(&["--print", "--file", "a.rms"]);
+        assert_eq!(Some("a.rms"), flag_value(&args, "--file")?);
+        assert_eq!(None, flag_value(&args, "--break")?);
+        for args in [&["--file"][..], &["--file", "--print"]] {
+            let error = flag_value(&strings(args), "--file").unwrap_err();
+            assert_eq!("`--file` requires a value.", error.to_string());
+        }
+        Ok(())
+    }
+
+    /// Tests that a single watched script is rebuilt only when its own path
+    /// changes, not when a script with the same name changes.
+    #[test]
+    fn watch_single_script() -> io::Result<()> {
+        let dir = std::env::temp_dir().join("preprocessor_watch_single_script");
+        fs::create_dir_all(&dir)?;
+        let path = dir.join("a.rms");
+        fs::write(&path, "a")?;
+        let watched = Watched::new(Some(&path), Some("a.rms"))?;
+        let target = fs::canonicalize(&path)?;
+        let other = scripts_dir().join("a.rms");
+        let (script, elsewhere) = (watched.script(&target), watched.script(&other));
+        fs::remove_dir_all(&dir)?;
+        assert_eq!(Some(path.as_path()), script);
+        assert_eq!(None, elsewhere);
+        let watched = Watched::new(None, Some("a.rms"))?;
+        assert_eq!(Some(other.as_path()), watched.script(&other));
+        assert_eq!(None, watched.script(Path::new("b.rms")));
+        Ok(())
+    }
+
+    /// Tests that a script path after `--watermark` is the positional path
+    /// instead of the watermark's template.
+    #[test]
+    fn watermark_before_path() {
+        let args = strings(&["--watermark", "/tmp/e.rms", "--print"]);
+        assert_eq!(Some(DEFAULT_WATERMARK), watermark_template(&args));
+        assert_eq!(Some("/tmp/e.rms"), positional_arg(&args));
+        let args = strings(&["--watermark", "Cargo.toml"]);
+        assert_eq!(Some(DEFAULT_WATERMARK), watermark_template(&args));
+        assert_eq!(Some("Cargo.toml"), positional_arg(&args));
+        let args = strings(&["--watermark", "FROM {src}", "a.rms"]);
+        assert_eq!(Some("FROM {src}"), watermark_template(&args));
+        assert_eq!(Some("a.rms"), positional_arg(&args));
+        assert_eq!(None, watermark_template(&strings(&["a.rms"])));
     }
-    Ok(())
 }
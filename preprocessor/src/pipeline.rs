@@ -0,0 +1,258 @@
+//! The ordered passes applied to the body of a map script, and the lints
+//! applied to its output.
+
+use crate::{
+    annotate_sections, assign_objects, assign_zones, check_braces, check_labels,
+    check_object_distances, comment_out_regions, compact_blocks, condense_whitespace,
+    expand_macros, expand_player_blocks, expand_rnd_counts, extract_rnd, filter_debug_lines,
+    mirror_lands, normalize_keywords, number_actor_areas, remove_pipeline_flags, repeat_lines,
+    report_similar_objects, resolve_repeat_consts, scale_positions, scope_labels,
+    select_player_branches, strip_comments, substitute_aliases, warn_object_counts, Line, Options,
+    PreprocessError,
+};
+
+/// A pass of the pipeline, rewriting the lines of a map script.
+pub type Pass<'a> = Box<dyn Fn(Vec<Line>) -> Result<Vec<Line>, PreprocessError> + 'a>;
+
+/// A lint of the pipeline, checking the output of a map script, with its header,
+/// after the passes are applied. Line numbers are those of the output.
+pub type Lint<'a> = Box<dyn Fn(&[String]) -> Result<(), PreprocessError> + 'a>;
+
+/// Returns the index of the item named `name` of `items`, or an error if there
+/// is no such item.
+fn position<T>(items: &[(String, T)], name: &str) -> Result<usize, PreprocessError> {
+    items
+        .iter()
+        .position(|(n, _)| n == name)
+        .ok_or_else(|| PreprocessError::UnknownPass {
+            name: name.to_string(),
+        })
+}
+
+/// Returns the pass applying the infallible rewrite `f`.
+fn infallible(f: fn(Vec<Line>) -> Vec<Line>) -> Pass<'static> {
+    Box::new(move |lines| Ok(f(lines)))
+}
+
+/// Returns the pass applying the fallible rewrite `f`.
+fn fallible(f: fn(Vec<Line>) -> Result<Vec<Line>, PreprocessError>) -> Pass<'static> {
+    Box::new(f)
+}
+
+/// An ordered list of named passes applied to the body of a map script.
+///
+/// Each line keeps the number of the source line it comes from, so errors are
+/// reported by source line whichever pass finds them.
+///
+/// The standard pipeline has the passes, in order:
+/// - `"strip_comments"`
+/// - `"condense_whitespace"`
+/// - `"player_branches"`
+/// - `"debug_lines"`
+/// - `"flags"`, which removes the `#EXPAND_AFTER_REPEAT`, `#NO_MACROS`,
+///   `#NO_REPEAT`, and `#CHECK_LABELS` flags.
+/// - `"object_counts"`
+/// - `"repeat_consts"`
+/// - `"macros"`
+/// - `"repeat"`
+/// - `"player_blocks"`
+/// - `"rnd_counts"`
+/// - `"aliases"`
+/// - `"label_scopes"`
+/// - `"object_distances"`
+/// - `"assign_objects"`
+/// - `"mirror_lands"`
+/// - `"assign_zones"`
+/// - `"scale_positions"`
+/// - `"extract_rnd"`
+/// - `"actor_area_names"`
+/// - `"report_similar_objects"`
+/// - `"annotate_sections"`
+/// - `"comment_out_regions"`
+/// - `"normalize_keywords"`
+/// - `"compact_blocks"`
+///
+/// The standard pipeline then applies the lints to the output, in order:
+/// - `"check_labels"`, which the standard pipeline of a script without the
+///   `#CHECK_LABELS` flag does not have.
+/// - `"check_braces"`
+///
+/// The builder methods return an error if a pass or lint they refer to does
+/// not exist.
+pub struct Pipeline<'a> {
+    /// The passes paired with their names, in the order they are applied.
+    passes: Vec<(String, Pass<'a>)>,
+    /// The lints paired with their names, in the order they are applied.
+    lints: Vec<(String, Lint<'a>)>,
+}
+
+impl<'a> Pipeline<'a> {
+    /// Returns a pipeline without any passes.
+    pub fn new() -> Self {
+        Pipeline {
+            passes: vec![],
+            lints: vec![],
+        }
+    }
+
+    /// Returns the standard pipeline for `options`, with the named actor areas
+    /// numbered starting from `actor_area_base`.
+    pub fn standard(options: &'a Options, actor_area_base: u32) -> Self {
+        // Including files feels more trouble than it's worth, just do the Boars
+        // in the maps themselves.
+        Pipeline::new()
+            .push("strip_comments", fallible(strip_comments))
+            .push("condense_whitespace", infallible(condense_whitespace))
+            .push(
+                "player_branches",
+                Box::new(|lines| select_player_branches(lines, options.players)),
+            )
+            .push(
+                "debug_lines",
+                Box::new(|lines| Ok(filter_debug_lines(lines, options.mode))),
+            )
+            .push("flags", infallible(remove_pipeline_flags))
+            .push("object_counts", fallible(warn_object_counts))
+            .push("repeat_consts", fallible(resolve_repeat_consts))
+            .push(
+                "macros",
+                Box::new(|lines| expand_macros(lines, options.strict)),
+            )
+            .push("repeat", fallible(repeat_lines))
+            .push("player_blocks", fallible(expand_player_blocks))
+            .push("rnd_counts", fallible(expand_rnd_counts))
+            .push("aliases", fallible(substitute_aliases))
+            .push("label_scopes", fallible(scope_labels))
+            .push(
+                "object_distances",
+                Box::new(|lines| {
+                    check_object_distances(&lines, options.strict)?;
+                    Ok(lines)
+                }),
+            )
+            .push("assign_objects", fallible(assign_objects))
+            .push("mirror_lands", fallible(mirror_lands))
+            .push("assign_zones", fallible(assign_zones))
+            .push("scale_positions", fallible(scale_positions))
+            .push("extract_rnd", fallible(extract_rnd))
+            .push(
+                "actor_area_names",
+                Box::new(move |lines| {
+                    Ok(number_actor_areas(lines, actor_area_base, options.verbose))
+                }),
+            )
+            .push("report_similar_objects", infallible(report_similar_objects))
+            // Runs after comments are stripped, so the section comments are kept.
+            .push("annotate_sections", fallible(annotate_sections))
+            .push("comment_out_regions", fallible(comment_out_regions))
+            .push("normalize_keywords", infallible(normalize_keywords))
+            .push("compact_blocks", infallible(compact_blocks))
+            .push_lint(
+                "check_labels",
+                Box::new(|output| {
+                    check_labels(output);
+                    Ok(())
+                }),
+            )
+            .push_lint("check_braces", Box::new(check_braces))
+    }
+
+    /// Returns the names of the passes, in the order they are applied.
+    pub fn names(&self) -> Vec<&str> {
+        self.passes.iter().map(|(name, _)| &name[..]).collect()
+    }
+
+    /// Returns this pipeline with `pass` named `name` applied last.
+    pub fn push(mut self, name: &str, pass: Pass<'a>) -> Self {
+        self.passes.push((name.to_string(), pass));
+        self
+    }
+
+    /// Returns this pipeline with `pass` named `name` applied immediately
+    /// before the pass named `target`.
+    pub fn insert_before(
+        mut self,
+        target: &str,
+        name: &str,
+        pass: Pass<'a>,
+    ) -> Result<Self, PreprocessError> {
+        let i = position(&self.passes, target)?;
+        self.passes.insert(i, (name.to_string(), pass));
+        Ok(self)
+    }
+
+    /// Returns this pipeline with `pass` named `name` applied immediately
+    /// after the pass named `target`.
+    pub fn insert_after(
+        mut self,
+        target: &str,
+        name: &str,
+        pass: Pass<'a>,
+    ) -> Result<Self, PreprocessError> {
+        let i = position(&self.passes, target)?;
+        self.passes.insert(i + 1, (name.to_string(), pass));
+        Ok(self)
+    }
+
+    /// Returns this pipeline without the pass named `name`.
+    pub fn remove(mut self, name: &str) -> Result<Self, PreprocessError> {
+        let i = position(&self.passes, name)?;
+        drop(self.passes.remove(i));
+        Ok(self)
+    }
+
+    /// Returns this pipeline with the pass named `name` moved to immediately
+    /// before the pass named `target`.
+    pub fn move_before(mut self, name: &str, target: &str) -> Result<Self, PreprocessError> {
+        let pass = self.passes.remove(position(&self.passes, name)?);
+        let i = position(&self.passes, target)?;
+        self.passes.insert(i, pass);
+        Ok(self)
+    }
+
+    /// Returns this pipeline with the pass named `name` moved to immediately
+    /// after the pass named `target`.
+    pub fn move_after(mut self, name: &str, target: &str) -> Result<Self, PreprocessError> {
+        let pass = self.passes.remove(position(&self.passes, name)?);
+        let i = position(&self.passes, target)?;
+        self.passes.insert(i + 1, pass);
+        Ok(self)
+    }
+
+    /// Applies the passes to `lines` in order, returning the result or the
+    /// first error.
+    pub fn run(&self, lines: Vec<Line>) -> Result<Vec<Line>, PreprocessError> {
+        self.passes
+            .iter()
+            .try_fold(lines, |lines, (_, pass)| pass(lines))
+    }
+
+    /// Returns the names of the lints, in the order they are applied.
+    pub fn lint_names(&self) -> Vec<&str> {
+        self.lints.iter().map(|(name, _)| &name[..]).collect()
+    }
+
+    /// Returns this pipeline with `lint` named `name` applied last.
+    pub fn push_lint(mut self, name: &str, lint: Lint<'a>) -> Self {
+        self.lints.push((name.to_string(), lint));
+        self
+    }
+
+    /// Returns this pipeline without the lint named `name`.
+    pub fn remove_lint(mut self, name: &str) -> Result<Self, PreprocessError> {
+        let i = position(&self.lints, name)?;
+        drop(self.lints.remove(i));
+        Ok(self)
+    }
+
+    /// Applies the lints to the `output` in order, returning the first error.
+    pub fn lint(&self, output: &[String]) -> Result<(), PreprocessError> {
+        self.lints.iter().try_for_each(|(_, lint)| lint(output))
+    }
+}
+
+impl Default for Pipeline<'_> {
+    fn default() -> Self {
+        Pipeline::new()
+    }
+}
@@ -1,5 +1,7 @@
 //! Utility functions.
 
+use std::f64::consts::{PI, TAU};
+
 pub type Pointf64 = (f64, f64);
 pub type Pointu32 = (u32, u32);
 
@@ -17,3 +19,159 @@ pub fn cos(theta: f64) -> f64 {
 pub fn round(x: f64) -> u32 {
     x.round() as u32
 }
+
+/// Returns `x.round()`, clamped to the inclusive range `[lo, hi]`.
+/// Unlike `round`, this function is safe to use when `x` may be slightly
+/// outside of the map, such as a position computed just off of its edge.
+/// Requires `lo <= hi`.
+pub fn round_clamped(x: f64, lo: u32, hi: u32) -> u32 {
+    debug_assert!(lo <= hi);
+    x.round().clamp(lo as f64, hi as f64) as u32
+}
+
+/// Returns the angle `degrees` converted to radians.
+pub fn deg_to_rad(degrees: f64) -> f64 {
+    degrees * PI / 180.0
+}
+
+/// Returns the angle `radians` converted to degrees.
+pub fn rad_to_deg(radians: f64) -> f64 {
+    radians * 180.0 / PI
+}
+
+/// Returns the angle equivalent to `theta` in the range `[0, TAU)`.
+pub fn angle_in_range(theta: f64) -> f64 {
+    let a = theta.rem_euclid(TAU);
+    // Rounding may produce exactly `TAU` for tiny negative angles.
+    if a < TAU {
+        a
+    } else {
+        0.0
+    }
+}
+
+/// Same as the standard `y.atan2(x)`, but the output is given in the region
+/// `[0..TAU)`. A point on the positive `x` axis, including `y == -0.0`, has
+/// angle `0.0`, never `TAU` or `-0.0`.
+pub fn arctan(y: f64, x: f64) -> f64 {
+    // Adding `0.0` turns `-0.0` into `0.0`.
+    angle_in_range(y.atan2(x)) + 0.0
+}
+
+/// Builds the lines of a `start_random` block, one `percent_chance` per line,
+/// in the order the chances are added.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct RandomBlock {
+    /// The percents of the chances, paired with the lines they select.
+    chances: Vec<(u32, String)>,
+}
+
+impl RandomBlock {
+    /// Returns a new block without any chances.
+    pub fn new() -> Self {
+        RandomBlock::default()
+    }
+
+    /// Adds the chance `percent_chance percent line`, such as `line` of
+    /// `#define LABEL`.
+    pub fn chance(mut self, percent: u32, line: impl Into<String>) -> Self {
+        self.chances.push((percent, line.into()));
+        self
+    }
+
+    /// Returns the sum of the percents of the chances.
+    pub fn total(&self) -> u32 {
+        self.chances.iter().map(|(percent, _)| percent).sum()
+    }
+
+    /// Returns the lines of the block, enclosed in `start_random` and
+    /// `end_random`. Panics if the percents sum to more than `100`.
+    pub fn build(self) -> Vec<String> {
+        let total = self.total();
+        assert!(total <= 100, "Random block percents sum to {total} > 100.");
+        let mut lines = Vec::with_capacity(self.chances.len() + 2);
+        lines.push(String::from("start_random"));
+        for (percent, line) in self.chances {
+            lines.push(format!("percent_chance {percent} {line}"));
+        }
+        lines.push(String::from("end_random"));
+        lines
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Tests that rounding clamps values outside of the range.
+    #[test]
+    fn round_clamped_bounds() {
+        assert_eq!(0, round_clamped(-0.4, 0, 100));
+        assert_eq!(0, round_clamped(-3.0, 0, 100));
+        assert_eq!(100, round_clamped(100.6, 0, 100));
+        assert_eq!(43, round_clamped(42.5, 0, 100));
+    }
+
+    /// Tests converting between degrees and radians.
+    #[test]
+    fn degree_conversions() {
+        assert_eq!(PI, deg_to_rad(180.0));
+        assert_eq!(90.0, rad_to_deg(PI / 2.0));
+        assert!((45.0 - rad_to_deg(deg_to_rad(45.0))).abs() < 1e-12);
+    }
+
+    /// Tests normalizing angles into `[0, TAU)`.
+    #[test]
+    fn angles_in_range() {
+        assert_eq!(0.0, angle_in_range(0.0));
+        assert_eq!(0.0, angle_in_range(TAU));
+        assert_eq!(PI, angle_in_range(-PI));
+        assert_eq!(PI / 2.0, angle_in_range(PI / 2.0 + 2.0 * TAU));
+        assert_eq!(0.0, angle_in_range(-1e-20));
+    }
+
+    /// Tests the arctangent of points on the four axes and just below the
+    /// positive `x` axis.
+    #[test]
+    fn arctan_boundaries() {
+        assert_eq!(0.0, arctan(0.0, 1.0));
+        assert_eq!(PI / 2.0, arctan(1.0, 0.0));
+        assert_eq!(PI, arctan(0.0, -1.0));
+        assert_eq!(3.0 * PI / 2.0, arctan(-1.0, 0.0));
+        // Signed zeros are on the positive `x` axis, and at angle `0.0`.
+        assert!(arctan(-0.0, 1.0).is_sign_positive());
+        assert_eq!(0.0, arctan(-0.0, 1.0));
+        // Just below the axis is just below `TAU`, or `0.0` if rounded up.
+        let below = arctan(-1e-9, 1.0);
+        assert!(below < TAU && below > TAU - 1e-8);
+        assert_eq!(0.0, arctan(-1e-20, 1.0));
+    }
+
+    /// Tests the lines of a random block and its total.
+    #[test]
+    fn random_block_lines() {
+        let block = RandomBlock::new()
+            .chance(50, "#define A")
+            .chance(25, String::from("#const B 1"));
+        assert_eq!(75, block.total());
+        assert_eq!(
+            vec![
+                "start_random",
+                "percent_chance 50 #define A",
+                "percent_chance 25 #const B 1",
+                "end_random",
+            ],
+            block.build()
+        );
+    }
+
+    /// Tests that percents summing to more than `100` are rejected.
+    #[test]
+    #[should_panic]
+    fn random_block_over_100() {
+        RandomBlock::new()
+            .chance(60, "#define A")
+            .chance(41, "#define B")
+            .build();
+    }
+}
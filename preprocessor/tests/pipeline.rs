@@ -0,0 +1,752 @@
+//! End to end tests of the preprocessing pipeline.
+
+use std::cell::Cell;
+
+use preprocessor::{
+    process_lines, process_lines_with, process_script, BuildMode, Line, Lint, Options, Pass,
+    Pipeline, PreprocessError, Truncation,
+};
+
+/// Returns the lines of `script` as owned strings.
+fn lines(script: &str) -> Vec<String> {
+    script.lines().map(String::from).collect()
+}
+
+/// Tests a script combining a header, comments, a repeat block, a macro,
+/// `#SET_PLACE_FOR_EVERY_PLAYER`, and a named actor area.
+#[test]
+fn full_pipeline() -> Result<(), PreprocessError> {
+    let script = "#HEADER_START
+/* Test map */
+#HEADER_END
+/* A comment
+   spanning lines. */
+<LAND_GENERATION>
+#REPEAT(2)
+create_land {   terrain_type WATER /* inline */ }
+#END_REPEAT
+<OBJECTS_GENERATION>
+create_actor_area 10 10 box0 3
+#vision
+create_object VILLAGER {
+#SET_PLACE_FOR_EVERY_PLAYER
+actor_area_to_place_in box0
+}
+";
+    let expected = vec![
+        "/* Test map */",
+        "<LAND_GENERATION>",
+        "create_land { terrain_type WATER }",
+        "create_land { terrain_type WATER }",
+        "<OBJECTS_GENERATION>",
+        "create_actor_area 10 10 20000 3",
+        "create_object TEMPORARY_REVEALER {",
+        "number_of_objects 4",
+        "actor_area_to_place_in 20000",
+        "set_place_for_every_player",
+        "max_distance_to_players 2",
+        "}",
+        "create_object VILLAGER {",
+        "actor_area_to_place_in 20000",
+        "place_on_specific_land_id 1",
+        "}",
+        "create_object VILLAGER {",
+        "actor_area_to_place_in 20000",
+        "place_on_specific_land_id 2",
+        "}",
+    ];
+    assert_eq!(expected, process_lines(lines(script), &Options::default())?);
+    Ok(())
+}
+
+/// Tests that the hut macro replaces every house with a hut.
+#[test]
+fn hut_gap_macro() -> Result<(), PreprocessError> {
+    let output = process_lines(lines("#HUTGAP3"), &Options::default())?;
+    assert!(output.iter().any(|line| line.contains("HUT")));
+    assert!(output.iter().all(|line| !line.contains("HOUSE")));
+    Ok(())
+}
+
+/// Tests that generated land positions are well formed.
+#[test]
+fn land_position_macros() -> Result<(), PreprocessError> {
+    for script in ["#SNAKELANDS", "#BFLANDS", "#CIRCLE_POSITION_P1(30,90)"] {
+        for line in process_lines(lines(script), &Options::default())? {
+            assert!(!line.contains("land position"), "{script}: {line}");
+        }
+    }
+    Ok(())
+}
+
+/// Tests processing a script from a reader into a writer, stopping at
+/// `#BREAK`.
+#[test]
+fn script_until_break() -> Result<(), PreprocessError> {
+    let script = "#HEADER_START\n/* Header */\n#HEADER_END\na /* b */\n#BREAK\nc\nd\n";
+    let mut dest = vec![];
+    let truncation = process_script(&mut script.as_bytes(), &mut dest, &Options::default())?;
+    assert_eq!("/* Header */\na", String::from_utf8(dest).unwrap());
+    assert_eq!(
+        Some(Truncation {
+            line: 3,
+            dropped: 2
+        }),
+        truncation
+    );
+    Ok(())
+}
+
+/// Tests that the truncation counts the lines of the output, including the
+/// lines of multi-line macro expansions and the watermark.
+#[test]
+fn break_after_expansion() -> Result<(), PreprocessError> {
+    let options = Options {
+        watermark: Some(String::from("w")),
+        ..Options::default()
+    };
+    let mut dest = vec![];
+    let script = "#POSITION_P1\n#BREAK\n#POSITION_P2\na";
+    let truncation = process_script(&mut script.as_bytes(), &mut dest, &options)?;
+    let written = String::from_utf8(dest).unwrap().lines().count();
+    let p2 = process_lines(lines("#POSITION_P2"), &Options::default())?;
+    let dropped = p2.join("\n").lines().count() + 1;
+    assert!(dropped > p2.len() + 1);
+    assert_eq!(
+        Some(Truncation {
+            line: written + 1,
+            dropped
+        }),
+        truncation
+    );
+    Ok(())
+}
+
+/// Tests that `#SCALE` scales the positions generated by a macro, which
+/// expands into several lines.
+#[test]
+fn scale_macro_positions() -> Result<(), PreprocessError> {
+    let output = process_lines(lines("#SCALE(0.5)\n#POSITION_P2"), &Options::default())?;
+    let output = output.join("\n");
+    assert!(!output.contains("land_position rnd(75,83) rnd(45,55)"));
+    assert!(output.contains("land_position rnd(38,42) rnd(23,28)"));
+    Ok(())
+}
+
+/// Tests that malformed scripts are reported as errors.
+#[test]
+fn malformed_scripts() {
+    let result = process_lines(lines("#HEADER_START\na"), &Options::default());
+    assert!(matches!(result, Err(PreprocessError::UnterminatedHeader)));
+    let result = process_lines(lines("a\nb /* c\nd"), &Options::default());
+    assert!(matches!(
+        result,
+        Err(PreprocessError::UnbalancedComment { line: 2 })
+    ));
+    let result = process_lines(lines("#REPEAT(-1)\na\n#END_REPEAT"), &Options::default());
+    assert!(matches!(
+        result,
+        Err(PreprocessError::BadRepeatCount { line: 1, .. })
+    ));
+    let result = process_lines(lines("#REPEAT(2)\na"), &Options::default());
+    assert!(matches!(
+        result,
+        Err(PreprocessError::UnterminatedRepeat { line: 1 })
+    ));
+    let result = process_lines(lines("a\n#END_REPEAT"), &Options::default());
+    assert!(matches!(
+        result,
+        Err(PreprocessError::UnexpectedEndRepeat { line: 2 })
+    ));
+    let result = process_lines(lines("#FOURSEASONSLAKES(x)"), &Options::default());
+    assert!(matches!(
+        result,
+        Err(PreprocessError::BadMacroArgs { line: 1, .. })
+    ));
+    let result = process_lines(
+        lines("#EXTRACT_RND\n<ELEVATION_GENERATION>\nland_position rnd(4,x) 5"),
+        &Options::default(),
+    );
+    assert!(matches!(
+        result,
+        Err(PreprocessError::BadRnd { line: 3, .. })
+    ));
+}
+
+/// Tests that errors report the source line, after skipping the header,
+/// comments, blank lines, and expanded macros.
+#[test]
+fn error_source_lines() {
+    let script = "#HEADER_START
+/* Header */
+#HEADER_END
+/* A comment
+   spanning lines. */
+
+#VISION
+#REPEAT(2)
+a
+#END_REPEAT
+#END_REPEAT
+";
+    let error = process_lines(lines(script), &Options::default()).unwrap_err();
+    assert!(matches!(
+        error,
+        PreprocessError::UnexpectedEndRepeat { line: 11 }
+    ));
+    assert_eq!("Line 11: unexpected end repeat.", error.to_string());
+}
+
+/// Tests that `#DEBUG` lines are removed in release mode and kept without
+/// their prefix in debug mode.
+#[test]
+fn debug_lines() -> Result<(), PreprocessError> {
+    let debug = Options {
+        mode: BuildMode::Debug,
+        ..Options::default()
+    };
+    let script = "a\n#DEBUG  reveal_map\n#DEBUGGING\nb";
+    assert_eq!(
+        vec!["a", "#DEBUGGING", "b"],
+        process_lines(lines(script), &Options::default())?
+    );
+    assert_eq!(
+        vec!["a", "reveal_map", "#DEBUGGING", "b"],
+        process_lines(lines(script), &debug)?
+    );
+    Ok(())
+}
+
+/// Tests that `#NO_MACROS` and `#NO_REPEAT` skip their passes, and that
+/// `#RAW` passes the script through verbatim.
+#[test]
+fn disabled_passes() -> Result<(), PreprocessError> {
+    let script = "#NO_MACROS\n#REPEAT(2)\n#VISION /* a */\n#END_REPEAT";
+    assert_eq!(
+        vec!["#VISION", "#VISION"],
+        process_lines(lines(script), &Options::default())?
+    );
+    let script = "#no_repeat\n#REPEAT(2)\na /* b */\n#END_REPEAT";
+    assert_eq!(
+        vec!["#REPEAT(2)", "a", "#END_REPEAT"],
+        process_lines(lines(script), &Options::default())?
+    );
+    let script = "#HEADER_START\n  #RAW\n#REPEAT(2)\n  #VISION /* a */";
+    assert_eq!(
+        vec!["#HEADER_START", "#REPEAT(2)", "  #VISION /* a */"],
+        process_lines(lines(script), &Options::default())?
+    );
+    Ok(())
+}
+
+/// Tests that the watermark comment is written before the header comment.
+#[test]
+fn watermark_before_header() -> Result<(), PreprocessError> {
+    let script = "#HEADER_START\n/* Header */\n#HEADER_END\na";
+    let options = Options {
+        watermark: Some(String::from("GENERATED FROM x.rms")),
+        ..Options::default()
+    };
+    let mut dest = vec![];
+    process_script(&mut script.as_bytes(), &mut dest, &options)?;
+    assert_eq!(
+        "/* GENERATED FROM x.rms */\n/* Header */\na",
+        String::from_utf8(dest).unwrap()
+    );
+    Ok(())
+}
+
+/// Tests that the stamp comment records the hash of the unprocessed source and
+/// is written after the watermark.
+#[test]
+fn stamp_after_watermark() -> Result<(), PreprocessError> {
+    let options = Options {
+        watermark: Some(String::from("w")),
+        stamp: true,
+        ..Options::default()
+    };
+    let mut dest = vec![];
+    process_script(&mut "abc".as_bytes(), &mut dest, &options)?;
+    let expected = format!(
+        "/* w */\n/* src-hash: a9993e364706816aba3e25717850c26c9cd0d89d, version: {} */\nabc",
+        env!("CARGO_PKG_VERSION")
+    );
+    assert_eq!(expected, String::from_utf8(dest).unwrap());
+    Ok(())
+}
+
+/// Tests selecting `#IF_PLAYERS` branches, including nested blocks and
+/// comparison operators.
+#[test]
+fn player_conditions() -> Result<(), PreprocessError> {
+    let script = "a
+#IF_PLAYERS 2
+b
+#ELSE
+c
+#IF_PLAYERS >= 4
+d
+#ENDIF_PLAYERS
+#ENDIF_PLAYERS
+e";
+    let players = |n| Options {
+        players: Some(n),
+        ..Options::default()
+    };
+    assert_eq!(
+        vec!["a", "b", "e"],
+        process_lines(lines(script), &players(2))?
+    );
+    assert_eq!(
+        vec!["a", "c", "e"],
+        process_lines(lines(script), &players(3))?
+    );
+    assert_eq!(
+        vec!["a", "c", "d", "e"],
+        process_lines(lines(script), &players(8))?
+    );
+    Ok(())
+}
+
+/// Tests the errors of malformed `#IF_PLAYERS` blocks.
+#[test]
+fn player_condition_errors() {
+    let options = Options {
+        players: Some(2),
+        ..Options::default()
+    };
+    let result = process_lines(lines("a\n#IF_PLAYERS 2\nb"), &options);
+    assert!(matches!(
+        result,
+        Err(PreprocessError::UnterminatedPlayerCondition { line: 2 })
+    ));
+    let result = process_lines(lines("#ELSE"), &options);
+    assert!(matches!(
+        result,
+        Err(PreprocessError::UnexpectedPlayerDirective { line: 1, .. })
+    ));
+    let result = process_lines(lines("#IF_PLAYERS =< 2\n#ENDIF_PLAYERS"), &options);
+    assert!(matches!(
+        result,
+        Err(PreprocessError::BadPlayerCondition { line: 1, .. })
+    ));
+    let result = process_lines(lines("#IF_PLAYERS 2\n#ENDIF_PLAYERS"), &Options::default());
+    assert!(matches!(
+        result,
+        Err(PreprocessError::MissingPlayerCount { line: 1 })
+    ));
+}
+
+/// Tests that the labels of two circle blocks in separate label scopes do not
+/// interfere, and that unbalanced scopes are reported as errors.
+#[test]
+fn label_scopes() -> Result<(), PreprocessError> {
+    let script = "#LABEL_SCOPE
+#CIRCLE_LABELS(30,112)
+#CIRCLE_POSITION_P1(30,112)
+#END_LABEL_SCOPE
+#LABEL_SCOPE(ISLANDS)
+#CIRCLE_LABELS(30,112)
+#CIRCLE_POSITION_P1(30,112)
+#END_LABEL_SCOPE
+if P1_POINT_0
+endif";
+    let output = process_lines(lines(script), &Options::default())?;
+    let first = output
+        .iter()
+        .position(|line| line.contains("ISLANDS"))
+        .unwrap();
+    let (first, second) = output.split_at(first);
+    assert!(first
+        .iter()
+        .any(|line| line.ends_with("#define _A_P1_POINT_0")));
+    assert!(first.iter().any(|line| line == "if _A_P1_POINT_0"));
+    assert!(second
+        .iter()
+        .any(|line| line.ends_with("#define ISLANDS_P1_POINT_0")));
+    assert!(second.iter().any(|line| line == "if ISLANDS_P1_POINT_0"));
+    // Only the line outside of the scopes refers to the unprefixed label.
+    let bare = output.iter().filter(|line| line.contains(" P1_POINT_"));
+    assert_eq!(vec!["if P1_POINT_0"], bare.collect::<Vec<_>>());
+
+    let result = process_lines(lines("a\n#LABEL_SCOPE\nb"), &Options::default());
+    assert!(matches!(
+        result,
+        Err(PreprocessError::UnterminatedLabelScope { line: 2 })
+    ));
+    let result = process_lines(lines("#END_LABEL_SCOPE"), &Options::default());
+    assert!(matches!(
+        result,
+        Err(PreprocessError::UnexpectedEndLabelScope { line: 1 })
+    ));
+    Ok(())
+}
+
+/// Tests that `#AUTO_ZONE` gives each player land without a zone the next
+/// unused zone, and reports a land that is never closed.
+#[test]
+fn auto_zones() -> Result<(), PreprocessError> {
+    let script = "#AUTO_ZONE
+create_land { terrain_type GRASS assign_to_player 1 }
+create_land {
+  terrain_type GRASS
+  assign_to_player 2
+}
+create_land { terrain_type WATER }
+create_land { terrain_type GRASS assign_to_player 3 }
+create_land { terrain_type DESERT assign_to AT_TEAM 1 0 0 zone 5 }";
+    let expected = vec![
+        "create_land { terrain_type GRASS assign_to_player 1 zone 1 }",
+        "create_land {",
+        "terrain_type GRASS",
+        "assign_to_player 2",
+        "zone 2",
+        "}",
+        "create_land { terrain_type WATER }",
+        "create_land { terrain_type GRASS assign_to_player 3 zone 3 }",
+        "create_land { terrain_type DESERT assign_to AT_TEAM 1 0 0 zone 5 }",
+    ];
+    assert_eq!(expected, process_lines(lines(script), &Options::default())?);
+
+    let script = "#AUTO_ZONE\na\ncreate_land {\nassign_to_player 1";
+    let error = process_lines(lines(script), &Options::default()).unwrap_err();
+    assert!(matches!(error, PreprocessError::UnclosedLand { line: 3 }));
+    Ok(())
+}
+
+/// Tests duplicating a land for each player of a `#FOR_PLAYERS` block, and the
+/// errors of malformed blocks.
+#[test]
+fn for_players_blocks() -> Result<(), PreprocessError> {
+    let script = "#FOR_PLAYERS(3,4)
+create_land {
+terrain_type GRASS
+assign_to_player 1
+}
+create_land { terrain_type FOREST assign_to_player }
+#END_FOR_PLAYERS
+a";
+    let expected = vec![
+        "create_land {",
+        "terrain_type GRASS",
+        "assign_to_player 3",
+        "}",
+        "create_land { terrain_type FOREST assign_to_player 3 }",
+        "create_land {",
+        "terrain_type GRASS",
+        "assign_to_player 4",
+        "}",
+        "create_land { terrain_type FOREST assign_to_player 4 }",
+        "a",
+    ];
+    assert_eq!(expected, process_lines(lines(script), &Options::default())?);
+
+    let result = process_lines(
+        lines("#FOR_PLAYERS(0,2)\n#END_FOR_PLAYERS"),
+        &Options::default(),
+    );
+    assert!(matches!(
+        result,
+        Err(PreprocessError::BadMacroArgs { line: 1, .. })
+    ));
+    let result = process_lines(lines("a\n#FOR_PLAYERS(1,2)\nb"), &Options::default());
+    assert!(matches!(
+        result,
+        Err(PreprocessError::UnterminatedForPlayers { line: 2 })
+    ));
+    let result = process_lines(lines("#END_FOR_PLAYERS"), &Options::default());
+    assert!(matches!(
+        result,
+        Err(PreprocessError::UnexpectedEndForPlayers { line: 1 })
+    ));
+    Ok(())
+}
+
+/// Tests that strict mode rejects unknown macros with arguments, but passes
+/// through unknown directives without arguments.
+#[test]
+fn strict_macros() -> Result<(), PreprocessError> {
+    let strict = Options {
+        strict: true,
+        ..Options::default()
+    };
+    let script = "#REPEAT(2)\n#MYTHING\n#END_REPEAT\n#VISON(8,5)";
+    assert_eq!(
+        vec!["#MYTHING", "#MYTHING", "#VISON(8,5)"],
+        process_lines(lines(script), &Options::default())?
+    );
+    let error = process_lines(lines(script), &strict).unwrap_err();
+    assert_eq!("Line 4: unknown macro: `#VISON(8,5)`.", error.to_string());
+    Ok(())
+}
+
+/// Tests that `#RND_COUNT` sets its attribute to every value of its range with
+/// fresh labels for each use, and the errors of malformed arguments.
+#[test]
+fn rnd_counts() -> Result<(), PreprocessError> {
+    let script = "create_object GOLD {
+#RND_COUNT(number_of_objects,2,5)
+#RND_COUNT(number_of_groups, 3, 3)
+}
+create_land {
+#RND_COUNT(number_of_tiles,10,11)
+}";
+    let output = process_lines(lines(script), &Options::default())?;
+    for count in 2..=5 {
+        assert!(output.contains(&format!("number_of_objects {count}")));
+    }
+    assert!(output.contains(&"number_of_groups 3".to_string()));
+    assert!(output.contains(&"number_of_tiles 10".to_string()));
+    assert!(output.contains(&"number_of_tiles 11".to_string()));
+    let percent: u32 = output
+        .iter()
+        .filter_map(|line| line.strip_prefix("percent_chance "))
+        .map(|line| line.split(' ').next().unwrap().parse::<u32>().unwrap())
+        .sum();
+    assert_eq!(200, percent);
+    assert!(output.contains(&"if RND_COUNT_A_0".to_string()));
+    assert!(output.contains(&"elseif RND_COUNT_A_3".to_string()));
+    assert!(output.contains(&"if RND_COUNT_B_0".to_string()));
+
+    for script in ["#RND_COUNT(number_of_objects,5,2)", "#RND_COUNT(a,1)"] {
+        let result = process_lines(lines(script), &Options::default());
+        assert!(matches!(
+            result,
+            Err(PreprocessError::BadMacroArgs { line: 1, .. })
+        ));
+    }
+    Ok(())
+}
+
+/// Tests that the `actor_area_base` header metadata sets the ID number of the
+/// first named actor area, and that metadata is not written to the output.
+#[test]
+fn header_actor_area_base() -> Result<(), PreprocessError> {
+    let script = "#HEADER_START
+/* Test map */
+actor_area_base: 30000
+color: blue
+#HEADER_END
+create_actor_area 50 50 center 3
+actor_area_to_place_in center";
+    let expected = vec![
+        "/* Test map */",
+        "create_actor_area 50 50 30000 3",
+        "actor_area_to_place_in 30000",
+    ];
+    assert_eq!(expected, process_lines(lines(script), &Options::default())?);
+
+    let script = "#HEADER_START\nactor_area_base: many\n#HEADER_END";
+    let error = process_lines(lines(script), &Options::default()).unwrap_err();
+    assert_eq!(
+        "Invalid header value for `actor_area_base`: `many`.",
+        error.to_string()
+    );
+    Ok(())
+}
+
+/// Tests customizing the passes of the pipeline, and that line numbers of
+/// errors are positions in the source after the header.
+#[test]
+fn custom_pipeline() -> Result<(), PreprocessError> {
+    let options = Options::default();
+    let standard = Pipeline::standard(&options, 20_000);
+    assert_eq!("strip_comments", standard.names()[0]);
+    assert_eq!(25, standard.names().len());
+    assert_eq!(vec!["check_labels", "check_braces"], standard.lint_names());
+
+    let script = "#HEADER_START\n/* h */\n#HEADER_END\n#SECTION a\nb\n#END_SECTION";
+    let upper: Pass = Box::new(|lines| {
+        let upper = |line: Line| Line {
+            text: line.text.to_uppercase(),
+            ..line
+        };
+        Ok(lines.into_iter().map(upper).collect())
+    });
+    let output = process_lines_with(lines(script), &options, |pipeline| {
+        pipeline
+            .remove("annotate_sections")?
+            .insert_after("label_scopes", "upper", upper)?
+            .move_before("comment_out_regions", "upper")
+    })?;
+    assert_eq!(vec!["/* h */", "#SECTION A", "B", "#END_SECTION"], output);
+
+    let push = |text: &'static str| -> Pass {
+        Box::new(move |mut lines| {
+            let number = lines.len() + 1;
+            let text = text.to_string();
+            lines.push(Line { number, text });
+            Ok(lines)
+        })
+    };
+    let pipeline = Pipeline::new()
+        .push("a", push("a"))
+        .push("b", push("b"))
+        .move_after("a", "b")?;
+    assert_eq!(vec!["b", "a"], pipeline.names());
+    let output: Vec<String> = pipeline.run(vec![])?.into_iter().map(|l| l.text).collect();
+    assert_eq!(vec!["b", "a"], output);
+
+    let script = "#HEADER_START\n/* h */\n#HEADER_END\n#REPEAT(x)\n#END_REPEAT";
+    let error = process_lines_with(lines(script), &options, Ok).unwrap_err();
+    assert!(matches!(
+        error,
+        PreprocessError::BadRepeatCount { line: 4, .. }
+    ));
+    Ok(())
+}
+
+/// Tests that a pipeline builder method naming a missing pass returns an
+/// error, and that each line keeps the number of its source line through the
+/// standard passes.
+#[test]
+fn pipeline_errors_and_numbers() -> Result<(), PreprocessError> {
+    let error = Pipeline::new().remove("expand").err().unwrap();
+    assert_eq!("No pass named `expand`.", error.to_string());
+    let error = process_lines_with(vec![], &Options::default(), |pipeline| {
+        pipeline.move_after("macros", "missing")
+    })
+    .unwrap_err();
+    assert!(matches!(error, PreprocessError::UnknownPass { name } if name == "missing"));
+
+    let options = Options::default();
+    let numbers: Pass = Box::new(|lines| {
+        let numbers: Vec<String> = lines.iter().map(|line| line.number.to_string()).collect();
+        let text = numbers.join(" ");
+        Ok(vec![Line { number: 0, text }])
+    });
+    let script = "#HEADER_START\n#HEADER_END\n/* a */\n#REPEAT(2)\nb\n#END_REPEAT\nc";
+    let output = process_lines_with(lines(script), &options, |pipeline| {
+        pipeline.insert_after("label_scopes", "numbers", numbers)
+    })?;
+    assert_eq!(vec!["5 5 7"], output);
+
+    let length = Cell::new(0);
+    let record: Lint = Box::new(|output| {
+        length.set(output.len());
+        Ok(())
+    });
+    let script = "#HEADER_START\n/* h */\n#HEADER_END\na";
+    process_lines_with(lines(script), &options, |pipeline| {
+        Ok(pipeline.push_lint("record", record))
+    })?;
+    assert_eq!(2, length.get());
+
+    let scripts = [
+        ("a", vec!["check_braces"]),
+        ("#CHECK_LABELS\na", vec!["check_labels", "check_braces"]),
+    ];
+    for (script, lints) in scripts {
+        let output = process_lines_with(lines(script), &options, |pipeline| {
+            assert_eq!(lints, pipeline.lint_names());
+            Ok(pipeline)
+        })?;
+        assert_eq!(vec!["a"], output);
+    }
+    Ok(())
+}
+
+/// Tests that `#COMPACT` joins each block onto a single line, with the same
+/// words in the same order as the expanded output, and leaves an unclosed
+/// block for the brace check to report.
+#[test]
+fn compact_blocks() -> Result<(), PreprocessError> {
+    let script = "<LAND_GENERATION>
+create_land {
+terrain_type GRASS
+land_percent 20
+}
+create_land { terrain_type WATER }
+<OBJECTS_GENERATION>
+#CENTER_OBJECT(RELIC,1)";
+    let expanded = process_lines(lines(script), &Options::default())?;
+    let compact = process_lines(lines(&format!("#COMPACT\n{script}")), &Options::default())?;
+    let expected = vec![
+        "<LAND_GENERATION>",
+        "create_land { terrain_type GRASS land_percent 20 }",
+        "create_land { terrain_type WATER }",
+        "<OBJECTS_GENERATION>",
+        "create_actor_area 50 50 20000 0",
+        "create_object RELIC { set_gaia_object_only number_of_objects 1 actor_area_to_place_in 20000 }",
+    ];
+    assert_eq!(expected, compact);
+    let words = |lines: &[String]| {
+        lines
+            .join(" ")
+            .split_whitespace()
+            .map(String::from)
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(words(&expanded), words(&compact));
+    let error = process_lines(
+        lines("#COMPACT\ncreate_land {\nterrain_type GRASS"),
+        &Options::default(),
+    )
+    .unwrap_err();
+    assert!(matches!(
+        error,
+        PreprocessError::UnclosedBlock { line: 1, .. }
+    ));
+    Ok(())
+}
+
+/// Tests that an unclosed `create_object` is reported by the brace check
+/// with the line of the output that opens it.
+#[test]
+fn unclosed_object() {
+    let script = "<OBJECTS_GENERATION>
+create_object RELIC {
+#SET_PLACE_FOR_EVERY_PLAYER
+number_of_objects 1";
+    let error = process_lines(lines(script), &Options::default()).unwrap_err();
+    assert!(matches!(
+        error,
+        PreprocessError::UnclosedBlock { line: 2, ref text } if text == "create_object RELIC {"
+    ));
+}
+
+/// Tests that `#LOWERCASE_KEYWORDS` lowercases the instructions but not the
+/// constants, labels, actor area names, or comments, even if they match a
+/// keyword.
+#[test]
+fn lowercase_keywords() -> Result<(), PreprocessError> {
+    let script = "#LOWERCASE_KEYWORDS
+#SECTION IF
+CREATE_LAND {
+Terrain_Type GRASS
+}
+#END_SECTION
+IF Zone
+CREATE_ACTOR_AREA 1 2 Zone 3
+ENDIF";
+    assert_eq!(
+        vec![
+            "/* SECTION IF */",
+            "create_land {",
+            "terrain_type GRASS",
+            "}",
+            "/* END SECTION IF */",
+            "if Zone",
+            "create_actor_area 1 2 Zone 3",
+            "endif",
+        ],
+        process_lines(lines(script), &Options::default())?
+    );
+    Ok(())
+}
+
+/// Tests that a `#REPEAT` count may be a const defined after the repeat block.
+#[test]
+fn repeat_const_defined_later() -> Result<(), PreprocessError> {
+    let script = "#REPEAT(NUM)\na\n#END_REPEAT\n#const NUM 3";
+    assert_eq!(
+        vec!["a", "a", "a", "#const NUM 3"],
+        process_lines(lines(script), &Options::default())?
+    );
+    let result = process_lines(lines("#REPEAT(OTHER)\n#END_REPEAT"), &Options::default());
+    assert!(matches!(
+        result,
+        Err(PreprocessError::BadRepeatCount { line: 1, .. })
+    ));
+    Ok(())
+}